@@ -2,12 +2,21 @@ extern crate ulc11;
 
 use ulc11::ExecPath;
 use ulc11::Embed;
+use ulc11::NO_PAYLOAD;
 use ulc11::auto::AutoEmbed;
 
+use std::error::Error;
+
 fn main() {
     let mut p = AutoEmbed::new(ExecPath::This).unwrap();
+    // other instances wait until this one has stored its count
+    let _lock = p.lock(true).unwrap();
     
-    let mut d = p.load().unwrap();
+    let mut d = match p.load() {
+        Ok(d) => d,
+        Err(ref e) if e.description() == NO_PAYLOAD => vec![],
+        Err(e) => panic!("{}", e)
+    };
     
     // if fresh run
     if d.len() == 0 {
@@ -25,5 +34,3 @@ fn main() {
         
     p.store(&d).unwrap();
 }
-
-
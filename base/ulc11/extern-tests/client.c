@@ -7,15 +7,7 @@
 
 #include <stdio.h>
 
-typedef void ExecBlob;
-
-ExecBlob*   ulc11_new(const char *path);
-void        ulc11_free(ExecBlob *e);
-
-uint8_t*    ulc11_data_load(ExecBlob *e, uint64_t *length);
-uint8_t*    ulc11_data_free(uint8_t *data);
-bool        ulc11_data_store(ExecBlob *e, uint8_t *data, uint64_t length);
-bool        ulc11_data_strip(ExecBlob *e);
+#include "ulc11.h"
 
 int main(int argc, char **argv){
     ExecBlob *e;
@@ -126,7 +126,7 @@ SO=$(ls ../target/libulc11*.so) || die "No shared object found in ../target"
 RLIB=$(ls ../target/libulc11*.rlib) || die "No rust library found in ../target"
 
 rustc -g -o "rust-client" -L ../target -L ../target/deps "client.rs" || die "failed to compile rust code"
-gcc -std=c99 -pedantic -g -o "c-client" "client.c" "../target/$SO" || die "failed to compile c code"
+gcc -std=c99 -pedantic -g -I ../include -o "c-client" "client.c" "../target/$SO" || die "failed to compile c code"
 
 leak_check() {
     valgrind --error-exitcode=1 --tool=memcheck "--errors-for-leak-kinds=all" "$1" || die "'$1' memory check failed"
@@ -0,0 +1,77 @@
+/*
+ * ulc11.h: the C interface of ulc11, embeding data in compiled executables.
+ *
+ * Link against the ulc11 shared object (libulc11-*.so).
+ *
+ * Functions report success through their return value: true, or a non NULL
+ * pointer. On failure, an error code and message are recorded for the
+ * calling thread, and can be retrieved with ulc11_last_error_code() and
+ * ulc11_last_error() until the next failure.
+ */
+
+#ifndef ULC11_H
+#define ULC11_H
+
+#include <stdint.h>
+#include <stdbool.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* error codes */
+#define ULC11_OK                    0
+#define ULC11_ERR_NOT_FOUND         1   /* the executable doesn't exist */
+#define ULC11_ERR_PERMISSION        2   /* the executable can't be read or replaced */
+#define ULC11_ERR_NO_PAYLOAD        3   /* nothing, or no such resource, is embeded */
+#define ULC11_ERR_TRUNCATED         4   /* the embeded data is cut short */
+#define ULC11_ERR_CHECKSUM          5   /* the embeded data is corrupted */
+#define ULC11_ERR_UNSUPPORTED       6   /* the embeded data is in a newer format */
+#define ULC11_ERR_AUTHENTICATION    7   /* the embeded data is encrypted with a key not given */
+#define ULC11_ERR_INVALID           8   /* bad arguments, or a blob that isn't an archive */
+#define ULC11_ERR_IO                9   /* any other error */
+#define ULC11_ERR_IDENTITY          10  /* the embeded data was bound to another executable */
+
+typedef void ExecBlob;
+
+/* The code of the last error on this thread, ULC11_OK if none occured. */
+int         ulc11_last_error_code(void);
+/* The message of the last error on this thread, NULL if none occured. It
+ * is owned by ulc11, and valid until the next error. */
+const char* ulc11_last_error(void);
+
+/* Opens the executable at `path`, or the running one if `path` is NULL.
+ * The functions below fail with ULC11_ERR_INVALID if given a NULL handle. */
+ExecBlob*   ulc11_new(const char *path);
+void        ulc11_free(ExecBlob *e);
+
+/* The embeded blob. An executable without one has an empty blob. The
+ * returned buffer is freed with ulc11_data_free(). */
+uint8_t*    ulc11_data_load(ExecBlob *e, uint64_t *length);
+void        ulc11_data_free(uint8_t *data);
+/* Replaces the embeded blob with `length` bytes at `data`, which may be
+ * NULL if `length` is 0. */
+bool        ulc11_data_store(ExecBlob *e, const uint8_t *data, uint64_t length);
+/* Removes the embeded blob, if any. */
+bool        ulc11_data_strip(ExecBlob *e);
+/* Checks the integrity of the embeded blob without loading it. */
+bool        ulc11_data_verify(ExecBlob *e);
+
+/* Named resources, stored as an archive in the embeded blob. Buffers are
+ * freed with ulc11_data_free(). */
+
+/* The names of the resources, sorted, each followed by a NUL. */
+uint8_t*    ulc11_resource_names(ExecBlob *e, uint64_t *length);
+/* Resource `name`; fails with ULC11_ERR_NO_PAYLOAD if there's none. */
+uint8_t*    ulc11_resource_get(ExecBlob *e, const char *name, uint64_t *length);
+/* Embeds `length` bytes at `data` as resource `name`, replacing it. `data`
+ * may be NULL if `length` is 0. */
+bool        ulc11_resource_insert(ExecBlob *e, const char *name, const uint8_t *data, uint64_t length);
+/* Removes resource `name`; fails with ULC11_ERR_NO_PAYLOAD if there's none. */
+bool        ulc11_resource_remove(ExecBlob *e, const char *name);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ULC11_H */
@@ -1,29 +1,567 @@
 use ::ExecPath;
 use ::Embed;
+use ::NO_PAYLOAD;
+use ::region::Region;
+use ::region::Mapping;
+use ::trailer::Trailer;
+use ::trailer::FLAG_ARCHIVE;
+use ::trailer::FLAG_COMPRESSED;
+use ::trailer::FLAG_ENCRYPTED;
+use ::codec;
+use ::lock;
+use ::target::Target;
+use ::rewrite;
 use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Cursor;
+use std::error::Error;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::os::unix::fs::PermissionsExt;
+use ulc91::archive::Archive;
+use ulc91::archive::file::ArchiveHandle;
 
-/// The module for a compile-time data embedder: AutoEmbed
+/// The module for a format-detecting data embedder: AutoEmbed
 
 pub struct AutoEmbed {
-    be: Box<Embed>
+    be: Box<Embed>,
+    target: Target,
+    options: codec::Options,    // Also given to copies (see `clone_with`)
+    in_place: bool              // Whether the blob can be read in place (see `region`)
 }
 
-/// `AutoEmbed` is a wrapper embedder that uses an apropreate Embeder at
-/// compile time. (Defaults to `GenericEmbed`)
+// converts ulc91's stacked errors
+fn archive_error(e: Vec<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "Embeded blob is not a valid archive", Some(e.connect(": ")))
+}
+
+// checks that the resources of `h` lie within its `size` byte blob, so that
+// the lengths in its lookup table can be trusted
+fn check_extents<T: Read + Write + Seek>(h: &ArchiveHandle<T>, size: u64) -> io::Result<()> {
+    for name in h.names().iter() {
+        match h.position(name).unwrap().checked_add(h.len(name).unwrap()) {
+            Some(end) if end <= size => {},
+            _ => return Err(archive_error(vec![format!("Resource '{}' ends past the blob", name)]))
+        }
+    }
+    Ok(())
+}
+
+/// `AutoEmbed` is a wrapper embedder that picks an apropreate Embeder for
+/// the executable: `ElfEmbed` for ELF files, `ScriptEmbed` for scripts
+/// (files starting with `#!`), `GenericEmbed` otherwise.
+///
+/// Besides the raw blob operations of `Embed`, `AutoEmbed` can treat the
+/// blob as a `ulc91::archive` of named resources (`list`, `get`, `insert`,
+/// `remove` and `resources`). An executable without a blob is treated as an
+/// empty archive. Don't mix the two on the same executable.
 impl AutoEmbed {
     pub fn new(executable: ExecPath) -> io::Result<AutoEmbed> {
-        match ::generic::GenericEmbed::new(executable) {
-            Ok(e) => Ok(AutoEmbed{be:Box::new(e) as Box<Embed>}),
+        let target = try!(executable.target());
+        let head = try!(target.head(4));
+
+        if ::elf::has_magic(&head) {
+            let e = try!(::elf::ElfEmbed::new(target.exec_path()));
+            Ok(AutoEmbed{be:Box::new(e) as Box<Embed>, target: target, options: codec::Options::new(), in_place: true})
+        }
+        else if ::script::has_magic(&head) {
+            let e = try!(::script::ScriptEmbed::new(target.exec_path()));
+            Ok(AutoEmbed{be:Box::new(e) as Box<Embed>, target: target, options: codec::Options::new(), in_place: false})
+        }
+        else {
+            let e = try!(::generic::GenericEmbed::new(target.exec_path()));
+            Ok(AutoEmbed{be:Box::new(e) as Box<Embed>, target: target, options: codec::Options::new(), in_place: true})
+        }
+    }
+
+    /// Locks the executable against other writers until the returned lock
+    /// is dropped, so that a `load` and the `store` that follows it aren't
+    /// interleaved with another writer's. If the executable is already
+    /// locked, waits for it if `wait` is set, and fails with `BUSY`
+    /// otherwise. Stores made while holding the lock don't wait.
+    ///
+    /// Only executable files are locked; `None` is returned for executables
+    /// given as a file descriptor or an image.
+    pub fn lock(&self, wait: bool) -> io::Result<Option<lock::Lock>> {
+        self.target.lock(wait)
+    }
+
+    /// Writes a copy of the executable to `dest`, changed by `edit`, without
+    /// modifying the executable itself. `edit` gets an `AutoEmbed` for the
+    /// copy, with the same options. The copy gets the owner, mode and xattrs
+    /// of the executable (mode 0755 if it isn't a file), and replaces `dest`
+    /// as a whole once `edit` succeeds.
+    pub fn clone_with<F>(&self, dest: &Path, edit: F) -> io::Result<()>
+        where F: FnOnce(&mut AutoEmbed) -> io::Result<()>
+    {
+        let image = Rc::new(RefCell::new(Cursor::new(try!(self.target.read_all()))));
+        {
+            let mut copy = try!(AutoEmbed::new(ExecPath::Image(image.clone())));
+            copy.set_options(self.options.clone());
+            try!(edit(&mut copy));
+        }
+
+        let mut staged = try!(rewrite::Staged::new(dest));
+        try!(staged.file().write_all(image.borrow().get_ref()));
+        match self.target {
+            Target::File(ref p) => staged.commit_like(Some(p)),
+            Target::Image(_) => {
+                // before the rename: `dest` is never seen with another mode
+                try!(staged.file().set_permissions(fs::Permissions::from_mode(0o755)));
+                staged.commit_like(None)
+            }
+        }
+    }
+
+    /// Writes a copy of the executable with `data` embeded to `dest` (see
+    /// `clone_with`).
+    pub fn store_to(&self, dest: &Path, data: &[u8]) -> io::Result<()> {
+        self.clone_with(dest, |copy| copy.store(data))
+    }
+
+    /// Extracts the embeded directory tree (see `vfs::pack_dir`) into
+    /// `dir`, as `vfs::Vfs::extract_to` does.
+    pub fn extract_to(&self, dir: &Path, overwrite: ::vfs::Overwrite) -> io::Result<()> {
+        try!(::vfs::Vfs::with_embed(self)).extract_to(dir, overwrite)
+    }
+
+    // the whole archive, in memory
+    fn archive(&self) -> io::Result<Archive> {
+        match self.load() {
+            Ok(blob) => Archive::new(Some(&blob)).map_err(archive_error),
+            Err(ref e) if e.description() == NO_PAYLOAD => Archive::new(None).map_err(archive_error),
             Err(e) => Err(e)
         }
     }
+
+    /// Names of the embeded resources, sorted.
+    pub fn list(&self) -> io::Result<Vec<String>> {
+        Ok(try!(self.resources()).names())
+    }
+
+    /// Reads the embeded resource `name`, if present.
+    pub fn get(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        let mut r = try!(self.resources());
+        match r.open(name) {
+            Some(mut res) => {
+                let mut b: Vec<u8> = vec![];
+                try!(res.read_to_end(&mut b));
+                Ok(Some(b))
+            },
+            None => Ok(None)
+        }
+    }
+
+    /// Embeds `data` as resource `name`, replacing it if present.
+    pub fn insert(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let _lock = try!(self.lock(true));
+        let mut ar = try!(self.archive());
+        ar.insert(name.to_string(), data.to_vec());
+        self.store_with(&ar.to_bytes(), FLAG_ARCHIVE)
+    }
+
+    /// Removes resource `name`. Returns false if it wasn't embeded.
+    pub fn remove(&mut self, name: &str) -> io::Result<bool> {
+        let _lock = try!(self.lock(true));
+        let mut ar = try!(self.archive());
+        if ar.remove(name).is_none() {
+            return Ok(false);
+        }
+        try!(self.store_with(&ar.to_bytes(), FLAG_ARCHIVE));
+        Ok(true)
+    }
+
+    /// Opens the embeded resources for lazy reading: only the archive's
+    /// lookup table is loaded. Compressed or encrypted archives, and those
+    /// embeded in scripts, are loaded as a whole.
+    pub fn resources(&self) -> io::Result<Resources> {
+        if !self.in_place {
+            let source = match self.load() {
+                Ok(ref blob) if blob.len() == 0 => return Ok(Resources {handle: None}),
+                Ok(blob) => Source::Memory(Cursor::new(blob)),
+                Err(ref e) if e.description() == NO_PAYLOAD => return Ok(Resources {handle: None}),
+                Err(e) => return Err(e)
+            };
+            let size = source.len();
+            let h = try!(ArchiveHandle::new(source).map_err(archive_error));
+            try!(check_extents(&h, size));
+            return Ok(Resources {handle: Some(h)});
+        }
+        let source = match try!(self.region()) {
+            Some((ref r, _)) if r.len() == 0 => return Ok(Resources {handle: None}),
+            Some((r, t)) => {
+                if t.flags & (FLAG_COMPRESSED | FLAG_ENCRYPTED) != 0 {
+                    Source::Memory(Cursor::new(try!(self.load())))
+                }
+                else {
+                    Source::Region(r)
+                }
+            }
+            None => return Ok(Resources {handle: None})
+        };
+        let size = source.len();
+        let h = try!(ArchiveHandle::new(source).map_err(archive_error));
+        try!(check_extents(&h, size));
+        Ok(Resources {handle: Some(h)})
+    }
+
+    /// Maps the embeded resources into memory, without copying them (see
+    /// `Embed::map`).
+    pub fn map_resources(&self) -> io::Result<MappedResources> {
+        // the flags are checked, the blob mapped and its index read through
+        // the one region, so all come from the same executable even if it is
+        // replaced meanwhile
+        let (region, t) = match try!(self.region()) {
+            Some(found) => found,
+            None => return Ok(MappedResources {mapping: None, records: HashMap::new()})
+        };
+        if t.flags & (FLAG_COMPRESSED | FLAG_ENCRYPTED) != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Compressed or encrypted data can't be mapped", None));
+        }
+        let mapping = try!(region.map());
+        let mut records: HashMap<String, (usize, usize)> = HashMap::new();
+        if mapping.len() > 0 {
+            let h = try!(ArchiveHandle::new(region).map_err(archive_error));
+            try!(check_extents(&h, mapping.len() as u64));
+            for name in h.names().into_iter() {
+                let start = h.position(&name).unwrap() as usize;
+                let end = start + h.len(&name).unwrap() as usize;
+                records.insert(name, (start, end));
+            }
+        }
+        Ok(MappedResources {mapping: Some(mapping), records: records})
+    }
 }
 
 impl Embed for AutoEmbed {
     fn load(&self) -> io::Result<Vec<u8>> {self.be.load()}
+    fn verify(&self) -> io::Result<()> {self.be.verify()}
+    fn info(&self) -> io::Result<Trailer> {self.be.info()}
     fn strip(&mut self) -> io::Result<()> {self.be.strip()}
-    fn store(&mut self, data: &[u8]) -> io::Result<()> {self.be.store(data)}
+    fn store_with(&mut self, data: &[u8], flags: u16) -> io::Result<()> {self.be.store_with(data, flags)}
+    fn set_options(&mut self, options: codec::Options) {
+        self.options = options.clone();
+        self.be.set_options(options)
+    }
+    fn region(&self) -> io::Result<Option<(Region, Trailer)>> {self.be.region()}
+}
+
+
+// where resources are read from: the executable itself, or a copy of the
+// blob decoded in memory
+enum Source {
+    Region(Region),
+    Memory(Cursor<Vec<u8>>)
+}
+impl Source {
+    fn len(&self) -> u64 {
+        match *self {
+            Source::Region(ref r) => r.len(),
+            Source::Memory(ref c) => c.get_ref().len() as u64
+        }
+    }
+}
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Source::Region(ref mut r) => r.read(buf),
+            Source::Memory(ref mut c) => c.read(buf)
+        }
+    }
+}
+impl Seek for Source {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match *self {
+            Source::Region(ref mut r) => r.seek(pos),
+            Source::Memory(ref mut c) => c.seek(pos)
+        }
+    }
+}
+impl Write for Source {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Source::Region(ref mut r) => r.write(buf),
+            Source::Memory(_) => Err(io::Error::new(io::ErrorKind::PermissionDenied, "Embeded blobs are read-only in place", None))
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Lazy access to the named resources embeded in an executable.
+pub struct Resources {
+    handle: Option<ArchiveHandle<Source>>  // None if nothing is embeded
+}
+impl Resources {
+    /// Names of the resources, sorted.
+    pub fn names(&self) -> Vec<String> {
+        match self.handle {
+            Some(ref h) => h.names(),
+            None => vec![]
+        }
+    }
+
+    /// Length of resource `name`, if present.
+    pub fn len(&self, name: &str) -> Option<u64> {
+        match self.handle {
+            Some(ref h) => h.len(name),
+            None => None
+        }
+    }
+
+    /// Reads resource `name` from `offset` into `buf`. Returns the number of
+    /// bytes read: 0 at its end, or if it isn't present.
+    pub fn read_at(&mut self, name: &str, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        match self.handle {
+            Some(ref mut h) => h.read(name, buf, offset),
+            None => Ok(0)
+        }
+    }
+
+    /// Opens resource `name` for reading, if present.
+    pub fn open<'a>(&'a mut self, name: &str) -> Option<Resource<'a>> {
+        if self.len(name).is_none() {
+            return None;
+        }
+        match self.handle {
+            Some(ref mut h) => Some(Resource {handle: h, name: name.to_string(), pos: 0}),
+            None => None
+        }
+    }
+}
+
+/// The named resources embeded in an executable, mapped into memory.
+pub struct MappedResources {
+    mapping: Option<Mapping>,                   // None if nothing is embeded
+    records: HashMap<String, (usize, usize)>    // Where each resource is in the mapping
+}
+impl MappedResources {
+    /// Names of the resources, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.records.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Resource `name`, if present.
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        match (&self.mapping, self.records.get(name)) {
+            (&Some(ref m), Some(&(start, end))) => Some(&m[start..end]),
+            _ => None
+        }
+    }
+}
+
+/// A reader of a single embeded resource.
+pub struct Resource<'a> {
+    handle: &'a mut ArchiveHandle<Source>,
+    name: String,
+    pos: u64
+}
+impl<'a> Read for Resource<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = try!(self.handle.read(&self.name, buf, self.pos));
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+
+#[test]
+fn test_resources() {
+    use std::fs;
+    use std::path::PathBuf;
+
+    let path = "/tmp/test_auto_resources";
+    let _ = fs::remove_file(path);
+    fs::copy("/proc/self/exe", path).unwrap();
+
+    let mut p = AutoEmbed::new(ExecPath::File(PathBuf::from(path))).unwrap();
+    assert!(p.list().unwrap().len() == 0);
+    assert!(p.get("icon.png").unwrap().is_none());
+
+    p.insert("icon.png", &[0x89, 0x50, 0x4e, 0x47]).unwrap();
+    p.insert("config", b"verbose = yes").unwrap();
+    p.insert("empty", &[]).unwrap();
+    assert!(p.info().unwrap().has(FLAG_ARCHIVE));
+    assert!(p.list().unwrap() == vec!["config".to_string(), "empty".to_string(), "icon.png".to_string()]);
+    assert!(p.get("config").unwrap().unwrap() == b"verbose = yes");
+    assert!(p.get("empty").unwrap().unwrap().len() == 0);
+
+    // replacing
+    p.insert("config", b"verbose = no").unwrap();
+    assert!(p.get("config").unwrap().unwrap() == b"verbose = no");
+
+    // lazy reader
+    {
+        let mut r = p.resources().unwrap();
+        assert!(r.len("icon.png") == Some(4));
+        let mut b: Vec<u8> = vec![];
+        r.open("icon.png").unwrap().read_to_end(&mut b).unwrap();
+        assert!(b == [0x89, 0x50, 0x4e, 0x47]);
+        assert!(r.open("missing").is_none());
+    }
+
+    assert!(p.remove("icon.png").unwrap());
+    assert!(!p.remove("icon.png").unwrap());
+    assert!(p.list().unwrap() == vec!["config".to_string(), "empty".to_string()]);
+
+    // mapped
+    {
+        let m = p.map_resources().unwrap();
+        assert!(m.names() == vec!["config".to_string(), "empty".to_string()]);
+        assert!(m.get("config").unwrap() == b"verbose = no");
+        assert!(m.get("empty").unwrap().len() == 0);
+        assert!(m.get("icon.png").is_none());
+        assert!(p.map().unwrap().unwrap().len() as u64 == p.info().unwrap().length);
+
+        // the executable is replaced, not modified: the mapping keeps
+        // showing the resources as they were
+        p.insert("config", b"verbose = maybe").unwrap();
+        assert!(m.get("config").unwrap() == b"verbose = no");
+        assert!(p.map_resources().unwrap().get("config").unwrap() == b"verbose = maybe");
+        p.insert("config", b"verbose = no").unwrap();
+    }
+
+    // compressed archives
+    p.set_options(codec::Options::new().compress(codec::DEFAULT_LEVEL));
+    let text: Vec<u8> = (0..5000).map(|i| b"abcdefgh"[i % 8]).collect();
+    p.insert("text", &text).unwrap();
+    assert!(p.info().unwrap().has(FLAG_COMPRESSED));
+    assert!(p.get("text").unwrap().unwrap() == text);
+    assert!(p.get("config").unwrap().unwrap() == b"verbose = no");
+    assert!(p.resources().unwrap().len("text") == Some(5000));
+    assert!(p.map_resources().is_err());
+
+    // encrypted archives
+    p.set_options(codec::Options::new().encrypt(codec::Key::Raw([1u8; codec::KEY_LEN])));
+    p.insert("token", b"s3cr3t").unwrap();
+    assert!(p.get("token").unwrap().unwrap() == b"s3cr3t");
+    assert!(p.get("text").unwrap().unwrap() == text);
+    p.set_options(codec::Options::new());
+    assert!(p.list().is_err());
+
+    // lookup tables claiming more than the blob holds
+    let mut ar = Archive::new(None).unwrap();
+    ar.insert("big".to_string(), vec![7u8; 4]);
+    let mut blob = ar.to_bytes();
+    let at = blob.windows(11).position(|w| w == b"\x04\0\0\0\0\0\0\0big").unwrap();
+    for b in blob[at..at + 8].iter_mut() {
+        *b = 0xff;
+    }
+    p.store_with(&blob, FLAG_ARCHIVE).unwrap();
+    assert!(p.resources().err().unwrap().description() == "Embeded blob is not a valid archive");
+    assert!(p.map_resources().is_err());
+    assert!(::vfs::Vfs::with_embed(&p).is_err());
+    fs::remove_file(path).unwrap();
 }
 
+#[test]
+fn test_script_resources() {
+    use std::fs;
+    use std::path::PathBuf;
+
+    let path = "/tmp/test_auto_script";
+    let _ = fs::remove_file(path);
+    fs::File::create(path).unwrap().write_all(b"#!/usr/bin/env python\nprint('hi')\n").unwrap();
 
+    let mut p = AutoEmbed::new(ExecPath::File(PathBuf::from(path))).unwrap();
+    assert!(p.list().unwrap().len() == 0);
+    p.insert("setup.tar", b"tarball").unwrap();
+    p.insert("README", b"read me").unwrap();
+    assert!(p.list().unwrap() == vec!["README".to_string(), "setup.tar".to_string()]);
+    assert!(p.get("setup.tar").unwrap().unwrap() == b"tarball");
+    assert!(p.map_resources().is_err());
+    p.strip().unwrap();
+    assert!(p.list().unwrap().len() == 0);
+    fs::remove_file(path).unwrap();
+}
 
+#[test]
+fn test_store_to() {
+    use std::path::PathBuf;
+
+    let src = "/tmp/test_auto_store_to";
+    let dest = "/tmp/test_auto_store_to.tenant";
+    let _ = fs::remove_file(src);
+    let _ = fs::remove_file(dest);
+    fs::copy("/proc/self/exe", src).unwrap();
+    fs::set_permissions(src, fs::Permissions::from_mode(0o750)).unwrap();
+    let original = fs::metadata(src).unwrap().len();
+
+    let mut p = AutoEmbed::new(ExecPath::File(PathBuf::from(src))).unwrap();
+    p.set_options(codec::Options::new().compress(codec::DEFAULT_LEVEL));
+    p.clone_with(Path::new(dest), |c| {
+        try!(c.insert("tenant", b"acme"));
+        c.insert("plan", b"gold")
+    }).unwrap();
+    assert!(AutoEmbed::new(ExecPath::File(PathBuf::from(dest))).unwrap().list().unwrap().len() == 2);
+    p.store_to(Path::new(dest), &[5u8; 5000]).unwrap();
+
+    // the source is untouched
+    assert!(fs::metadata(src).unwrap().len() == original);
+    assert!(p.info().is_err());
+
+    let q = AutoEmbed::new(ExecPath::File(PathBuf::from(dest))).unwrap();
+    assert!(q.load().unwrap() == vec![5u8; 5000]);
+    assert!(q.info().unwrap().has(FLAG_COMPRESSED));
+    assert!(fs::metadata(dest).unwrap().permissions().mode() & 0o7777 == 0o750);
+
+    // failed edits leave the destination alone
+    assert!(p.clone_with(Path::new(dest), |_| Err(io::Error::new(io::ErrorKind::Other, "cancelled", None))).is_err());
+    assert!(q.load().unwrap() == vec![5u8; 5000]);
+
+    // images are copied out with an executable mode
+    let image = Rc::new(RefCell::new(Cursor::new(b"data".to_vec())));
+    let m = AutoEmbed::new(ExecPath::Image(image.clone())).unwrap();
+    m.clone_with(Path::new(dest), |c| c.insert("k", b"v")).unwrap();
+    assert!(image.borrow().get_ref() == b"data");
+    let q = AutoEmbed::new(ExecPath::File(PathBuf::from(dest))).unwrap();
+    assert!(q.get("k").unwrap().unwrap() == b"v");
+    assert!(fs::metadata(dest).unwrap().permissions().mode() & 0o777 == 0o755);
+
+    fs::remove_file(src).unwrap();
+    fs::remove_file(dest).unwrap();
+}
+
+#[test]
+fn test_appended_elf() {
+    use std::path::PathBuf;
+    use ::generic::GenericEmbed;
+    use ::trailer::read_trailer;
+
+    // an ELF executable with a blob appended, as written before sections
+    // were used
+    let path = "/tmp/test_auto_appended";
+    let _ = fs::remove_file(path);
+    fs::copy("/proc/self/exe", path).unwrap();
+    GenericEmbed::new(ExecPath::File(PathBuf::from(path))).unwrap().store(b"old format").unwrap();
+
+    let mut p = AutoEmbed::new(ExecPath::File(PathBuf::from(path))).unwrap();
+    assert!(p.load().unwrap() == b"old format");
+    assert!(p.verify().is_ok());
+    assert!(p.info().unwrap().length == 10);
+    assert!(&p.map().unwrap().unwrap()[..] == b"old format");
+
+    // storing moves the blob into the section
+    p.store(b"new format").unwrap();
+    assert!(p.load().unwrap() == b"new format");
+    assert!(read_trailer(&mut fs::File::open(path).unwrap()).unwrap().is_none());
+
+    // and stripping removes it either way
+    p.strip().unwrap();
+    assert!(p.load().unwrap_err().description() == NO_PAYLOAD);
+    let stripped = fs::metadata(path).unwrap().len();
+    GenericEmbed::new(ExecPath::File(PathBuf::from(path))).unwrap().store(b"old format").unwrap();
+    p.strip().unwrap();
+    assert!(p.load().unwrap_err().description() == NO_PAYLOAD);
+    assert!(fs::metadata(path).unwrap().len() == stripped);
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,426 @@
+//! ulc11-embed: packs data into executables, and back out of them.
+
+#![feature(env)]
+#![feature(io)]
+#![feature(fs)]
+#![feature(path)]
+#![feature(exit_status)]
+
+extern crate ulc11;
+
+use ulc11::ExecPath;
+use ulc11::Embed;
+use ulc11::NO_PAYLOAD;
+use ulc11::TRUNCATED;
+use ulc11::CHECKSUM_MISMATCH;
+use ulc11::UNSUPPORTED_FORMAT;
+use ulc11::AUTHENTICATION_FAILED;
+use ulc11::BUSY;
+use ulc11::IDENTITY_MISMATCH;
+use ulc11::auto::AutoEmbed;
+use ulc11::codec;
+use ulc11::trailer;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::process;
+use std::error::Error;
+use std::path::PathBuf;
+
+const USAGE: &'static str = "\
+usage: ulc11-embed COMMAND [OPTIONS] EXECUTABLE [FILE]
+
+commands:
+    pack        embeds FILE (or stdin) into EXECUTABLE
+    unpack      writes the data embeded in EXECUTABLE to FILE (or stdout)
+    list        lists the resources embeded in EXECUTABLE
+    strip       removes the data embeded in EXECUTABLE
+    verify      checks the integrity of the data embeded in EXECUTABLE
+    info        describes the data embeded in EXECUTABLE
+
+options:
+    --name=NAME             pack or unpack the resource NAME, rather than
+                            the whole blob
+    --compress[=LEVEL]      compress packed data (LEVEL 1 to 9, default 6)
+    --key-file=PATH         encrypt packed data, or decrypt unpacked data,
+                            with the 32 byte key in PATH
+    --passphrase-file=PATH  likewise, with the passphrase in PATH
+    --bind                  bind packed data to EXECUTABLE; it won't load
+                            out of any other executable
+    --no-wait               fail, rather than wait, if another process is
+                            modifying EXECUTABLE
+    --output=PATH           pack into a copy of EXECUTABLE written to PATH,
+                            leaving EXECUTABLE untouched
+    --help                  prints this message
+
+A FILE of '-' stands for stdin or stdout.
+
+exit status:
+    0   success
+    1   bad usage
+    2   nothing (or no such resource) is embeded
+    3   the embeded data is truncated
+    4   the embeded data is corrupted
+    5   the embeded data is in an unsupported format
+    6   the embeded data failed authentication (wrong or missing key)
+    7   EXECUTABLE is being modified by another process (--no-wait)
+    8   the embeded data was bound to another executable
+    10  any other error
+";
+
+const EXIT_USAGE: i32 = 1;
+
+// exit status of the failure `e`
+fn exit_code(e: &io::Error) -> i32 {
+    let d = e.description();
+    if d == NO_PAYLOAD {2}
+    else if d == TRUNCATED {3}
+    else if d == CHECKSUM_MISMATCH {4}
+    else if d == UNSUPPORTED_FORMAT {5}
+    else if d == AUTHENTICATION_FAILED {6}
+    else if d == BUSY {7}
+    else if d == IDENTITY_MISMATCH {8}
+    else {10}
+}
+
+// the parsed command line
+struct Command {
+    command: String,
+    executable: PathBuf,
+    file: Option<String>,       // None or "-" for stdin/stdout
+    name: Option<String>,
+    options: codec::Options,
+    wait: bool,                 // whether to wait for other writers
+    output: Option<PathBuf>     // where to write a packed copy to
+}
+
+fn read_file(path: &str) -> io::Result<Vec<u8>> {
+    let mut b: Vec<u8> = vec![];
+    try!(try!(fs::File::open(path)).read_to_end(&mut b));
+    Ok(b)
+}
+
+fn parse(args: Vec<String>) -> Result<Command, String> {
+    let mut positional: Vec<String> = vec![];
+    let mut name: Option<String> = None;
+    let mut options = codec::Options::new();
+    let mut wait = true;
+    let mut output: Option<PathBuf> = None;
+
+    for arg in args.into_iter() {
+        if arg.len() < 2 || &arg[..2] != "--" {
+            positional.push(arg);
+            continue;
+        }
+        let (option, value) = match arg.find('=') {
+            Some(i) => (&arg[..i], Some(&arg[i + 1..])),
+            None => (&arg[..], None)
+        };
+        match (option, value) {
+            ("--help", _) => return Err(String::new()),
+            ("--name", Some(n)) => {name = Some(n.to_string());},
+            ("--no-wait", None) => {wait = false;},
+            ("--output", Some(p)) => {output = Some(PathBuf::from(p));},
+            ("--bind", None) => {options = options.bind();},
+            ("--compress", None) => {options = options.compress(codec::DEFAULT_LEVEL);},
+            ("--compress", Some(l)) => {
+                match l.parse::<u32>() {
+                    Ok(l) if l >= 1 && l <= codec::MAX_LEVEL => {options = options.compress(l);},
+                    _ => return Err(format!("invalid compression level '{}'", l))
+                }
+            },
+            ("--key-file", Some(p)) => {
+                let b = try!(read_file(p).map_err(|e| format!("{}: {}", p, e)));
+                if b.len() != codec::KEY_LEN {
+                    return Err(format!("{}: keys are {} bytes long, not {}", p, codec::KEY_LEN, b.len()));
+                }
+                let mut k = [0u8; codec::KEY_LEN];
+                for (d, s) in k.iter_mut().zip(b.iter()) {
+                    *d = *s;
+                }
+                options = options.encrypt(codec::Key::Raw(k));
+            },
+            ("--passphrase-file", Some(p)) => {
+                let b = try!(read_file(p).map_err(|e| format!("{}: {}", p, e)));
+                let s = try!(String::from_utf8(b).map_err(|_| format!("{}: passphrase is not valid UTF-8", p)));
+                options = options.encrypt(codec::Key::Passphrase(s.trim_right_matches('\n').to_string()));
+            },
+            (o, _) => return Err(format!("unknown option, or missing value: '{}'", o))
+        }
+    }
+
+    let (min, max) = match positional.first().map(|c| &c[..]) {
+        Some("pack") | Some("unpack") => (2, 3),
+        Some("list") | Some("strip") | Some("verify") | Some("info") => (2, 2),
+        Some(c) => return Err(format!("unknown command '{}'", c)),
+        None => return Err(format!("no command given"))
+    };
+    if positional.len() < min || positional.len() > max {
+        return Err(format!("wrong number of arguments for '{}'", positional[0]));
+    }
+    if output.is_some() && positional[0] != "pack" {
+        return Err(format!("--output only applies to 'pack'"));
+    }
+
+    let mut positional = positional.into_iter();
+    Ok(Command {
+        command: positional.next().unwrap(),
+        executable: PathBuf::from(positional.next().unwrap()),
+        file: positional.next(),
+        name: name,
+        options: options,
+        wait: wait,
+        output: output
+    })
+}
+
+fn flag_names(flags: u16) -> String {
+    let known = [(trailer::FLAG_COMPRESSED, "compressed"),
+                 (trailer::FLAG_ENCRYPTED, "encrypted"),
+                 (trailer::FLAG_ARCHIVE, "archive")];
+    let mut names: Vec<String> = vec![];
+    for &(f, n) in known.iter() {
+        if flags & f != 0 {
+            names.push(n.to_string());
+        }
+    }
+    let rest = known.iter().fold(flags, |r, &(f, _)| r & !f);
+    if rest != 0 {
+        names.push(format!("{:#06x}", rest));
+    }
+    if names.len() == 0 {"none".to_string()} else {names.connect(", ")}
+}
+
+fn run(c: Command) -> io::Result<()> {
+    let mut e = try!(AutoEmbed::new(ExecPath::File(c.executable)));
+    e.set_options(c.options);
+    let file = match c.file {
+        Some(ref f) if f != "-" => Some(f.clone()),
+        _ => None
+    };
+
+    // writers lock the executable for the whole command
+    let _lock = match (&c.command[..], &c.output) {
+        ("pack", &None) | ("strip", _) => try!(e.lock(c.wait)),
+        _ => None
+    };
+
+    match &c.command[..] {
+        "pack" => {
+            let data = match file {
+                Some(f) => try!(read_file(&f)),
+                None => {
+                    let mut b: Vec<u8> = vec![];
+                    try!(io::stdin().read_to_end(&mut b));
+                    b
+                }
+            };
+            match (c.name, c.output) {
+                (Some(n), Some(o)) => e.clone_with(&o, |copy| copy.insert(&n, &data)),
+                (None, Some(o)) => e.store_to(&o, &data),
+                (Some(n), None) => e.insert(&n, &data),
+                (None, None) => e.store(&data)
+            }
+        },
+        "unpack" => {
+            let data = match c.name {
+                Some(n) => match try!(e.get(&n)) {
+                    Some(d) => d,
+                    None => return Err(io::Error::new(io::ErrorKind::NotFound, NO_PAYLOAD,
+                                                        Some(format!("No resource named '{}'", n))))
+                },
+                None => try!(e.load())
+            };
+            match file {
+                Some(f) => try!(fs::File::create(&f)).write_all(&data),
+                None => io::stdout().write_all(&data)
+            }
+        },
+        "list" => {
+            let r = try!(e.resources());
+            for n in r.names().iter() {
+                println!("{}\t{}", r.len(n).unwrap_or(0), n);
+            }
+            Ok(())
+        },
+        "strip" => e.strip(),
+        "verify" => e.verify(),
+        "info" => {
+            let t = try!(e.info());
+            println!("version:    {}", t.version);
+            println!("flags:      {}", flag_names(t.flags));
+            println!("length:     {}", t.original_length());
+            println!("stored:     {}", t.length);
+            match t.checksum {
+                Some(crc) => println!("checksum:   {:08x}", crc),
+                None => println!("checksum:   none")
+            }
+            let tags: Vec<String> = t.extensions.iter().map(|&(tag, ref v)| format!("{} ({} bytes)", tag, v.len())).collect();
+            println!("extensions: {}", if tags.len() == 0 {"none".to_string()} else {tags.connect(", ")});
+            Ok(())
+        },
+        _ => unreachable!() // checked by parse
+    }
+}
+
+fn main() {
+    let c = match parse(env::args().skip(1).collect()) {
+        Ok(c) => c,
+        Err(ref msg) if msg.len() == 0 => {
+            print!("{}", USAGE);
+            return;
+        },
+        Err(msg) => {
+            let _ = io::stderr().write_all(format!("ulc11-embed: {}\n\n{}", msg, USAGE).as_bytes());
+            process::exit(EXIT_USAGE);
+        }
+    };
+    if let Err(e) = run(c) {
+        let _ = io::stderr().write_all(format!("ulc11-embed: {}\n", e).as_bytes());
+        process::exit(exit_code(&e));
+    }
+}
+
+
+#[test]
+fn test_parse() {
+    let args = |s: &str| s.split(' ').map(|a| a.to_string()).collect::<Vec<String>>();
+
+    let c = parse(args("pack --compress=9 --name=icon.png ./prog icon.png")).unwrap();
+    assert!(c.command == "pack" && c.executable == PathBuf::from("./prog"));
+    assert!(c.file == Some("icon.png".to_string()) && c.name == Some("icon.png".to_string()));
+    assert!(c.options.compression == Some(9));
+
+    let c = parse(args("unpack ./prog")).unwrap();
+    assert!(c.file.is_none() && c.name.is_none() && c.options.compression.is_none() && c.wait);
+    assert!(!parse(args("strip --no-wait ./prog")).unwrap().wait);
+    assert!(parse(args("pack --output=./copy ./prog")).unwrap().output == Some(PathBuf::from("./copy")));
+    assert!(parse(args("strip --output=./copy ./prog")).is_err());
+    assert!(parse(args("info --compress ./prog")).unwrap().options.compression == Some(codec::DEFAULT_LEVEL));
+    assert!(parse(args("pack --bind ./prog")).unwrap().options.bind);
+    assert!(!parse(args("pack ./prog")).unwrap().options.bind);
+
+    assert!(parse(args("--help")).err() == Some(String::new()));
+    assert!(parse(args("pack")).is_err());
+    assert!(parse(args("verify ./prog extra")).is_err());
+    assert!(parse(args("frobnicate ./prog")).is_err());
+    assert!(parse(args("pack --compress=10 ./prog")).is_err());
+    assert!(parse(args("pack --name ./prog")).is_err());
+    assert!(parse(args("pack --key-file=/nonexistent ./prog")).is_err());
+    assert!(parse(vec![]).is_err());
+
+    assert!(flag_names(0) == "none");
+    assert!(flag_names(trailer::FLAG_COMPRESSED | trailer::FLAG_ARCHIVE | 0x8000) == "compressed, archive, 0x8000");
+}
+
+#[test]
+fn test_run() {
+    use std::thread;
+    use std::sync::mpsc;
+    use std::path::Path;
+
+    let dir = "/tmp/test_ulc11_embed_run";
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir(dir).unwrap();
+    let path = |name: &str| format!("{}/{}", dir, name);
+    let write = |name: &str, data: &[u8]| fs::File::create(&path(name)).unwrap().write_all(data).unwrap();
+    // runs a command line as `main` does, `@` standing for `dir`, and
+    // returns the exit status
+    let status = |line: &str| {
+        match parse(line.split(' ').map(|a| a.replace("@", dir)).collect()) {
+            Err(_) => EXIT_USAGE,
+            Ok(c) => match run(c) {
+                Ok(()) => 0,
+                Err(e) => exit_code(&e)
+            }
+        }
+    };
+    let data: Vec<u8> = (0..200).map(|i| i as u8).collect();
+    let program = b"a generic program";
+    fs::copy("/proc/self/exe", &path("elf")).unwrap();
+    write("prog", program);
+    write("data", &data);
+    write("passphrase", b"hunter2\n");
+
+    // pack, unpack, verify and strip
+    for exe in ["elf", "prog"].iter() {
+        assert!(status(&format!("pack --compress @/{} @/data", exe)) == 0);
+        assert!(status(&format!("verify @/{}", exe)) == 0);
+        assert!(status(&format!("info @/{}", exe)) == 0);
+        assert!(status(&format!("unpack @/{} @/out", exe)) == 0);
+        assert!(read_file(&path("out")).unwrap() == data);
+        assert!(status(&format!("strip @/{}", exe)) == 0);
+        assert!(status(&format!("unpack @/{} @/out", exe)) == 2);
+        assert!(status(&format!("verify @/{}", exe)) == 2);
+    }
+    assert!(read_file(&path("prog")).unwrap() == program);
+
+    // resources, and packing into a copy
+    assert!(status("pack --name=d --output=@/copy @/elf @/data") == 0);
+    assert!(status("list @/copy") == 0);
+    assert!(status("unpack --name=d @/copy @/out") == 0);
+    assert!(read_file(&path("out")).unwrap() == data);
+    assert!(status("unpack --name=d @/elf @/out") == 2);
+    assert!(status("unpack --name=missing @/copy @/out") == 2);
+
+    // bad usage
+    assert!(status("frobnicate @/prog") == 1);
+    assert!(status("pack") == 1);
+    assert!(status("strip --output=@/copy @/prog") == 1);
+
+    // damage: cut short, corrupted, and from the future
+    let packed = || {
+        write("prog", program);
+        assert!(status("pack @/prog @/data") == 0);
+        read_file(&path("prog")).unwrap()
+    };
+    let image = packed();
+    write("prog", &image[100..]);
+    assert!(status("verify @/prog") == 3);
+    let mut image = packed();
+    image[program.len()] ^= 0xff;
+    write("prog", &image);
+    assert!(status("verify @/prog") == 4);
+    let mut image = packed();
+    let at = image.len() - 18; // the version, before the 16 byte marker
+    image[at] = 0xff;
+    write("prog", &image);
+    assert!(status("info @/prog") == 5);
+
+    // keys
+    write("prog", program);
+    assert!(status("pack --passphrase-file=@/passphrase @/prog @/data") == 0);
+    assert!(status("unpack --passphrase-file=@/passphrase @/prog @/out") == 0);
+    assert!(status("unpack @/prog @/out") == 6);
+
+    // another writer
+    let (locked_tx, locked_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let prog = path("prog");
+    let writer = thread::spawn(move || {
+        let _lock = ulc11::lock::lock(Path::new(&prog), true).unwrap();
+        locked_tx.send(()).unwrap();
+        let _ = done_rx.recv();
+    });
+    locked_rx.recv().unwrap();
+    assert!(status("strip --no-wait @/prog") == 7);
+    done_tx.send(()).unwrap();
+    writer.join().unwrap();
+
+    // bound data, in another executable
+    write("prog", program);
+    assert!(status("pack --bind @/prog @/data") == 0);
+    let mut image = read_file(&path("prog")).unwrap();
+    assert!(status("unpack @/prog @/out") == 0);
+    image[0] = b'b';
+    write("other", &image);
+    assert!(status("unpack @/other @/out") == 8);
+
+    // anything else
+    assert!(status("unpack @/missing @/out") == 10);
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -1,34 +1,157 @@
-//extern crate libc;
+//! The C interface of ulc11 (see include/ulc11.h).
+//!
+//! Functions report success through their return value (true, or a non null
+//! pointer). On failure, they record an error code and message that can be
+//! retrieved with `ulc11_last_error_code` and `ulc11_last_error`, errno
+//! style: per thread, and until the next failure.
 
 use std::str;
 use std::ffi;
 use std::ptr;
 use std::slice;
+use std::io;
+use std::cell::RefCell;
+use std::error::Error;
 use std::path::PathBuf;
+use libc::c_char;
+use libc::c_int;
 
 use ::ExecPath;
 use ::Embed;
+use ::NO_PAYLOAD;
+use ::TRUNCATED;
+use ::CHECKSUM_MISMATCH;
+use ::UNSUPPORTED_FORMAT;
+use ::AUTHENTICATION_FAILED;
+use ::IDENTITY_MISMATCH;
 use ::auto::AutoEmbed;
 
 use ::ulc21::object;
 use ::ulc21::buffer;
 
+// error codes, mirrored in ulc11.h
+pub const ULC11_OK: c_int = 0;
+pub const ULC11_ERR_NOT_FOUND: c_int = 1;       // the executable doesn't exist
+pub const ULC11_ERR_PERMISSION: c_int = 2;      // the executable can't be read or replaced
+pub const ULC11_ERR_NO_PAYLOAD: c_int = 3;      // nothing, or no such resource, is embeded
+pub const ULC11_ERR_TRUNCATED: c_int = 4;
+pub const ULC11_ERR_CHECKSUM: c_int = 5;
+pub const ULC11_ERR_UNSUPPORTED: c_int = 6;
+pub const ULC11_ERR_AUTHENTICATION: c_int = 7;
+pub const ULC11_ERR_INVALID: c_int = 8;         // bad arguments, or a blob that isn't an archive
+pub const ULC11_ERR_IO: c_int = 9;              // any other error
+pub const ULC11_ERR_IDENTITY: c_int = 10;       // the embeded data was bound to another executable
+
+thread_local!(static LAST_ERROR: RefCell<(c_int, Option<ffi::CString>)> = RefCell::new((ULC11_OK, None)));
+
+fn set_error(code: c_int, message: String) {
+    // messages can't hold NULs in C
+    let message = ffi::CString::new(message.replace("\0", "\\0")).unwrap();
+    LAST_ERROR.with(|l| *l.borrow_mut() = (code, Some(message)));
+}
+
+fn error_code(e: &io::Error) -> c_int {
+    let d = e.description();
+    if d == NO_PAYLOAD {ULC11_ERR_NO_PAYLOAD}
+    else if d == TRUNCATED {ULC11_ERR_TRUNCATED}
+    else if d == CHECKSUM_MISMATCH {ULC11_ERR_CHECKSUM}
+    else if d == UNSUPPORTED_FORMAT {ULC11_ERR_UNSUPPORTED}
+    else if d == AUTHENTICATION_FAILED {ULC11_ERR_AUTHENTICATION}
+    else if d == IDENTITY_MISMATCH {ULC11_ERR_IDENTITY}
+    else {
+        match e.kind() {
+            io::ErrorKind::NotFound => ULC11_ERR_NOT_FOUND,
+            io::ErrorKind::PermissionDenied => ULC11_ERR_PERMISSION,
+            io::ErrorKind::InvalidInput => ULC11_ERR_INVALID,
+            _ => ULC11_ERR_IO
+        }
+    }
+}
+
+// records `e` as the last error
+fn fail(e: io::Error) {
+    set_error(error_code(&e), format!("{}", e));
+}
+
+// converts a C string argument, recording an error if it isn't valid
+unsafe fn c_str<'a>(s: *const c_char, what: &str) -> Option<&'a str> {
+    if s.is_null() {
+        set_error(ULC11_ERR_INVALID, format!("{} is NULL", what));
+        return None;
+    }
+    match str::from_utf8(ffi::CStr::from_ptr(s).to_bytes()) {
+        Ok(s) => Some(s),
+        Err(_) => {
+            set_error(ULC11_ERR_INVALID, format!("{} is not valid UTF-8", what));
+            None
+        }
+    }
+}
+
+// the embedder behind a handle, recording an error if it is NULL
+unsafe fn embedder<'a>(e: *mut AutoEmbed) -> Option<&'a mut AutoEmbed> {
+    if e.is_null() {
+        set_error(ULC11_ERR_INVALID, format!("Embedder handle is NULL"));
+        return None;
+    }
+    Some(&mut *e)
+}
+
+// converts a buffer argument, recording an error if it is NULL; empty
+// buffers may be NULL
+unsafe fn c_buffer<'a>(data: *const u8, length: u64) -> Option<&'a [u8]> {
+    if length == 0 {
+        return Some(&[]);
+    }
+    if data.is_null() {
+        set_error(ULC11_ERR_INVALID, format!("Data is NULL"));
+        return None;
+    }
+    Some(slice::from_raw_parts(data, length as usize))
+}
+
+// hands `data` over to C, to be freed by `ulc11_data_free`
+unsafe fn give_buffer(data: Vec<u8>, length: *mut u64) -> *mut u8 {
+    let (ptr, len) = buffer::into_buffer(data);
+    *length = len as u64;
+    ptr
+}
+
+/// The code of the last error on this thread, ULC11_OK if none occured.
 #[no_mangle]
-pub extern "C" fn ulc11_new(name: *const i8) -> *mut AutoEmbed {
+pub extern "C" fn ulc11_last_error_code() -> c_int {
+    LAST_ERROR.with(|l| l.borrow().0)
+}
+
+/// The message of the last error on this thread, NULL if none occured. The
+/// message is owned by ulc11, and is valid until the next error.
+#[no_mangle]
+pub extern "C" fn ulc11_last_error() -> *const c_char {
+    LAST_ERROR.with(|l| match l.borrow().1 {
+        Some(ref m) => m.as_ptr(),
+        None => ptr::null()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn ulc11_new(name: *const c_char) -> *mut AutoEmbed {
     let ep: ExecPath;
-    
+
     if name.is_null() {
         ep = ExecPath::This;
     }
     else {
-        match unsafe {str::from_utf8(ffi::CStr::from_ptr(name).to_bytes())} {
-            Ok(filename) => {ep = ExecPath::File(PathBuf::new(filename));},
-            _ => {return ptr::null_mut();}       
+        match unsafe {c_str(name, "Executable path")} {
+            Some(filename) => {ep = ExecPath::File(PathBuf::new(filename));},
+            None => {return ptr::null_mut();}
         }
     }
     match AutoEmbed::new(ep) {
         Ok(e) => unsafe {object::into_obj(e)},
-        _ => ptr::null_mut()
+        Err(e) => {
+            fail(e);
+            ptr::null_mut()
+        }
     }
 }
 
@@ -39,22 +162,50 @@ pub extern "C" fn ulc11_free(e: *mut AutoEmbed) {
 
 #[no_mangle]
 pub extern "C" fn ulc11_data_strip(e: *mut AutoEmbed) -> bool{
-    match unsafe {(*e).strip()} {
+    let e = match unsafe {embedder(e)} {
+        Some(e) => e,
+        None => return false
+    };
+    match e.strip() {
         Ok(_) => true,
-        Err(_) => false
+        Err(err) => {
+            fail(err);
+            false
+        }
     }
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn ulc11_data_load(e: *mut AutoEmbed, length: *mut u64) -> *mut u8 {
-    match (*e).load() {
-        Ok(l) => {
-            let (ptr, len) = buffer::into_buffer(l);
-            *length = len as u64;
-            return ptr;
+    let e = match embedder(e) {
+        Some(e) => e,
+        None => return ptr::null_mut()
+    };
+    // an executable without data has an empty buffer, like before checksums
+    let data = match e.load() {
+        Err(ref err) if err.description() == NO_PAYLOAD => Ok(vec![]),
+        r => r
+    };
+    match data {
+        Ok(l) => give_buffer(l, length),
+        Err(err) => {
+            fail(err);
+            ptr::null_mut()
         }
-        _ => {
-            return ptr::null_mut();
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn ulc11_data_verify(e: *mut AutoEmbed) -> bool {
+    let e = match unsafe {embedder(e)} {
+        Some(e) => e,
+        None => return false
+    };
+    match e.verify() {
+        Ok(_) => true,
+        Err(err) => {
+            fail(err);
+            false
         }
     }
 }
@@ -66,9 +217,171 @@ pub unsafe extern "C" fn ulc11_data_free(data: *mut u8) {
 
 
 #[no_mangle]
-pub extern "C" fn ulc11_data_store(e: *mut AutoEmbed, data: *mut u8, length: u64) -> bool {
-    match unsafe {(*e).store(slice::from_raw_parts(data, length as usize))} {
+pub extern "C" fn ulc11_data_store(e: *mut AutoEmbed, data: *const u8, length: u64) -> bool {
+    let (e, data) = match unsafe {(embedder(e), c_buffer(data, length))} {
+        (Some(e), Some(data)) => (e, data),
+        _ => return false
+    };
+    match e.store(data) {
         Ok(_) => {true},
-        Err(_) => {false}
+        Err(err) => {
+            fail(err);
+            false
+        }
+    }
+}
+
+/// The names of the embeded resources, sorted, each followed by a NUL.
+#[no_mangle]
+pub unsafe extern "C" fn ulc11_resource_names(e: *mut AutoEmbed, length: *mut u64) -> *mut u8 {
+    let e = match embedder(e) {
+        Some(e) => e,
+        None => return ptr::null_mut()
+    };
+    match e.list() {
+        Ok(names) => {
+            let mut out: Vec<u8> = vec![];
+            for n in names.iter() {
+                out.push_all(n.as_bytes());
+                out.push(0);
+            }
+            give_buffer(out, length)
+        },
+        Err(err) => {
+            fail(err);
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ulc11_resource_get(e: *mut AutoEmbed, name: *const c_char, length: *mut u64) -> *mut u8 {
+    let name = match c_str(name, "Resource name") {
+        Some(n) => n,
+        None => return ptr::null_mut()
+    };
+    let e = match embedder(e) {
+        Some(e) => e,
+        None => return ptr::null_mut()
+    };
+    match e.get(name) {
+        Ok(Some(d)) => give_buffer(d, length),
+        Ok(None) => {
+            set_error(ULC11_ERR_NO_PAYLOAD, format!("No resource named '{}'", name));
+            ptr::null_mut()
+        },
+        Err(err) => {
+            fail(err);
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ulc11_resource_insert(e: *mut AutoEmbed, name: *const c_char, data: *const u8, length: u64) -> bool {
+    let name = match c_str(name, "Resource name") {
+        Some(n) => n,
+        None => return false
+    };
+    let (e, data) = match (embedder(e), c_buffer(data, length)) {
+        (Some(e), Some(data)) => (e, data),
+        _ => return false
+    };
+    match e.insert(name, data) {
+        Ok(_) => true,
+        Err(err) => {
+            fail(err);
+            false
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ulc11_resource_remove(e: *mut AutoEmbed, name: *const c_char) -> bool {
+    let name = match c_str(name, "Resource name") {
+        Some(n) => n,
+        None => return false
+    };
+    let e = match embedder(e) {
+        Some(e) => e,
+        None => return false
+    };
+    match e.remove(name) {
+        Ok(true) => true,
+        Ok(false) => {
+            set_error(ULC11_ERR_NO_PAYLOAD, format!("No resource named '{}'", name));
+            false
+        },
+        Err(err) => {
+            fail(err);
+            false
+        }
     }
 }
+
+
+#[test]
+fn test_c() {
+    use std::fs;
+    use std::ffi::CStr;
+    use std::ffi::CString;
+
+    let last = || unsafe { str::from_utf8(CStr::from_ptr(ulc11_last_error()).to_bytes()).unwrap().to_string() };
+
+    let missing = CString::new("/nonexistent/ulc11").unwrap();
+    assert!(ulc11_new(missing.as_ptr()).is_null());
+    assert!(ulc11_last_error_code() == ULC11_ERR_NOT_FOUND);
+    assert!(last().len() > 0);
+
+    let path = "/tmp/test_ulc11_c";
+    let _ = fs::remove_file(path);
+    fs::copy("/proc/self/exe", path).unwrap();
+    let cpath = CString::new(path).unwrap();
+    let e = ulc11_new(cpath.as_ptr());
+    assert!(!e.is_null());
+
+    let mut length = 0u64;
+    assert!(!ulc11_data_verify(e));
+    assert!(ulc11_last_error_code() == ULC11_ERR_NO_PAYLOAD);
+    assert!(last().starts_with(NO_PAYLOAD));
+
+    unsafe {
+        let icon = CString::new("icon").unwrap();
+        assert!(ulc11_resource_insert(e, icon.as_ptr(), [1u8, 2, 3].as_ptr(), 3));
+        assert!(ulc11_resource_insert(e, CString::new("z").unwrap().as_ptr(), ptr::null(), 0));
+        assert!(!ulc11_resource_insert(e, icon.as_ptr(), ptr::null(), 3));
+        assert!(ulc11_last_error_code() == ULC11_ERR_INVALID);
+
+        let names = ulc11_resource_names(e, &mut length);
+        assert!(slice::from_raw_parts(names, length as usize) == b"icon\0z\0");
+        ulc11_data_free(names);
+
+        let d = ulc11_resource_get(e, icon.as_ptr(), &mut length);
+        assert!(slice::from_raw_parts(d, length as usize) == [1, 2, 3]);
+        ulc11_data_free(d);
+
+        assert!(ulc11_resource_remove(e, icon.as_ptr()));
+        assert!(!ulc11_resource_remove(e, icon.as_ptr()));
+        assert!(ulc11_last_error_code() == ULC11_ERR_NO_PAYLOAD);
+        assert!(ulc11_resource_get(e, icon.as_ptr(), &mut length).is_null());
+        assert!(ulc11_resource_get(e, ptr::null(), &mut length).is_null());
+        assert!(ulc11_last_error_code() == ULC11_ERR_INVALID);
+
+        // NULL handles are refused
+        assert!(!ulc11_data_store(ptr::null_mut(), [9u8].as_ptr(), 1));
+        assert!(ulc11_last_error_code() == ULC11_ERR_INVALID);
+        assert!(ulc11_data_load(ptr::null_mut(), &mut length).is_null());
+        assert!(ulc11_resource_names(ptr::null_mut(), &mut length).is_null());
+        assert!(!ulc11_data_verify(ptr::null_mut()) && !ulc11_data_strip(ptr::null_mut()));
+
+        // a raw blob isn't an archive
+        assert!(ulc11_data_store(e, [9u8].as_ptr(), 1));
+        assert!(ulc11_data_verify(e));
+        assert!(ulc11_resource_names(e, &mut length).is_null());
+        assert!(ulc11_last_error_code() == ULC11_ERR_INVALID);
+    }
+
+    assert!(ulc11_data_strip(e));
+    ulc11_free(e);
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,294 @@
+//! How data is transformed on its way into and out of an embeded blob:
+//! compressed first, then encrypted.
+//!
+//! Transformations are recorded in the trailer's flags, so loading undoes
+//! them without being told; only the key of encrypted data has to be
+//! given, through `Options`.
+
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use ::lz;
+use ::crypto;
+use ::trailer;
+use ::trailer::Trailer;
+use ::trailer::FLAG_COMPRESSED;
+use ::trailer::FLAG_ENCRYPTED;
+use ::trailer::EXT_ORIGINAL_LENGTH;
+use ::trailer::EXT_NONCE;
+use ::trailer::EXT_KDF;
+use ::trailer::EXT_IDENTITY;
+use ::AUTHENTICATION_FAILED;
+use ulc91::unsigned;
+
+pub use ::lz::DEFAULT_LEVEL;
+pub use ::lz::MAX_LEVEL;
+pub use ::crypto::KEY_LEN;
+
+const SALT_LEN: usize = 16;
+const KDF_ITERATIONS: u32 = 100000;
+// the most iterations loading accepts: the count comes from the trailer,
+// and a tampered one could otherwise stall loading for hours
+const MAX_KDF_ITERATIONS: u32 = 16 * KDF_ITERATIONS;
+
+/// A key to encrypt and decrypt embeded data with.
+#[derive(Clone)]
+pub enum Key {
+    Raw([u8; KEY_LEN]),     // Used as is
+    Passphrase(String)      // Stretched with PBKDF2-HMAC-SHA256, salted anew on each store
+}
+
+/// How an embedder stores data, and the key it loads encrypted data with.
+/// By default data is stored as is.
+#[derive(Clone)]
+pub struct Options {
+    pub compression: Option<u32>,   // Compression level (1 to MAX_LEVEL), None to not compress
+    pub key: Option<Key>,           // Key to encrypt with (ChaCha20-Poly1305), None to not encrypt
+    pub bind: bool                  // Whether to bind data to its executable (see identity.rs)
+}
+impl Options {
+    pub fn new() -> Options {
+        Options {compression: None, key: None, bind: false}
+    }
+
+    /// Compresses stored data at `level` (1 to `MAX_LEVEL`).
+    pub fn compress(mut self, level: u32) -> Options {
+        self.compression = Some(level);
+        self
+    }
+
+    /// Encrypts stored data with `key`, and decrypts loaded data with it.
+    pub fn encrypt(mut self, key: Key) -> Options {
+        self.key = Some(key);
+        self
+    }
+
+    /// Binds stored data to the executable it is stored in: loading it out
+    /// of any other executable fails with `IDENTITY_MISMATCH`.
+    pub fn bind(mut self) -> Options {
+        self.bind = true;
+        self
+    }
+}
+
+fn authentication_failed(detail: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, AUTHENTICATION_FAILED, Some(detail.to_string()))
+}
+
+// the additional data authenticated along with encrypted data: its flags,
+// so that they can't be flipped to have it misinterpreted
+fn aad(flags: u16) -> Vec<u8> {
+    unsigned::to_le_bytes(flags)
+}
+
+/// Generates the embeded blob for `data` (see `trailer::gen_embed_blob`),
+/// transformed as `options` says. Data that doesn't shrink when compressed
+/// is stored uncompressed.
+pub fn encode(data: &[u8], flags: u16, options: &Options) -> io::Result<Vec<u8>> {
+    encode_for(data, flags, options, None)
+}
+
+/// Like `encode`, also recording `identity`, the identity of the executable
+/// the blob is stored in, if given (see identity.rs).
+pub fn encode_for(data: &[u8], flags: u16, options: &Options, identity: Option<Vec<u8>>) -> io::Result<Vec<u8>> {
+    let mut flags = flags & !(FLAG_COMPRESSED | FLAG_ENCRYPTED);
+    let mut ext: Vec<(u16, Vec<u8>)> = vec![];
+    let mut payload: Vec<u8> = vec![];
+
+    if let Some(level) = options.compression {
+        let packed = lz::compress(data, level);
+        if packed.len() < data.len() {
+            ext.push((EXT_ORIGINAL_LENGTH, unsigned::to_le_bytes(data.len() as u64)));
+            flags |= FLAG_COMPRESSED;
+            payload = packed;
+        }
+    }
+    if flags & FLAG_COMPRESSED == 0 {
+        payload = data.to_vec();
+    }
+
+    if let Some(ref key) = options.key {
+        let nonce = try!(crypto::random(crypto::NONCE_LEN));
+        let k = match *key {
+            Key::Raw(k) => k,
+            Key::Passphrase(ref p) => {
+                let salt = try!(crypto::random(SALT_LEN));
+                let mut k = [0u8; KEY_LEN];
+                crypto::pbkdf2_sha256(p.as_bytes(), &salt, KDF_ITERATIONS, &mut k);
+                let mut kdf = unsigned::to_le_bytes(KDF_ITERATIONS);
+                kdf.push_all(&salt);
+                ext.push((EXT_KDF, kdf));
+                k
+            }
+        };
+        flags |= FLAG_ENCRYPTED;
+        payload = crypto::seal(&k, &nonce, &aad(flags), &payload);
+        ext.push((EXT_NONCE, nonce));
+    }
+    if let Some(id) = identity {
+        ext.push((EXT_IDENTITY, id));
+    }
+
+    Ok(trailer::gen_embed_blob(&payload, flags, &ext))
+}
+
+// decrypts `b`, stored as described by `t`
+fn decrypt(b: &[u8], t: &Trailer, options: &Options) -> io::Result<Vec<u8>> {
+    let nonce = match t.extension(EXT_NONCE) {
+        Some(n) if n.len() == crypto::NONCE_LEN => n,
+        _ => return Err(trailer::unsupported(format!("Encrypted data without a valid nonce")))
+    };
+    let k = match (&options.key, t.extension(EXT_KDF)) {
+        (&None, _) => return Err(authentication_failed("No key was given")),
+        (&Some(Key::Raw(k)), None) => k,
+        (&Some(Key::Passphrase(ref p)), Some(kdf)) if kdf.len() == 4 + SALT_LEN => {
+            let iterations = unsigned::from_le_bytes::<u32>(&kdf[..4]);
+            if iterations > MAX_KDF_ITERATIONS {
+                return Err(trailer::unsupported(format!("{} key derivation iterations, at most {} are supported",
+                                                        iterations, MAX_KDF_ITERATIONS)));
+            }
+            let mut k = [0u8; KEY_LEN];
+            crypto::pbkdf2_sha256(p.as_bytes(), &kdf[4..], iterations, &mut k);
+            k
+        },
+        (&Some(Key::Passphrase(_)), Some(_)) => return Err(trailer::unsupported(format!("Malformed key derivation parameters"))),
+        (&Some(Key::Raw(_)), Some(_)) => return Err(authentication_failed("Data is encrypted with a passphrase, not a key")),
+        (&Some(Key::Passphrase(_)), None) => return Err(authentication_failed("Data is encrypted with a key, not a passphrase"))
+    };
+    match crypto::open(&k, nonce, &aad(t.flags), b) {
+        Some(plain) => Ok(plain),
+        None => Err(authentication_failed("Wrong key, or tampered data"))
+    }
+}
+
+/// Reads the data described by `t` out of `e`, checking it and undoing
+/// the transformations it was stored with. Encrypted data is decrypted
+/// with the key in `options`, and fails with `AUTHENTICATION_FAILED` if
+/// that key is missing or wrong, or if the data was tampered with.
+pub fn decode<T: Read + Seek>(e: &mut T, t: &Trailer, options: &Options) -> io::Result<Vec<u8>> {
+    let mut b = try!(trailer::load(e, t));
+    if t.has(FLAG_ENCRYPTED) {
+        b = try!(decrypt(&b, t, options));
+    }
+    if !t.has(FLAG_COMPRESSED) {
+        return Ok(b);
+    }
+    // the original length isn't covered by the checksum: bound it before
+    // allocating for it
+    let length = match t.extension(EXT_ORIGINAL_LENGTH) {
+        Some(v) if v.len() == 8 => unsigned::from_le_bytes::<u64>(v),
+        _ => return Err(trailer::unsupported(format!("Compressed data without its original length")))
+    };
+    if length > lz::max_expansion(b.len()) as u64 {
+        return Err(trailer::unsupported(format!("{} compressed bytes can't expand to {}", b.len(), length)));
+    }
+    lz::decompress(&b, length as usize)
+}
+
+
+#[test]
+fn test_codec() {
+    use std::io::Cursor;
+    use std::error::Error;
+
+    let mut text: Vec<u8> = vec![];
+    for i in 0..1000 {
+        text.push_all(format!("line {} of a large text asset\n", i % 17).as_bytes());
+    }
+
+    // stored as is by default
+    let blob = encode(&text, 0, &Options::new()).unwrap();
+    let mut c = Cursor::new(&blob[..]);
+    let t = trailer::read_trailer(&mut c).unwrap().unwrap();
+    assert!(!t.has(FLAG_COMPRESSED) && t.length == text.len() as u64);
+    assert!(t.original_length() == text.len() as u64);
+    assert!(decode(&mut c, &t, &Options::new()).unwrap() == text);
+
+    // compressed
+    let blob = encode(&text, trailer::FLAG_ARCHIVE, &Options::new().compress(DEFAULT_LEVEL)).unwrap();
+    let mut c = Cursor::new(&blob[..]);
+    let t = trailer::read_trailer(&mut c).unwrap().unwrap();
+    assert!(t.has(FLAG_COMPRESSED) && t.has(trailer::FLAG_ARCHIVE));
+    assert!(t.length < text.len() as u64 / 4);
+    assert!(t.original_length() == text.len() as u64);
+    assert!(decode(&mut c, &t, &Options::new()).unwrap() == text);
+
+    // incompressible data is stored as is
+    let blob = encode(&[1, 2, 3], 0, &Options::new().compress(MAX_LEVEL)).unwrap();
+    let mut c = Cursor::new(&blob[..]);
+    let t = trailer::read_trailer(&mut c).unwrap().unwrap();
+    assert!(!t.has(FLAG_COMPRESSED));
+    assert!(decode(&mut c, &t, &Options::new()).unwrap() == [1, 2, 3]);
+
+    // an original length the data can't expand to is refused up front
+    let blob = encode(&text, 0, &Options::new().compress(DEFAULT_LEVEL)).unwrap();
+    let t = trailer::read_trailer(&mut Cursor::new(&blob[..])).unwrap().unwrap();
+    let end = t.length as usize;
+    let mut bad = blob[..end].to_vec();
+    let ext: Vec<(u16, Vec<u8>)> = t.extensions.iter().map(|&(k, ref v)| {
+        (k, if k == EXT_ORIGINAL_LENGTH {unsigned::to_le_bytes(1u64 << 40)} else {v.clone()})
+    }).collect();
+    bad.push_all(&trailer::gen_trailer(end as u64, t.checksum.unwrap(), t.flags, &ext));
+    let mut c = Cursor::new(&bad[..]);
+    let bt = trailer::read_trailer(&mut c).unwrap().unwrap();
+    assert!(decode(&mut c, &bt, &Options::new()).unwrap_err().description() == ::UNSUPPORTED_FORMAT);
+
+    // encrypted, with a key
+    let key = Key::Raw([7u8; KEY_LEN]);
+    let opts = Options::new().compress(DEFAULT_LEVEL).encrypt(key.clone());
+    let blob = encode(&text, 0, &opts).unwrap();
+    assert!(blob.windows(13).all(|w| w != &b"large text as"[..]));
+    let mut c = Cursor::new(&blob[..]);
+    let t = trailer::read_trailer(&mut c).unwrap().unwrap();
+    assert!(t.has(FLAG_ENCRYPTED) && t.has(FLAG_COMPRESSED));
+    assert!(t.original_length() == text.len() as u64);
+    assert!(decode(&mut c, &t, &opts).unwrap() == text);
+    assert!(decode(&mut c, &t, &Options::new().encrypt(key)).unwrap() == text);
+
+    let failure = |o: &Options| decode(&mut Cursor::new(&blob[..]), &t, o).unwrap_err().description().to_string();
+    assert!(failure(&Options::new()) == AUTHENTICATION_FAILED);
+    assert!(failure(&Options::new().encrypt(Key::Raw([8u8; KEY_LEN]))) == AUTHENTICATION_FAILED);
+    assert!(failure(&Options::new().encrypt(Key::Passphrase("7".to_string()))) == AUTHENTICATION_FAILED);
+
+    // tampered: the checksum is fixed up, the tag catches it
+    let mut bad = blob.clone();
+    bad[5] ^= 1;
+    let end = t.length as usize;
+    let mut bad = bad[..end].to_vec();
+    bad.push_all(&trailer::gen_trailer(end as u64, ::ulc91::utilities::crc32(&bad), t.flags, &t.extensions));
+    let mut c = Cursor::new(&bad[..]);
+    let bt = trailer::read_trailer(&mut c).unwrap().unwrap();
+    assert!(decode(&mut c, &bt, &opts).unwrap_err().description() == AUTHENTICATION_FAILED);
+    // so are flipped flags
+    let mut bad = blob[..end].to_vec();
+    bad.push_all(&trailer::gen_trailer(end as u64, t.checksum.unwrap(), FLAG_ENCRYPTED, &t.extensions));
+    let mut c = Cursor::new(&bad[..]);
+    let bt = trailer::read_trailer(&mut c).unwrap().unwrap();
+    assert!(decode(&mut c, &bt, &opts).unwrap_err().description() == AUTHENTICATION_FAILED);
+
+    // with a passphrase
+    let opts = Options::new().encrypt(Key::Passphrase("open sesame".to_string()));
+    let blob = encode(b"license: 1234", 0, &opts).unwrap();
+    let mut c = Cursor::new(&blob[..]);
+    let t = trailer::read_trailer(&mut c).unwrap().unwrap();
+    assert!(t.extension(EXT_KDF).is_some());
+    assert!(decode(&mut c, &t, &opts).unwrap() == b"license: 1234");
+    let wrong = Options::new().encrypt(Key::Passphrase("open sesam".to_string()));
+    assert!(decode(&mut c, &t, &wrong).unwrap_err().description() == AUTHENTICATION_FAILED);
+
+    // absurd iteration counts are refused before deriving anything
+    let end = t.length as usize;
+    let mut bad = blob[..end].to_vec();
+    let ext: Vec<(u16, Vec<u8>)> = t.extensions.iter().map(|&(k, ref v)| {
+        if k != EXT_KDF {
+            return (k, v.clone());
+        }
+        let mut kdf = unsigned::to_le_bytes(!0u32);
+        kdf.push_all(&v[4..]);
+        (k, kdf)
+    }).collect();
+    bad.push_all(&trailer::gen_trailer(end as u64, t.checksum.unwrap(), t.flags, &ext));
+    let mut c = Cursor::new(&bad[..]);
+    let bt = trailer::read_trailer(&mut c).unwrap().unwrap();
+    assert!(decode(&mut c, &bt, &opts).unwrap_err().description() == ::UNSUPPORTED_FORMAT);
+}
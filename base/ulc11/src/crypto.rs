@@ -0,0 +1,393 @@
+//! The primitives behind encrypted blobs: the ChaCha20-Poly1305 AEAD of
+//! RFC 7539, and PBKDF2-HMAC-SHA256 (RFC 2898) to derive keys from
+//! passphrases.
+
+use std::fs;
+use std::io;
+use std::io::Read;
+
+/// Length of keys.
+pub const KEY_LEN: usize = 32;
+/// Length of nonces.
+pub const NONCE_LEN: usize = 12;
+/// Length of the authentication tag that ends sealed data.
+pub const TAG_LEN: usize = 16;
+
+fn le32(b: &[u8]) -> u32 {
+    (b[0] as u32) | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24
+}
+
+fn put_le32(out: &mut [u8], v: u32) {
+    for i in 0..4 {
+        out[i] = (v >> (8 * i)) as u8;
+    }
+}
+
+fn le64_bytes(v: u64) -> [u8; 8] {
+    let mut b = [0u8; 8];
+    for i in 0..8 {
+        b[i] = (v >> (8 * i)) as u8;
+    }
+    b
+}
+
+/// `n` bytes from the kernel's random number generator.
+pub fn random(n: usize) -> io::Result<Vec<u8>> {
+    let mut b: Vec<u8> = vec![];
+    try!(try!(fs::File::open("/dev/urandom")).take(n as u64).read_to_end(&mut b));
+    if b.len() != n {
+        return Err(io::Error::new(io::ErrorKind::Other, "Could not read random bytes",
+                                    Some(format!("Read {} of {} bytes", b.len(), n))));
+    }
+    Ok(b)
+}
+
+// -------------------------------------------------------------------------
+// ChaCha20
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]); s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]); s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]); s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]); s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+fn chacha20_block(key: &[u8], counter: u32, nonce: &[u8], out: &mut [u8]) {
+    let mut init = [0u32; 16];
+    init[0] = 0x61707865; init[1] = 0x3320646e; init[2] = 0x79622d32; init[3] = 0x6b206574;
+    for i in 0..8 {
+        init[4 + i] = le32(&key[4 * i..]);
+    }
+    init[12] = counter;
+    for i in 0..3 {
+        init[13 + i] = le32(&nonce[4 * i..]);
+    }
+
+    let mut s = init;
+    for _ in 0..10 {
+        quarter_round(&mut s, 0, 4, 8, 12);
+        quarter_round(&mut s, 1, 5, 9, 13);
+        quarter_round(&mut s, 2, 6, 10, 14);
+        quarter_round(&mut s, 3, 7, 11, 15);
+        quarter_round(&mut s, 0, 5, 10, 15);
+        quarter_round(&mut s, 1, 6, 11, 12);
+        quarter_round(&mut s, 2, 7, 8, 13);
+        quarter_round(&mut s, 3, 4, 9, 14);
+    }
+    for i in 0..16 {
+        put_le32(&mut out[4 * i..], s[i].wrapping_add(init[i]));
+    }
+}
+
+// xors `data` with the key stream, starting at block `counter`
+fn chacha20_xor(key: &[u8], counter: u32, nonce: &[u8], data: &mut [u8]) {
+    let mut block = [0u8; 64];
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        chacha20_block(key, counter + i as u32, nonce, &mut block);
+        for (b, k) in chunk.iter_mut().zip(block.iter()) {
+            *b ^= *k;
+        }
+    }
+}
+
+// -------------------------------------------------------------------------
+// Poly1305, in 26-bit limbs
+
+fn poly1305(key: &[u8], msg: &[u8]) -> [u8; 16] {
+    let r0 = (le32(&key[0..]) & 0x3ffffff) as u64;
+    let r1 = ((le32(&key[3..]) >> 2) & 0x3ffff03) as u64;
+    let r2 = ((le32(&key[6..]) >> 4) & 0x3ffc0ff) as u64;
+    let r3 = ((le32(&key[9..]) >> 6) & 0x3f03fff) as u64;
+    let r4 = ((le32(&key[12..]) >> 8) & 0x00fffff) as u64;
+    let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+    let (mut h0, mut h1, mut h2, mut h3, mut h4) = (0u32, 0u32, 0u32, 0u32, 0u32);
+
+    for chunk in msg.chunks(16) {
+        let mut block = [0u8; 17];
+        for (b, m) in block.iter_mut().zip(chunk.iter()) {
+            *b = *m;
+        }
+        let hibit = if chunk.len() == 16 {1 << 24} else {block[chunk.len()] = 1; 0};
+
+        h0 += le32(&block[0..]) & 0x3ffffff;
+        h1 += (le32(&block[3..]) >> 2) & 0x3ffffff;
+        h2 += (le32(&block[6..]) >> 4) & 0x3ffffff;
+        h3 += (le32(&block[9..]) >> 6) & 0x3ffffff;
+        h4 += (le32(&block[12..]) >> 8) | hibit;
+
+        let (g0, g1, g2, g3, g4) = (h0 as u64, h1 as u64, h2 as u64, h3 as u64, h4 as u64);
+        let d0 = g0 * r0 + g1 * s4 + g2 * s3 + g3 * s2 + g4 * s1;
+        let mut d1 = g0 * r1 + g1 * r0 + g2 * s4 + g3 * s3 + g4 * s2;
+        let mut d2 = g0 * r2 + g1 * r1 + g2 * r0 + g3 * s4 + g4 * s3;
+        let mut d3 = g0 * r3 + g1 * r2 + g2 * r1 + g3 * r0 + g4 * s4;
+        let mut d4 = g0 * r4 + g1 * r3 + g2 * r2 + g3 * r1 + g4 * r0;
+
+        d1 += d0 >> 26; h0 = (d0 & 0x3ffffff) as u32;
+        d2 += d1 >> 26; h1 = (d1 & 0x3ffffff) as u32;
+        d3 += d2 >> 26; h2 = (d2 & 0x3ffffff) as u32;
+        d4 += d3 >> 26; h3 = (d3 & 0x3ffffff) as u32;
+        h0 += ((d4 >> 26) * 5) as u32; h4 = (d4 & 0x3ffffff) as u32;
+        h1 += h0 >> 26; h0 &= 0x3ffffff;
+    }
+
+    // fully carry h
+    h2 += h1 >> 26; h1 &= 0x3ffffff;
+    h3 += h2 >> 26; h2 &= 0x3ffffff;
+    h4 += h3 >> 26; h3 &= 0x3ffffff;
+    h0 += (h4 >> 26) * 5; h4 &= 0x3ffffff;
+    h1 += h0 >> 26; h0 &= 0x3ffffff;
+
+    // h - p, used instead of h if it doesn't underflow
+    let mut g0 = h0 + 5;
+    let mut g1 = h1 + (g0 >> 26); g0 &= 0x3ffffff;
+    let mut g2 = h2 + (g1 >> 26); g1 &= 0x3ffffff;
+    let mut g3 = h3 + (g2 >> 26); g2 &= 0x3ffffff;
+    let mut g4 = (h4 + (g3 >> 26)).wrapping_sub(1 << 26); g3 &= 0x3ffffff;
+    let mask = (g4 >> 31).wrapping_sub(1);
+    g0 &= mask; g1 &= mask; g2 &= mask; g3 &= mask; g4 &= mask;
+    let mask = !mask;
+    h0 = (h0 & mask) | g0;
+    h1 = (h1 & mask) | g1;
+    h2 = (h2 & mask) | g2;
+    h3 = (h3 & mask) | g3;
+    h4 = (h4 & mask) | g4;
+
+    // h + s, mod 2^128
+    let w0 = h0 | h1 << 26;
+    let w1 = h1 >> 6 | h2 << 20;
+    let w2 = h2 >> 12 | h3 << 14;
+    let w3 = h3 >> 18 | h4 << 8;
+    let mut tag = [0u8; 16];
+    let mut f = 0u64;
+    for (i, w) in [w0, w1, w2, w3].iter().enumerate() {
+        f = (f >> 32) + *w as u64 + le32(&key[16 + 4 * i..]) as u64;
+        put_le32(&mut tag[4 * i..], f as u32);
+    }
+    tag
+}
+
+fn aead_tag(key: &[u8], nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> [u8; 16] {
+    let mut otk = [0u8; 64];
+    chacha20_block(key, 0, nonce, &mut otk);
+
+    let mut mac: Vec<u8> = vec![];
+    mac.push_all(aad);
+    mac.push_all(&[0u8; 16][..(16 - aad.len() % 16) % 16]);
+    mac.push_all(ciphertext);
+    mac.push_all(&[0u8; 16][..(16 - ciphertext.len() % 16) % 16]);
+    mac.push_all(&le64_bytes(aad.len() as u64));
+    mac.push_all(&le64_bytes(ciphertext.len() as u64));
+    poly1305(&otk[..32], &mac)
+}
+
+/// Encrypts `plaintext` and authenticates it along with `aad`. Returns the
+/// ciphertext followed by the tag.
+pub fn seal(key: &[u8], nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut out = plaintext.to_vec();
+    chacha20_xor(key, 1, nonce, &mut out);
+    let tag = aead_tag(key, nonce, aad, &out);
+    out.push_all(&tag);
+    out
+}
+
+/// Checks and decrypts the output of `seal`. Returns None if the key,
+/// nonce or `aad` differ, or if `sealed` was tampered with.
+pub fn open(key: &[u8], nonce: &[u8], aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < TAG_LEN {
+        return None;
+    }
+    let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+
+    // compare in constant time
+    let expected = aead_tag(key, nonce, aad, ciphertext);
+    let diff = expected.iter().zip(tag.iter()).fold(0u8, |d, (a, b)| d | (*a ^ *b));
+    if diff != 0 {
+        return None;
+    }
+    let mut out = ciphertext.to_vec();
+    chacha20_xor(key, 1, nonce, &mut out);
+    Some(out)
+}
+
+// -------------------------------------------------------------------------
+// SHA-256, HMAC and PBKDF2
+
+const K256: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2];
+
+fn sha256_compress(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for i in 0..16 {
+        let b = &block[4 * i..];
+        w[i] = (b[0] as u32) << 24 | (b[1] as u32) << 16 | (b[2] as u32) << 8 | (b[3] as u32);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+
+    let mut v = *state;
+    for i in 0..64 {
+        let s1 = v[4].rotate_right(6) ^ v[4].rotate_right(11) ^ v[4].rotate_right(25);
+        let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+        let t1 = v[7].wrapping_add(s1).wrapping_add(ch).wrapping_add(K256[i]).wrapping_add(w[i]);
+        let s0 = v[0].rotate_right(2) ^ v[0].rotate_right(13) ^ v[0].rotate_right(22);
+        let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+        let t2 = s0.wrapping_add(maj);
+        v[7] = v[6]; v[6] = v[5]; v[5] = v[4];
+        v[4] = v[3].wrapping_add(t1);
+        v[3] = v[2]; v[2] = v[1]; v[1] = v[0];
+        v[0] = t1.wrapping_add(t2);
+    }
+    for i in 0..8 {
+        state[i] = state[i].wrapping_add(v[i]);
+    }
+}
+
+/// SHA-256 of the concatenation of `parts`.
+pub fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut state: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+                                0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+    let mut buf: Vec<u8> = vec![];
+    let mut total = 0u64;
+    for p in parts.iter() {
+        total += p.len() as u64;
+        buf.push_all(p);
+        let full = buf.len() - buf.len() % 64;
+        for block in buf[..full].chunks(64) {
+            sha256_compress(&mut state, block);
+        }
+        buf = buf[full..].to_vec();
+    }
+
+    buf.push(0x80);
+    while buf.len() % 64 != 56 {
+        buf.push(0);
+    }
+    for i in 0..8 {
+        buf.push(((total * 8) >> (56 - 8 * i)) as u8);
+    }
+    for block in buf.chunks(64) {
+        sha256_compress(&mut state, block);
+    }
+
+    let mut out = [0u8; 32];
+    for i in 0..8 {
+        for j in 0..4 {
+            out[4 * i + j] = (state[i] >> (24 - 8 * j)) as u8;
+        }
+    }
+    out
+}
+
+fn hmac_sha256(key: &[u8], msg: &[&[u8]]) -> [u8; 32] {
+    let mut k = [0u8; 64];
+    if key.len() > 64 {
+        for (d, s) in k.iter_mut().zip(sha256(&[key]).iter()) { *d = *s; }
+    }
+    else {
+        for (d, s) in k.iter_mut().zip(key.iter()) { *d = *s; }
+    }
+    let mut ipad = [0x36u8; 64];
+    let mut opad = [0x5cu8; 64];
+    for i in 0..64 {
+        ipad[i] ^= k[i];
+        opad[i] ^= k[i];
+    }
+
+    let mut parts: Vec<&[u8]> = vec![&ipad];
+    parts.push_all(msg);
+    let inner = sha256(&parts);
+    sha256(&[&opad, &inner])
+}
+
+/// Derives `out.len()` bytes from `passphrase` and `salt` with
+/// PBKDF2-HMAC-SHA256.
+pub fn pbkdf2_sha256(passphrase: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+    for (i, chunk) in out.chunks_mut(32).enumerate() {
+        let mut index = [0u8; 4];
+        for j in 0..4 {
+            index[j] = ((i as u32 + 1) >> (24 - 8 * j)) as u8;
+        }
+        let mut u = hmac_sha256(passphrase, &[salt, &index]);
+        let mut t = u;
+        for _ in 1..iterations {
+            u = hmac_sha256(passphrase, &[&u]);
+            for j in 0..32 {
+                t[j] ^= u[j];
+            }
+        }
+        for (d, s) in chunk.iter_mut().zip(t.iter()) {
+            *d = *s;
+        }
+    }
+}
+
+
+#[test]
+fn test_crypto() {
+    fn hex(s: &str) -> Vec<u8> {
+        let s: Vec<u8> = s.bytes().filter(|b| !(*b as char).is_whitespace()).collect();
+        s.chunks(2).map(|c| {
+            let d = |b: u8| if b >= b'a' {b - b'a' + 10} else {b - b'0'};
+            d(c[0]) << 4 | d(c[1])
+        }).collect()
+    }
+
+    // RFC 7539, 2.5.2
+    let key = hex("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b");
+    let tag = poly1305(&key, b"Cryptographic Forum Research Group");
+    assert!(tag[..] == hex("a8061dc1305136c6c22b8baf0c0127a9")[..]);
+
+    // RFC 7539, 2.8.2
+    let key = hex("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f");
+    let nonce = hex("070000004041424344454647");
+    let aad = hex("50515253c0c1c2c3c4c5c6c7");
+    let text = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+    let sealed = seal(&key, &nonce, &aad, text);
+    assert!(sealed[..16] == hex("d31a8d34648e60db7b86afbc53ef7ec2")[..]);
+    assert!(sealed[sealed.len() - 16..] == hex("1ae10b594f09e26a7e902ecbd0600691")[..]);
+    assert!(open(&key, &nonce, &aad, &sealed).unwrap() == text.to_vec());
+
+    // tampering, and the wrong key, nonce or aad
+    let mut bad = sealed.clone();
+    bad[3] ^= 1;
+    assert!(open(&key, &nonce, &aad, &bad).is_none());
+    let mut bad = sealed.clone();
+    let last = bad.len() - 1;
+    bad[last] ^= 0x80;
+    assert!(open(&key, &nonce, &aad, &bad).is_none());
+    let mut other = key.clone();
+    other[0] ^= 1;
+    assert!(open(&other, &nonce, &aad, &sealed).is_none());
+    assert!(open(&key, &hex("070000004041424344454648"), &aad, &sealed).is_none());
+    assert!(open(&key, &nonce, b"", &sealed).is_none());
+    assert!(open(&key, &nonce, &aad, &sealed[..10]).is_none());
+    assert!(open(&key, &nonce, &aad, &seal(&key, &nonce, &aad, b"")).unwrap().len() == 0);
+
+    // FIPS 180-2
+    assert!(sha256(&[b"abc"])[..] == hex("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")[..]);
+    assert!(sha256(&[b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"])[..] ==
+            hex("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1")[..]);
+    assert!(sha256(&[b"ab", b"", b"c"]) == sha256(&[b"abc"]));
+
+    // RFC 7914, 11
+    let mut dk = [0u8; 64];
+    pbkdf2_sha256(b"passwd", b"salt", 1, &mut dk);
+    assert!(dk[..] == hex("55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc
+                            49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783")[..]);
+    let mut dk = [0u8; 32];
+    pbkdf2_sha256(b"password", b"salt", 2, &mut dk);
+    assert!(dk[..] == hex("ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43")[..]);
+
+    assert!(random(16).unwrap().len() == 16);
+    assert!(random(16).unwrap() != random(16).unwrap());
+}
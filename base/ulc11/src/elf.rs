@@ -0,0 +1,721 @@
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::io::Seek;
+use std::io::Cursor;
+use std::path::Path;
+use ::ExecPath;
+use ::Embed;
+use ::target::Target;
+use ::target::Reader;
+use ::region::Region;
+use ::codec;
+use ::identity;
+use ::trailer;
+use ::trailer::Trailer;
+
+/* The module for the ELF-aware data embedder: ElfEmbed
+
+    +---------------+
+    |  ELF Header   |  e_shoff, e_shnum -----------------------+
+    |---------------|                                          |
+    |               |                                          |
+    |   Program     |                                          |
+    |   (segments,  |                                          |
+    |   sections)   |                                          |
+    |               |                                          |
+    |---------------|                                          |
+    |   .shstrtab   |  copy of the original, with the section  |
+    |               |  name appended (only if missing)         |
+    |---------------|                                          |
+    |  Embeded Blob |  SHT_PROGBITS, not allocated             |
+    |---------------|                                          |
+    | Section Table |  <---------------------------------------+
+    +---------------+
+
+    The blob lives in a named, non-allocated section; strip(1) and objcopy
+    keep sections they don't know about, and the data shows up in
+    `readelf -S`. Sections are always added at the end of the section table
+    so that no section indices (sh_link, sh_info, e_shstrndx) need fixing up
+    when storing.
+
+    Executables written before sections were used have the blob appended
+    instead, as `GenericEmbed` does. It is read if the section is missing,
+    and removed by the next store or strip.
+*/
+
+// -------------------------------------------------------------------------
+
+const ELF_MAGIC: [u8; 4] = [0x7f, 0x45, 0x4c, 0x46]; // \x7fELF
+
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ELFDATA2MSB: u8 = 2;
+
+const SHN_UNDEF: u64 = 0;
+const SHN_LORESERVE: u64 = 0xff00;
+
+const SHT_PROGBITS: u64 = 1;
+const SHT_RELA: u64 = 4;
+const SHT_NOBITS: u64 = 8;
+const SHT_REL: u64 = 9;
+const SHF_INFO_LINK: u64 = 0x40;
+const PT_NOTE: u64 = 4;
+const NT_GNU_BUILD_ID: u64 = 3;
+
+/// The name of the section used by `ElfEmbed::new`.
+pub const DEFAULT_SECTION: &'static str = ".ulc11";
+
+// -------------------------------------------------------------------------
+
+// Describes the width and byte order of the fields in an ELF file.
+#[derive(Clone, Copy)]
+struct Layout {
+    wide: bool, // ELF64
+    le: bool    // little endian
+}
+
+impl Layout {
+    fn new(ident: &[u8]) -> io::Result<Layout> {
+        if ident.len() < 6 || ident[0..4] != ELF_MAGIC[..] {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not an ELF file", None));
+        }
+        let wide = match ident[4] {
+            ELFCLASS32 => false,
+            ELFCLASS64 => true,
+            c => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown ELF class",
+                                            Some(format!("EI_CLASS is {}", c))))
+        };
+        let le = match ident[5] {
+            ELFDATA2LSB => true,
+            ELFDATA2MSB => false,
+            d => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown ELF byte order",
+                                            Some(format!("EI_DATA is {}", d))))
+        };
+        Ok(Layout {wide: wide, le: le})
+    }
+
+    // size of an address/offset field
+    fn word(&self) -> usize { if self.wide {8} else {4} }
+
+    fn get(&self, b: &[u8], at: usize, size: usize) -> u64 {
+        let mut n: u64 = 0;
+        for i in 0..size {
+            let byte = if self.le {b[at+size-1-i]} else {b[at+i]};
+            n = (n << 8) | byte as u64;
+        }
+        n
+    }
+
+    fn set(&self, b: &mut [u8], at: usize, size: usize, n: u64) {
+        for i in 0..size {
+            let byte = ((n >> (8*i)) & 0xff) as u8;
+            if self.le {b[at+i] = byte;} else {b[at+size-1-i] = byte;}
+        }
+    }
+
+    // ELF header fields
+    fn e_phoff(&self) -> usize { if self.wide {0x20} else {0x1c} }
+    fn e_shoff(&self) -> usize { if self.wide {0x28} else {0x20} }
+    fn e_ehsize(&self) -> usize { if self.wide {0x34} else {0x28} }
+    fn e_phentsize(&self) -> usize { self.e_ehsize() + 2 }
+    fn e_phnum(&self) -> usize { self.e_ehsize() + 4 }
+    fn e_shentsize(&self) -> usize { self.e_ehsize() + 6 }
+    fn e_shnum(&self) -> usize { self.e_ehsize() + 8 }
+    fn e_shstrndx(&self) -> usize { self.e_ehsize() + 10 }
+    fn ehdr_len(&self) -> usize { self.e_ehsize() + 12 }
+
+    // section header fields
+    fn sh_name(&self) -> usize { 0 }
+    fn sh_type(&self) -> usize { 4 }
+    fn sh_flags(&self) -> usize { 8 }
+    fn sh_offset(&self) -> usize { 8 + 2*self.word() }
+    fn sh_size(&self) -> usize { 8 + 3*self.word() }
+    fn sh_link(&self) -> usize { 8 + 4*self.word() }
+    fn sh_info(&self) -> usize { 12 + 4*self.word() }
+    fn sh_addralign(&self) -> usize { 16 + 4*self.word() }
+    fn shdr_len(&self) -> usize { 16 + 6*self.word() }
+
+    // program header fields
+    fn p_type(&self) -> usize { 0 }
+    fn p_offset(&self) -> usize { if self.wide {8} else {4} }
+    fn p_filesz(&self) -> usize { if self.wide {32} else {16} }
+}
+
+// The parts of an ELF file the embedder cares about.
+struct Table {
+    layout: Layout,
+    ehdr: Vec<u8>,
+    phdrs: Vec<Vec<u8>>,
+    shdrs: Vec<Vec<u8>>,
+    shstrtab: Vec<u8>
+}
+
+fn read_at<T: Read + Seek>(f: &mut T, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+    let mut b: Vec<u8> = vec![];
+    try!(f.seek(io::SeekFrom::Start(offset)));
+    try!(f.by_ref().take(length as u64).read_to_end(&mut b));
+    if b.len() != length {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Truncated ELF file",
+                    Some(format!("Expected {} bytes at offset {}, got {}", length, offset, b.len()))));
+    }
+    Ok(b)
+}
+
+// fails unless `length` bytes at `offset` lie within a `size` byte file
+fn check_range(offset: u64, length: u64, size: u64) -> io::Result<()> {
+    match offset.checked_add(length) {
+        Some(end) if end <= size => Ok(()),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Malformed ELF file",
+                    Some(format!("{} bytes at offset {} don't fit in {} bytes", length, offset, size))))
+    }
+}
+
+impl Table {
+    // Reads the ELF header, program and section header tables, and the
+    // section name string table. Nothing else is loaded. Every range the
+    // headers refer to is checked to lie within the file.
+    fn read<T: Read + Seek>(f: &mut T) -> io::Result<Table> {
+        let size = try!(f.seek(io::SeekFrom::End(0)));
+        let ident = try!(read_at(f, 0, 6));
+        let l = try!(Layout::new(&ident));
+        let ehdr = try!(read_at(f, 0, l.ehdr_len()));
+
+        let shoff = l.get(&ehdr, l.e_shoff(), l.word());
+        let shentsize = l.get(&ehdr, l.e_shentsize(), 2) as usize;
+        let shnum = l.get(&ehdr, l.e_shnum(), 2);
+        let shstrndx = l.get(&ehdr, l.e_shstrndx(), 2);
+        let phoff = l.get(&ehdr, l.e_phoff(), l.word());
+        let phentsize = l.get(&ehdr, l.e_phentsize(), 2) as usize;
+        let phnum = l.get(&ehdr, l.e_phnum(), 2);
+
+        if shoff == 0 || shnum == 0 || shstrndx == SHN_UNDEF || shstrndx >= SHN_LORESERVE {
+            // also covers extended section numbering, which we don't support
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unsupported ELF section table", None));
+        }
+        if shentsize < l.shdr_len() || shstrndx >= shnum {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Malformed ELF section table", None));
+        }
+
+        // both counts and sizes are 16 bits wide: the products can't overflow
+        try!(check_range(phoff, phnum*(phentsize as u64), size));
+        try!(check_range(shoff, shnum*(shentsize as u64), size));
+
+        let mut phdrs: Vec<Vec<u8>> = vec![];
+        for i in 0..phnum {
+            let p = try!(read_at(f, phoff + i*(phentsize as u64), phentsize));
+            try!(check_range(l.get(&p, l.p_offset(), l.word()), l.get(&p, l.p_filesz(), l.word()), size));
+            phdrs.push(p);
+        }
+        let mut shdrs: Vec<Vec<u8>> = vec![];
+        for i in 0..shnum {
+            let s = try!(read_at(f, shoff + i*(shentsize as u64), shentsize));
+            if l.get(&s, l.sh_type(), 4) != SHT_NOBITS {
+                try!(check_range(l.get(&s, l.sh_offset(), l.word()), l.get(&s, l.sh_size(), l.word()), size));
+            }
+            shdrs.push(s);
+        }
+
+        let shstrtab = {
+            let s = &shdrs[shstrndx as usize];
+            if l.get(s, l.sh_type(), 4) == SHT_NOBITS {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Malformed ELF section table",
+                                            Some("The section name table has no contents".to_string())));
+            }
+            try!(read_at(f, l.get(s, l.sh_offset(), l.word()), l.get(s, l.sh_size(), l.word()) as usize))
+        };
+
+        Ok(Table {layout: l, ehdr: ehdr, phdrs: phdrs, shdrs: shdrs, shstrtab: shstrtab})
+    }
+
+    fn shoff(&self) -> u64 { self.layout.get(&self.ehdr, self.layout.e_shoff(), self.layout.word()) }
+    fn shstrndx(&self) -> usize { self.layout.get(&self.ehdr, self.layout.e_shstrndx(), 2) as usize }
+    fn shentsize(&self) -> usize { self.layout.get(&self.ehdr, self.layout.e_shentsize(), 2) as usize }
+
+    // offset of `name` in the section name table
+    fn name_offset(&self, name: &str) -> Option<usize> {
+        let name = name.as_bytes();
+        let mut start = 0;
+        for (i, b) in self.shstrtab.iter().enumerate() {
+            if *b == 0 {
+                if &self.shstrtab[start..i] == name {
+                    return Some(start);
+                }
+                start = i+1;
+            }
+        }
+        None
+    }
+
+    // index of the section named `name` in the section table
+    fn find(&self, name: &str) -> Option<usize> {
+        let l = self.layout;
+        let noff = match self.name_offset(name) {
+            Some(o) => o,
+            None => return None
+        };
+        self.shdrs.iter().position(|s| l.get(s, l.sh_name(), 4) as usize == noff)
+    }
+
+    // (offset, length) of the contents of section `i`
+    fn section_range(&self, i: usize) -> (u64, u64) {
+        let l = self.layout;
+        let s = &self.shdrs[i];
+        (l.get(s, l.sh_offset(), l.word()), l.get(s, l.sh_size(), l.word()))
+    }
+
+    // The first byte after anything the ELF headers refer to, ignoring the
+    // section table itself. The ranges were checked by `read`, and sections
+    // added since lie within the image too.
+    fn content_end(&self) -> u64 {
+        let l = self.layout;
+        let mut end = l.ehdr_len() as u64;
+
+        let phoff = l.get(&self.ehdr, l.e_phoff(), l.word());
+        let phentsize = l.get(&self.ehdr, l.e_phentsize(), 2);
+        end = ::std::cmp::max(end, phoff + phentsize*(self.phdrs.len() as u64));
+        for p in self.phdrs.iter() {
+            end = ::std::cmp::max(end, l.get(p, l.p_offset(), l.word()) + l.get(p, l.p_filesz(), l.word()));
+        }
+        for s in self.shdrs.iter() {
+            if l.get(s, l.sh_type(), 4) != SHT_NOBITS {
+                end = ::std::cmp::max(end, l.get(s, l.sh_offset(), l.word()) + l.get(s, l.sh_size(), l.word()));
+            }
+        }
+        end
+    }
+
+    // Removes section `i`, renumbering any references to sections after it.
+    fn remove(&mut self, i: usize) {
+        let l = self.layout;
+        self.shdrs.remove(i);
+        for s in self.shdrs.iter_mut() {
+            let link = l.get(s, l.sh_link(), 4);
+            if link > i as u64 {
+                l.set(s, l.sh_link(), 4, link-1);
+            }
+            let kind = l.get(s, l.sh_type(), 4);
+            let info = l.get(s, l.sh_info(), 4);
+            let info_is_index = kind == SHT_REL || kind == SHT_RELA ||
+                                (l.get(s, l.sh_flags(), l.word()) & SHF_INFO_LINK) != 0;
+            if info_is_index && info > i as u64 {
+                l.set(s, l.sh_info(), 4, info-1);
+            }
+        }
+        let shstrndx = self.shstrndx() as u64;
+        if shstrndx > i as u64 {
+            l.set(&mut self.ehdr, l.e_shstrndx(), 2, shstrndx-1);
+        }
+    }
+
+    // Writes the headers back into `buf`, placing the section table at
+    // `shoff` (which may be the end of `buf`).
+    fn write_to(&mut self, buf: &mut Vec<u8>, shoff: u64) {
+        let l = self.layout;
+        let shnum = self.shdrs.len() as u64;
+        l.set(&mut self.ehdr, l.e_shoff(), l.word(), shoff);
+        l.set(&mut self.ehdr, l.e_shnum(), 2, shnum);
+
+        let mut table: Vec<u8> = vec![];
+        for s in self.shdrs.iter() {
+            table.push_all(s);
+        }
+
+        let shoff = shoff as usize;
+        while buf.len() < shoff + table.len() {
+            buf.push(0);
+        }
+        for (d, s) in buf[shoff..].iter_mut().zip(table.iter()) {
+            *d = *s;
+        }
+        for (d, s) in buf.iter_mut().zip(self.ehdr.iter()) {
+            *d = *s;
+        }
+    }
+}
+
+// true if the section table is the last thing in the file, which is how
+// linkers lay out executables (and how we leave them).
+fn table_at_end(t: &Table, buf: &[u8]) -> bool {
+    t.shoff() + (t.shdrs.len()*t.shentsize()) as u64 == buf.len() as u64
+}
+
+// pads `buf` to a multiple of `align`
+fn align(buf: &mut Vec<u8>, align: usize) {
+    while buf.len() % align != 0 {
+        buf.push(0);
+    }
+}
+
+// -------------------------------------------------------------------------
+
+// Removes section `name` from the ELF image in `buf`. Returns false if the
+// section wasn't found.
+fn strip_section(buf: &mut Vec<u8>, name: &str) -> io::Result<bool> {
+    let mut t = try!(Table::read(&mut Cursor::new(&buf[..])));
+    let i = match t.find(name) {
+        Some(i) => i,
+        None => return Ok(false)
+    };
+
+    if i == 0 || i == t.shstrndx() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Refusing to remove an essential section",
+                    Some(format!("Section '{}' is section #{}", name, i))));
+    }
+
+    let at_end = table_at_end(&t, buf);
+    t.remove(i);
+
+    if at_end {
+        // reclaim the section's contents along with anything else that became
+        // unreferenced (the old table, padding)
+        let end = t.content_end() as usize;
+        buf.truncate(end);
+        align(buf, t.layout.word());
+        let shoff = buf.len() as u64;
+        t.write_to(buf, shoff);
+    }
+    else {
+        // something is appended after the table; the shrunk table fits in place
+        let shoff = t.shoff();
+        t.write_to(buf, shoff);
+    }
+    Ok(true)
+}
+
+// Removes a blob appended to the ELF image in `buf` (see the top of the
+// file). Returns false if there was none, or if its trailer can't be read.
+fn strip_appended(buf: &mut Vec<u8>) -> bool {
+    match trailer::read_trailer(&mut Cursor::new(&buf[..])) {
+        Ok(Some(t)) => {
+            buf.truncate(t.start as usize);
+            true
+        },
+        _ => false
+    }
+}
+
+// Appends a section named `name` containing `data` to the ELF image in `buf`.
+// The section must not already exist.
+fn add_section(buf: &mut Vec<u8>, name: &str, data: &[u8]) -> io::Result<()> {
+    let mut t = try!(Table::read(&mut Cursor::new(&buf[..])));
+    let l = t.layout;
+
+    if table_at_end(&t, buf) {
+        let end = t.content_end() as usize;
+        buf.truncate(end);
+    }
+
+    // the section name
+    let name_offset = match t.name_offset(name) {
+        Some(o) => o,
+        None => {
+            let o = t.shstrtab.len();
+            t.shstrtab.push_all(name.as_bytes());
+            t.shstrtab.push(0);
+
+            let offset = buf.len() as u64;
+            let size = t.shstrtab.len() as u64;
+            buf.push_all(&t.shstrtab);
+            let shstrndx = t.shstrndx();
+            let s = &mut t.shdrs[shstrndx];
+            l.set(s, l.sh_offset(), l.word(), offset);
+            l.set(s, l.sh_size(), l.word(), size);
+            o
+        }
+    };
+
+    // the data
+    let offset = buf.len() as u64;
+    buf.push_all(data);
+
+    let mut s: Vec<u8> = vec![0; t.shentsize()];
+    l.set(&mut s, l.sh_name(), 4, name_offset as u64);
+    l.set(&mut s, l.sh_type(), 4, SHT_PROGBITS);
+    l.set(&mut s, l.sh_offset(), l.word(), offset);
+    l.set(&mut s, l.sh_size(), l.word(), data.len() as u64);
+    l.set(&mut s, l.sh_addralign(), l.word(), 1);
+    t.shdrs.push(s);
+
+    // the section table
+    align(buf, l.word());
+    let shoff = buf.len() as u64;
+    t.write_to(buf, shoff);
+    Ok(())
+}
+
+/// Returns true if the file at `path` starts with the ELF magic number.
+pub fn is_elf(path: &Path) -> io::Result<bool> {
+    let mut magic: Vec<u8> = vec![];
+    try!(try!(fs::File::open(path)).take(4).read_to_end(&mut magic));
+    Ok(has_magic(&magic))
+}
+
+/// Returns true if `head`, the start of an image, is the ELF magic number.
+pub fn has_magic(head: &[u8]) -> bool {
+    head.len() >= 4 && head[..4] == ELF_MAGIC[..]
+}
+
+// finds the GNU build ID among `notes`, the contents of a note section or
+// segment
+fn find_build_id(l: Layout, notes: &[u8]) -> Option<Vec<u8>> {
+    let mut i = 0;
+    while i + 12 <= notes.len() {
+        let namesz = l.get(notes, i, 4) as usize;
+        let descsz = l.get(notes, i + 4, 4) as usize;
+        if namesz > notes.len() || descsz > notes.len() {
+            return None;
+        }
+        let name = i + 12;
+        let desc = name + (namesz + 3) / 4 * 4;
+        if desc + descsz > notes.len() {
+            return None;
+        }
+        if l.get(notes, i + 8, 4) == NT_GNU_BUILD_ID && &notes[name..name + namesz] == b"GNU\0" {
+            return Some(notes[desc..desc + descsz].to_vec());
+        }
+        i = desc + (descsz + 3) / 4 * 4;
+    }
+    None
+}
+
+/// Returns the GNU build ID of the ELF executable in `f`, if it has one:
+/// the note in its `.note.gnu.build-id` section, or in its `PT_NOTE`
+/// segments.
+pub fn build_id<T: Read + Seek>(f: &mut T) -> io::Result<Option<Vec<u8>>> {
+    let t = try!(Table::read(f));
+    let l = t.layout;
+    let mut ranges: Vec<(u64, u64)> = vec![];
+    if let Some(i) = t.find(".note.gnu.build-id") {
+        ranges.push(t.section_range(i));
+    }
+    for p in t.phdrs.iter().filter(|p| l.get(p, l.p_type(), 4) == PT_NOTE) {
+        ranges.push((l.get(p, l.p_offset(), l.word()), l.get(p, l.p_filesz(), l.word())));
+    }
+    for &(offset, length) in ranges.iter() {
+        if let Some(id) = find_build_id(l, &try!(read_at(f, offset, length as usize))) {
+            return Ok(Some(id));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the SHA-256 digest of the segments of the ELF executable in `f`,
+/// which is what it runs. Embeding data only changes the section table
+/// fields of the ELF header, which are left out.
+pub fn segments_digest<T: Read + Seek>(f: &mut T) -> io::Result<[u8; 32]> {
+    let t = try!(Table::read(f));
+    let l = t.layout;
+    let mut contents: Vec<Vec<u8>> = vec![];
+    for p in t.phdrs.iter() {
+        let offset = l.get(p, l.p_offset(), l.word());
+        let mut c = try!(read_at(f, offset, l.get(p, l.p_filesz(), l.word()) as usize));
+        for &(at, size) in [(l.e_shoff(), l.word()), (l.e_shnum(), 2), (l.e_shstrndx(), 2)].iter() {
+            for i in (at as u64)..(at + size) as u64 {
+                if i >= offset && i - offset < c.len() as u64 {
+                    c[(i - offset) as usize] = 0;
+                }
+            }
+        }
+        contents.push(c);
+    }
+    let parts: Vec<&[u8]> = contents.iter().map(|c| &c[..]).collect();
+    Ok(::crypto::sha256(&parts))
+}
+
+// -------------------------------------------------------------------------
+
+/// `ElfEmbed` packs data into a dedicated section of ELF executables
+/// (ELF32 or ELF64, either byte order). Unlike `GenericEmbed`, the data
+/// survives `strip(1)` and `objcopy`.
+pub struct ElfEmbed {
+    target: Target,             // The executable
+    section: String,            // Name of the section holding the data
+    options: codec::Options     // How data is stored
+}
+impl ElfEmbed {
+    /// Allocates a new ElfEmbed object that stores data in the section
+    /// `DEFAULT_SECTION`. This function might fail if an IO error occures.
+    pub fn new(executable: ExecPath) -> io::Result<ElfEmbed> {
+        ElfEmbed::with_section(executable, DEFAULT_SECTION)
+    }
+
+    /// Like `new`, but stores data in the section named `section`, which
+    /// must be `DEFAULT_SECTION` or start with it and a `.` (e.g.
+    /// ".ulc11.config"): storing and stripping remove the section, so it
+    /// can't be one of the program's own.
+    pub fn with_section(executable: ExecPath, section: &str) -> io::Result<ElfEmbed> {
+        if section != DEFAULT_SECTION && !section.starts_with(&format!("{}.", DEFAULT_SECTION)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Section name isn't reserved for embeded data",
+                        Some(format!("'{}' doesn't start with '{}'", section, DEFAULT_SECTION))));
+        }
+        let target = try!(executable.target());
+        Result::Ok(ElfEmbed {target: target, section: section.to_string(), options: codec::Options::new()})
+    }
+}
+impl ElfEmbed {
+    // Finds the data in the executable: the section's contents followed by
+    // a trailer, or, for sections written before trailers were used, the
+    // bare contents. Without the section, a blob appended to the file is
+    // used. Positions in the returned trailer are relative to the start of
+    // the file.
+    fn locate(&self) -> io::Result<Option<(Reader, Trailer)>> {
+        let mut fd = try!(self.target.open());
+        let t = try!(Table::read(&mut fd));
+        let (offset, length) = match t.find(&self.section) {
+            Some(i) => t.section_range(i),
+            None => return Ok(try!(trailer::read_trailer(&mut fd)).map(|tr| (fd, tr)))
+        };
+
+        let mut r = Region::new(fd, offset, length);
+        let found = try!(trailer::read_trailer(&mut r));
+        let fd = r.into_reader();
+        match found {
+            Some(mut tr) => {
+                tr.start += offset;
+                Ok(Some((fd, tr)))
+            },
+            None => Ok(Some((fd, Trailer::unchecked(offset, length))))
+        }
+    }
+}
+impl Embed for ElfEmbed {
+    fn load(&self) -> io::Result<Vec<u8>> {
+        match try!(self.locate()) {
+            Some((mut fd, t)) => {
+                try!(identity::check(&t, &mut fd, t.start));
+                codec::decode(&mut fd, &t, &self.options)
+            },
+            None => Err(trailer::no_payload())
+        }
+    }
+
+    fn verify(&self) -> io::Result<()> {
+        match try!(self.locate()) {
+            Some((mut fd, t)) => {
+                try!(identity::check(&t, &mut fd, t.start));
+                trailer::verify(&mut fd, &t)
+            },
+            None => Err(trailer::no_payload())
+        }
+    }
+
+    fn info(&self) -> io::Result<Trailer> {
+        match try!(self.locate()) {
+            Some((_, t)) => Ok(t),
+            None => Err(trailer::no_payload())
+        }
+    }
+
+    fn strip(&mut self) -> io::Result<()> {
+        let _lock = try!(self.target.lock(true));
+        let mut buf = try!(self.target.read_all());
+
+        let appended = strip_appended(&mut buf);
+        if try!(strip_section(&mut buf, &self.section)) || appended {
+            try!(self.target.replace(|_, dst| dst.write_all(&buf)));
+        }
+        Ok(())
+    }
+
+    fn store_with(&mut self, data: &[u8], flags: u16) -> io::Result<()> {
+        let _lock = try!(self.target.lock(true));
+        let mut buf = try!(self.target.read_all());
+
+        strip_appended(&mut buf);
+        try!(strip_section(&mut buf, &self.section));
+        let id = if self.options.bind {Some(try!(identity::of(&mut Cursor::new(&buf[..]), buf.len() as u64)))} else {None};
+        try!(add_section(&mut buf, &self.section, &try!(codec::encode_for(data, flags, &self.options, id))));
+        self.target.replace(|_, dst| dst.write_all(&buf))
+    }
+
+    fn set_options(&mut self, options: codec::Options) {
+        self.options = options;
+    }
+
+    fn region(&self) -> io::Result<Option<(Region, Trailer)>> {
+        match try!(self.locate()) {
+            Some((mut fd, t)) => {
+                try!(identity::check(&t, &mut fd, t.start));
+                Ok(Some((Region::new(fd, t.start, t.length), t)))
+            },
+            None => Ok(None)
+        }
+    }
+}
+
+
+#[test]
+fn test_elf() {
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::error::Error;
+    use ::NO_PAYLOAD;
+    let path = "/tmp/test_elf_embed";
+    let _ = fs::remove_file(path);
+    fs::copy("/proc/self/exe", path).unwrap();
+    let original_len = fs::metadata(path).unwrap().len();
+
+    let mut p = ElfEmbed::new(ExecPath::File(PathBuf::from(path))).unwrap();
+    assert!(is_elf(Path::new(path)).unwrap());
+    assert!(p.load().unwrap_err().description() == NO_PAYLOAD);
+    assert!(p.store(&[1u8; 5]).is_ok());
+    assert!(p.load().unwrap() == [1u8; 5]);
+    assert!(p.store(&[12u8; 12]).is_ok());
+    assert!(p.load().unwrap() == [12u8; 12]);
+    assert!(p.info().unwrap().version == trailer::VERSION);
+
+    // survives strip(1), if available
+    if let Ok(o) = Command::new("strip").arg(path).output() {
+        if o.status.success() {
+            assert!(p.load().unwrap() == [12u8; 12]);
+        }
+    }
+
+    assert!(p.verify().is_ok());
+    assert!(p.strip().is_ok());
+    assert!(p.load().unwrap_err().description() == NO_PAYLOAD);
+    assert!(fs::metadata(path).unwrap().len() <= original_len + 16);
+
+    // headers are still sane
+    assert!(Table::read(&mut fs::File::open(path).unwrap()).is_ok());
+
+    // offsets and sizes that overflow or run past the end are refused
+    {
+        let mut image: Vec<u8> = vec![];
+        fs::File::open(path).unwrap().read_to_end(&mut image).unwrap();
+        let t = Table::read(&mut Cursor::new(&image[..])).unwrap();
+        let l = t.layout;
+        let malformed = |image: &[u8]| Table::read(&mut Cursor::new(image)).err().unwrap().kind() == io::ErrorKind::InvalidInput;
+        let mut bad = image.clone();
+        l.set(&mut bad, l.e_phoff(), l.word(), !0 - 8);
+        assert!(malformed(&bad));
+        let mut bad = image.clone();
+        l.set(&mut bad, l.e_shoff(), l.word(), image.len() as u64);
+        assert!(malformed(&bad));
+        let mut bad = image.clone();
+        let at = t.shoff() as usize + t.shstrndx()*t.shentsize() + l.sh_size();
+        l.set(&mut bad, at, l.word(), !0);
+        assert!(malformed(&bad));
+        let mut bad = image.clone();
+        let at = l.get(&t.ehdr, l.e_phoff(), l.word()) as usize + l.p_offset();
+        l.set(&mut bad, at, l.word(), !0 - 1);
+        assert!(malformed(&bad));
+    }
+
+    // sections of the program itself can't be used
+    assert!(ElfEmbed::with_section(ExecPath::File(PathBuf::from(path)), ".text").is_err());
+    assert!(ElfEmbed::with_section(ExecPath::File(PathBuf::from(path)), ".ulc11x").is_err());
+
+    let mut q = ElfEmbed::with_section(ExecPath::File(PathBuf::from(path)), ".ulc11.config").unwrap();
+    assert!(q.store(&[3u8; 3]).is_ok());
+    assert!(q.load().unwrap() == [3u8; 3]);
+    q.set_options(codec::Options::new().compress(1));
+    assert!(q.store(&[3u8; 3000]).is_ok());
+    assert!(q.info().unwrap().has(trailer::FLAG_COMPRESSED));
+    assert!(q.load().unwrap() == vec![3u8; 3000]);
+    assert!(p.load().is_err());
+    fs::remove_file(path).unwrap();
+}
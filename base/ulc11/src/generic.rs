@@ -1,13 +1,17 @@
-use std::fs;
 use std::io;
 use std::io::Read;
 use std::io::Write;
 use std::io::Seek;
-use std::path::PathBuf;
-use std::os::unix::OpenOptionsExt;
-use ulc91::unsigned;
+use std::io::SeekFrom;
 use ::ExecPath;
 use ::Embed;
+use ::rewrite;
+use ::target::Target;
+use ::region::Region;
+use ::trailer;
+use ::trailer::read_trailer;
+use ::codec;
+use ::identity;
 
 /* The module for the format-agnostic data embedder: GenericEmbed
 
@@ -38,160 +42,122 @@ macro_rules! debug_try {
 
 // -------------------------------------------------------------------------
 
-const MARKER: [u8;18] = [   0x63, 0x29, 0x7a, 0x8a, 0x3f, 0x73, 0x03, 0xf8, 
-                            0x2f, 0xfe, 0x9b, 0x65, 0x20, 0x08, 0x96, 0x8d, 
-                            0x0e, 0x40];
-                            
-macro_rules! magic_marker {
-     // 1/2^128, taken from the middle to avoid confusion with ^
-    () => (&MARKER[1..17])
-}
-
-const NEG_OFFSET_MAGIC: i64 = -16;
-const NEG_OFFSET_LEN: i64 = NEG_OFFSET_MAGIC-8;
-const NEG_OFFSET_ALL: i64 = NEG_OFFSET_LEN;
-
-// -------------------------------------------------------------------------
-
-// generates embeded blob as specified above
-fn gen_embed_blob(ar_blob: &[u8]) -> Vec<u8> {
-    let mut out: Vec<u8> = vec![];
-    out.push_all(ar_blob);
-    out.push_all(&unsigned::to_le_bytes(ar_blob.len() as u64)); // length as 8-byte LE int
-    out.push_all(magic_marker!());
-    out
-}
-
-// -------------------------------------------------------------------------
-
-// This function finds the negative offset (relative to the end of the 
-// file) of the embed blob and the blob's length. If a blob is not found,
-// None is returned.
-fn offset_and_length(e: &mut fs::File) -> io::Result<Option<(i64, u64)>> {
-    let mut b: Vec<u8> = vec![];
-    
-    // read the last 32 bytes of the executable to compare to the marker.
-    try!(e.seek(io::SeekFrom::End(NEG_OFFSET_MAGIC)));
-    try!(e.read_to_end(&mut b));
-    
-    // if no magic marker (see top of file) is present, assume no data 
-    // is embeded.
-    if b != magic_marker!() {
-        return Ok(Option::None);
+// Like `read_trailer`, but a trailer that can't be read (cut short, or of a
+// newer version) counts as nothing embeded: it doesn't say where the data
+// starts, and the executable must still be writable to recover it.
+fn readable_trailer<T: Read + Seek>(e: &mut T) -> io::Result<Option<trailer::Trailer>> {
+    match read_trailer(e) {
+        Err(ref err) if err.kind() == io::ErrorKind::InvalidInput => Ok(None),
+        r => r
     }
-    
-    // Read length of embeded data 
-    let mut dl: u64;
-    {
-        let mut l: [u8; 8] = [0u8; 8];
-        try!(e.seek(io::SeekFrom::End(NEG_OFFSET_LEN)));
-        if let Ok(count) = e.read(&mut l) {
-            if count != 8 {
-                return Result::Err(io::Error::new(io::ErrorKind::Other, "Bad len read", Option::None)); 
-            }
-        }
-        dl = unsigned::from_le_bytes(&l);
-    }
-    Ok(Some((NEG_OFFSET_ALL - (dl as i64), dl)))
 }
 
-// -------------------------------------------------------------------------
-
-
 /// `GenericEmbed` deals with packing data into generic executables. 
 /// Executables that make use of this embedder must be uneffected by 
 /// arbitrary data appended to the end of the executable's file.
 /// (e.g. elf binaries)
 pub struct GenericEmbed {
-    filename: PathBuf,  // Path to executable
+    target: Target,             // The executable
+    options: codec::Options     // How data is stored
 }
 impl GenericEmbed {
     /// Allocates a new GenericEmbed object. This function might fail if 
     /// an IO error occures.
     pub fn new(executable: ExecPath) -> io::Result<GenericEmbed> {
-        let filename = match executable {
-            ExecPath::File(p) => p,
-            ExecPath::This => fs::read_link("/proc/self/exe").unwrap()
-        };
-        Result::Ok(GenericEmbed {filename: filename})
+        let target = try!(executable.target());
+        Result::Ok(GenericEmbed {target: target, options: codec::Options::new()})
     }
 }
 impl Embed for GenericEmbed {
     fn load(&self) -> io::Result<Vec<u8>> {
-        let mut b: Vec<u8>;
-        let mut fd: fs::File;
-        let off_len: (i64, u64);
+        let mut fd;
         
-        fd = try!(fs::File::open(&self.filename));
+        fd = try!(self.target.open());
         
         // get data offset and length if available
-        match try!(offset_and_length(&mut fd)) {
-            Some(ol) => off_len = ol,
-            None => return Result::Ok(vec![])
-        }
+        let t = match try!(read_trailer(&mut fd)) {
+            Some(t) => t,
+            None => return Err(trailer::no_payload())
+        };
         
-        // read the data from the file and return
-        b = vec![];
-        try!(fd.seek(io::SeekFrom::End(off_len.0)));
-        try!(fd.read_to_end(&mut b));
-        b.truncate(off_len.1 as usize);
-        Ok(b)
+        // read the data from the file, check it, decode it and return
+        try!(identity::check(&t, &mut fd, t.start));
+        codec::decode(&mut fd, &t, &self.options)
     }
     
+    fn verify(&self) -> io::Result<()> {
+        let mut fd = try!(self.target.open());
+        match try!(read_trailer(&mut fd)) {
+            Some(t) => {
+                try!(identity::check(&t, &mut fd, t.start));
+                trailer::verify(&mut fd, &t)
+            },
+            None => Err(trailer::no_payload())
+        }
+    }
+    
+    fn info(&self) -> io::Result<trailer::Trailer> {
+        let mut fd = try!(self.target.open());
+        match try!(read_trailer(&mut fd)) {
+            Some(t) => Ok(t),
+            None => Err(trailer::no_payload())
+        }
+    }
     
     fn strip(&mut self) -> io::Result<()>{
-        let mut fd: fs::File;
+        let mut fd;
         
-        fd = try!(fs::File::open(&self.filename));        
+        // the blob found must still be there when the copy is made
+        let _lock = try!(self.target.lock(true));
+        fd = try!(self.target.open());
         
-        // Linux doesn't like the binary being modified while the process is 
-        // running (kernel doesn't load the entire elf into memory). As a work-
-        // around, I'll unlink the old one and write a new one.
-        // 
-        // TODO more effecient way than copying file into memory, stream plz 
-        // TODO failsafe way to do this: executable might delete itself without 
-        // saving new exec if error occurs while writing new copy
-    
-        let mut buf: Vec<u8> = vec![];
-        try!(fd.read_to_end(&mut buf));
+        // check if a blob is embeded in the exec; nothing to do otherwise
+        let keep = match try!(readable_trailer(&mut fd)) {
+            Some(t) => t.start,
+            None => return Ok(())
+        };
         drop(fd);
-        try!(fs::remove_file(&self.filename));
-        fd = try!(fs::OpenOptions::new()
-            .read(true).write(true).create(true).mode(0o700)
-            .open(&self.filename));
-        // copying old file to new
-        try!(fd.write_all(&buf));
-        
-        
-        // check if a blob is embeded in the exec, and remove by truncating
-        match try!(offset_and_length(&mut fd)) {
-            Some(ol) => try!(fd.set_len((try!(fd.metadata()).len() as i64 + ol.0) as u64)),
-            None => ()
-        }
         
-        Ok(())
+        // copy everything but the blob into a new executable
+        self.target.replace(|src, dst| rewrite::copy_range(src, dst, keep))
     }
     
-    fn store(&mut self, data: &[u8]) -> io::Result<()> {
-        let mut fd: fs::File;
+    fn store_with(&mut self, data: &[u8], flags: u16) -> io::Result<()> {
+        let mut fd;
+        
+        let _lock = try!(self.target.lock(true));
+        fd = try!(self.target.open());
         
-        // removes old data if preset
-        try!(self.strip());
+        // old data, if present, is left out of the copy
+        let keep = match try!(readable_trailer(&mut fd)) {
+            Some(t) => t.start,
+            None => try!(fd.seek(SeekFrom::End(0)))
+        };
+        let id = if self.options.bind {Some(try!(identity::of(&mut fd, keep)))} else {None};
+        drop(fd);
         
         // wraps data in an understood format 
-        let blob = gen_embed_blob(data);
+        let blob = try!(codec::encode_for(data, flags, &self.options, id));
         
-        // append the blob to the executable
-        fd = try!(fs::OpenOptions::new()
-                    .write(true)
-                    .append(true)
-                    .open(&self.filename));
-                    
-        if try!(fd.write(&blob)) != blob.len() {
-            Err(io::Error::new(io::ErrorKind::Other, "Bad blob write", Option::None))
-        }
-        else {
-            Ok(())
+        // append the blob to a copy of the executable
+        self.target.replace(|src, dst| {
+            try!(rewrite::copy_range(src, dst, keep));
+            dst.write_all(&blob)
+        })
+    }
+    
+    fn set_options(&mut self, options: codec::Options) {
+        self.options = options;
+    }
+    
+    fn region(&self) -> io::Result<Option<(Region, trailer::Trailer)>> {
+        let mut fd = try!(self.target.open());
+        match try!(read_trailer(&mut fd)) {
+            Some(t) => {
+                try!(identity::check(&t, &mut fd, t.start));
+                Ok(Some((Region::new(fd, t.start, t.length), t)))
+            },
+            None => Ok(None)
         }
     }
 }
@@ -199,11 +165,16 @@ impl Embed for GenericEmbed {
 
 #[test]
 fn test_generic() {
+    use std::error::Error;
+    use ::NO_PAYLOAD;
     let mut p = GenericEmbed::new(ExecPath::This).unwrap();
     
-    assert!(p.load().unwrap().len() == 0);
+    assert!(p.load().unwrap_err().description() == NO_PAYLOAD);
+    assert!(p.verify().unwrap_err().description() == NO_PAYLOAD);
     assert!(p.store(&[1u8; 5]).is_ok());
     assert!(p.load().unwrap() == [1u8; 5]);
+    assert!(p.verify().is_ok());
+    assert!(p.info().unwrap().length == 5);
     match p.store(&[12u8; 12]) {
         Err(e) => panic!("{}", e.detail().unwrap()),
         _ => ()
@@ -211,6 +182,50 @@ fn test_generic() {
     assert!(p.store(&[12u8; 12]).is_ok());
     assert!(p.load().unwrap().len() == 12);
     assert!(p.load().unwrap()[11] == 12u8);
+    assert!(p.store_with(&[7u8; 3], trailer::FLAG_ARCHIVE).is_ok());
+    assert!(p.info().unwrap().has(trailer::FLAG_ARCHIVE));
+    assert!(p.load().unwrap() == [7u8; 3]);
+    
+    p.set_options(codec::Options::new().compress(codec::DEFAULT_LEVEL));
+    assert!(p.store(&[42u8; 4096]).is_ok());
+    let t = p.info().unwrap();
+    assert!(t.has(trailer::FLAG_COMPRESSED) && t.length < 100 && t.original_length() == 4096);
+    assert!(p.load().unwrap() == vec![42u8; 4096]);
+    assert!(p.verify().is_ok());
+    
+    let key = codec::Key::Passphrase("hunter2".to_string());
+    p.set_options(codec::Options::new().encrypt(key));
+    assert!(p.store(b"secret").is_ok());
+    assert!(p.load().unwrap() == b"secret");
+    p.set_options(codec::Options::new());
+    assert!(p.load().unwrap_err().description() == ::AUTHENTICATION_FAILED);
+    assert!(p.verify().is_ok());
+    assert!(p.strip().is_ok());
+    assert!(p.load().unwrap_err().description() == NO_PAYLOAD);
     assert!(p.strip().is_ok());
-    assert!(p.load().unwrap().len() == 0);
+}
+
+#[test]
+fn test_damaged_trailer() {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::error::Error;
+    use ::TRUNCATED;
+    
+    // a trailer claiming more data than the file holds
+    let path = "/tmp/test_ulc11_damaged";
+    let _ = fs::remove_file(path);
+    let mut image: Vec<u8> = b"program".to_vec();
+    image.push_all(&trailer::gen_trailer(100, 0, 0, &[]));
+    fs::File::create(path).unwrap().write_all(&image).unwrap();
+    
+    let mut p = GenericEmbed::new(ExecPath::File(PathBuf::from(path))).unwrap();
+    assert!(p.load().unwrap_err().description() == TRUNCATED);
+    // but the executable can still be written over
+    p.store(b"fresh").unwrap();
+    assert!(p.load().unwrap() == b"fresh");
+    p.strip().unwrap();
+    assert!(p.load().unwrap_err().description() == TRUNCATED);
+    p.strip().unwrap();
+    fs::remove_file(path).unwrap();
 }
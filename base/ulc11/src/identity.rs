@@ -0,0 +1,163 @@
+//! Binding embeded data to the executable it is stored in.
+//!
+//! Data stored with `codec::Options::bind` records the identity of its
+//! executable in an `EXT_IDENTITY` trailer extension, and loading it out of
+//! any other executable fails with `IDENTITY_MISMATCH`. This catches a blob
+//! copied onto another build, such as a config left over from before an
+//! upgrade.
+//!
+//! The identity of an ELF executable is its GNU build ID, which linkers
+//! derive from the code. Without one, it is a SHA-256 digest of what the
+//! executable is made of besides the embeded data: the segments of an ELF
+//! executable, or, for anything else, all the bytes that aren't part of
+//! the blob.
+//!
+//! Readers that predate the extension skip it, and load the data anyway.
+
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use ::elf;
+use ::crypto;
+use ::trailer::Trailer;
+use ::trailer::EXT_IDENTITY;
+use ::IDENTITY_MISMATCH;
+
+// kinds of identity, the first byte of the record
+const BUILD_ID: u8 = 1;
+const DIGEST: u8 = 2;
+
+/// Returns the identity of the executable in `e`, as recorded in the
+/// `EXT_IDENTITY` extension. Only its first `end` bytes are its own, the
+/// rest being embeded data; ELF executables are identified by their build
+/// ID or segments regardless.
+pub fn of<T: Read + Seek>(e: &mut T, end: u64) -> io::Result<Vec<u8>> {
+    let mut head: Vec<u8> = vec![];
+    try!(e.seek(SeekFrom::Start(0)));
+    try!(e.by_ref().take(4).read_to_end(&mut head));
+    if elf::has_magic(&head) {
+        if let Some(id) = try!(elf::build_id(e)) {
+            return Ok([&[BUILD_ID][..], &id[..]].concat());
+        }
+        return Ok([&[DIGEST][..], &try!(elf::segments_digest(e))[..]].concat());
+    }
+
+    let mut own: Vec<u8> = vec![];
+    try!(e.seek(SeekFrom::Start(0)));
+    try!(e.by_ref().take(end).read_to_end(&mut own));
+    Ok([&[DIGEST][..], &crypto::sha256(&[&own])[..]].concat())
+}
+
+// a readable form of the identity `id`
+fn describe(id: &[u8]) -> String {
+    let hex: Vec<String> = id.iter().skip(1).map(|b| format!("{:02x}", b)).collect();
+    match id.first() {
+        Some(&BUILD_ID) => format!("build ID {}", hex.concat()),
+        Some(&DIGEST) => format!("digest {}", hex.concat()),
+        _ => format!("an unknown identity")
+    }
+}
+
+/// Checks that the data described by `t` belongs to the executable in `e`
+/// (see `of`), if it was bound to one. Fails with `IDENTITY_MISMATCH`
+/// otherwise.
+pub fn check<T: Read + Seek>(t: &Trailer, e: &mut T, end: u64) -> io::Result<()> {
+    let recorded = match t.extension(EXT_IDENTITY) {
+        Some(r) => r,
+        None => return Ok(())
+    };
+    let actual = try!(of(e, end));
+    if recorded == &actual[..] {
+        return Ok(());
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidInput, IDENTITY_MISMATCH,
+                        Some(format!("Stored for the executable with {}, found in the one with {}",
+                                        describe(recorded), describe(&actual)))))
+}
+
+
+#[test]
+fn test_identity() {
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::error::Error;
+    use ::ExecPath;
+    use ::Embed;
+    use ::codec;
+    use ::generic::GenericEmbed;
+    use ::script::ScriptEmbed;
+    use ::elf::ElfEmbed;
+
+    let read = |p: &str| {
+        let mut b: Vec<u8> = vec![];
+        fs::File::open(p).unwrap().read_to_end(&mut b).unwrap();
+        b
+    };
+    let bound = codec::Options::new().bind();
+
+    // generic: the blob is bound to the bytes before it
+    let (a, b) = ("/tmp/test_ulc11_identity_a", "/tmp/test_ulc11_identity_b");
+    fs::File::create(a).unwrap().write_all(b"program A").unwrap();
+    let mut p = GenericEmbed::new(ExecPath::File(PathBuf::from(a))).unwrap();
+    p.set_options(bound.clone());
+    p.store(b"config").unwrap();
+    p.store(b"config 2").unwrap();
+    assert!(p.load().unwrap() == b"config 2");
+    assert!(p.verify().is_ok());
+    assert!(p.region().unwrap().is_some());
+    assert!(p.info().unwrap().extension(EXT_IDENTITY).unwrap()[0] == DIGEST);
+    let blob = read(a)[9..].to_vec();
+    fs::File::create(b).unwrap().write_all(&[&b"program B"[..], &blob[..]].concat()).unwrap();
+    let q = GenericEmbed::new(ExecPath::File(PathBuf::from(b))).unwrap();
+    assert!(q.load().unwrap_err().description() == IDENTITY_MISMATCH);
+    assert!(q.verify().unwrap_err().description() == IDENTITY_MISMATCH);
+    assert!(q.region().is_err());
+    assert!(q.info().is_ok());
+
+    // unbound blobs can still be moved
+    p.set_options(codec::Options::new());
+    p.store(b"config 3").unwrap();
+    assert!(p.info().unwrap().extension(EXT_IDENTITY).is_none());
+    let blob = read(a)[9..].to_vec();
+    fs::File::create(b).unwrap().write_all(&[&b"program B"[..], &blob[..]].concat()).unwrap();
+    assert!(q.load().unwrap() == b"config 3");
+
+    // scripts: bound to the script around the block
+    fs::File::create(a).unwrap().write_all(b"#!/bin/sh\necho a").unwrap();
+    let mut p = ScriptEmbed::new(ExecPath::File(PathBuf::from(a))).unwrap();
+    p.set_options(bound.clone());
+    p.store(b"script config").unwrap();
+    assert!(p.load().unwrap() == b"script config");
+    let s = read(a);
+    let block = &s[s.windows(7).position(|w| w == b"# ulc11").unwrap()..];
+    assert!(block.len() > 0);
+    fs::File::create(b).unwrap().write_all(&[&b"#!/bin/sh\necho b\n"[..], block].concat()).unwrap();
+    let q = ScriptEmbed::new(ExecPath::File(PathBuf::from(b))).unwrap();
+    assert!(q.load().unwrap_err().description() == IDENTITY_MISMATCH);
+
+    // ELF: bound to the build ID, or to the segments
+    fs::copy("/proc/self/exe", a).unwrap();
+    let mut p = ElfEmbed::new(ExecPath::File(PathBuf::from(a))).unwrap();
+    p.set_options(bound.clone());
+    p.store(b"elf config").unwrap();
+    p.store(b"elf config 2").unwrap();
+    assert!(p.load().unwrap() == b"elf config 2");
+    let mut exe = read(a);
+    match elf::build_id(&mut fs::File::open(a).unwrap()).unwrap() {
+        Some(id) => {
+            assert!(p.info().unwrap().extension(EXT_IDENTITY).unwrap() == &[&[BUILD_ID][..], &id[..]].concat()[..]);
+            let at = exe.windows(id.len()).position(|w| w == &id[..]).unwrap();
+            exe[at] ^= 0xff;
+        },
+        None => exe[0x18] ^= 0xff // e_entry, in the first segment
+    }
+    fs::File::create(b).unwrap().write_all(&exe).unwrap();
+    let q = ElfEmbed::new(ExecPath::File(PathBuf::from(b))).unwrap();
+    assert!(q.load().unwrap_err().description() == IDENTITY_MISMATCH);
+    assert!(q.map().is_err());
+
+    fs::remove_file(a).unwrap();
+    fs::remove_file(b).unwrap();
+}
@@ -9,39 +9,151 @@
 #![feature(collections)]
 
 #![feature(core)]
+#![feature(libc)]
 
 extern crate ulc91;
 extern crate ulc21;
+extern crate libc;
 
 use std::path::PathBuf;
 use std::io;
+use std::fs;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::os::unix::io::RawFd;
 
 /// This enum specifies the path of the executable to operate on. It can be
 /// an explicit path, or `This` which denotes the current executing executable.
+///
+/// Executables that aren't files on disk can be given as an open file
+/// descriptor (`Fd`, readable, writable and seekable; it is left open), or
+/// as any `Read + Write + Seek` (`Image`, e.g. a `Cursor<Vec<u8>>`), which
+/// the caller keeps a reference to. These are rewritten in place, and
+/// aren't locked against other writers (see `target::Image`).
 pub enum ExecPath {
     File(PathBuf),
+    Fd(RawFd),
+    Image(Rc<RefCell<target::Image>>),
     This
 }
+impl ExecPath {
+    /// Returns the path of the executable file. Fails for executables that
+    /// aren't given by path.
+    pub fn resolve(self) -> io::Result<PathBuf> {
+        match self {
+            ExecPath::File(p) => Ok(p),
+            ExecPath::This => fs::read_link("/proc/self/exe"),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Executable is not given by path", None))
+        }
+    }
+
+    /// Returns the executable to operate on.
+    pub fn target(self) -> io::Result<target::Target> {
+        match self {
+            ExecPath::Fd(fd) => Ok(target::Target::fd(fd)),
+            ExecPath::Image(i) => Ok(target::Target::Image(i)),
+            p => p.resolve().map(target::Target::File)
+        }
+    }
+}
+/// Description of the error returned when no data is embeded.
+pub const NO_PAYLOAD: &'static str = "No data is embeded in the executable";
+/// Description of the error returned when the embeded data is cut short.
+pub const TRUNCATED: &'static str = "Embeded data is truncated";
+/// Description of the error returned when the embeded data doesn't match 
+/// its checksum.
+pub const CHECKSUM_MISMATCH: &'static str = "Embeded data is corrupted (checksum mismatch)";
+/// Description of the error returned when the embeded data was written in a
+/// newer format, or with features, than this library understands.
+pub const UNSUPPORTED_FORMAT: &'static str = "Embeded data is in an unsupported format";
+/// Description of the error returned when encrypted data can't be
+/// decrypted: the key is missing or wrong, or the data was tampered with.
+pub const AUTHENTICATION_FAILED: &'static str = "Embeded data failed authentication";
+/// Description of the error returned when another writer holds the lock on
+/// the executable and waiting wasn't asked for (see `lock`).
+pub const BUSY: &'static str = "Executable is being modified by another process";
+/// Description of the error returned when the embeded data was bound to
+/// another executable (see `codec::Options::bind`).
+pub const IDENTITY_MISMATCH: &'static str = "Embeded data belongs to another executable";
+
 /// Operations on an Executable file.
+///
+/// Errors specific to embeded data can be told apart by their description: 
+/// `NO_PAYLOAD`, `TRUNCATED`, `CHECKSUM_MISMATCH`, `UNSUPPORTED_FORMAT`,
+/// `AUTHENTICATION_FAILED` or `IDENTITY_MISMATCH`.
 pub trait Embed { //: io::Read + io::Write 
-    /// Reads a blob embeded in the executable. Always returns an array 
-    /// (empty if no data is embeded).
+    /// Reads a blob embeded in the executable, checking its integrity (and
+    /// that it belongs to the executable, if it was bound to it), and
+    /// decompressing and decrypting it if it was stored so.
     fn load(&self) -> io::Result<Vec<u8>>;
+    /// Checks the integrity of the embeded blob without loading it, and
+    /// that it belongs to the executable if it was bound to it.
+    fn verify(&self) -> io::Result<()>;
+    /// Reads the trailer of the embeded blob: its format version, flags
+    /// and extensions.
+    fn info(&self) -> io::Result<trailer::Trailer>;
     /// Strips the embeded blob from the executable if it exist.
     fn strip(&mut self) -> io::Result<()>;
     /// Embeds `data` into the executable. 
-    fn store(&mut self, data: &[u8]) -> io::Result<()>;
+    fn store(&mut self, data: &[u8]) -> io::Result<()> {
+        self.store_with(data, 0)
+    }
+    /// Embeds `data` into the executable, with the `trailer::FLAG_` bits
+    /// `flags` set in its trailer.
+    fn store_with(&mut self, data: &[u8], flags: u16) -> io::Result<()>;
+    /// Sets how data is stored from now on (e.g. compressed), and the key
+    /// encrypted data is loaded with.
+    fn set_options(&mut self, options: codec::Options);
+    /// Opens the embeded blob for reading in place, without loading it,
+    /// along with its trailer. Both come from the same executable, even if
+    /// it is replaced meanwhile. The blob is read as stored, compressed or
+    /// encrypted (see the trailer's flags). Returns `None` if no data is
+    /// embeded. Fails if the blob was bound to another executable.
+    fn region(&self) -> io::Result<Option<(region::Region, trailer::Trailer)>>;
+    /// Maps the embeded blob into memory, without copying it. Returns
+    /// `None` if no data is embeded. Compressed or encrypted blobs can't be
+    /// mapped, and the data isn't checked (see `verify`).
+    fn map(&self) -> io::Result<Option<region::Mapping>> {
+        match try!(self.region()) {
+            Some((r, t)) => {
+                if t.flags & (trailer::FLAG_COMPRESSED | trailer::FLAG_ENCRYPTED) != 0 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "Compressed or encrypted data can't be mapped", None));
+                }
+                r.map().map(Some)
+            }
+            None => Ok(None)
+        }
+    }
 }
 
 
-/// Operations on an Executable file.
-pub trait NewEmbed: io::Read + io::Write  {
+/// Streaming operations on an Executable file. Reading and seeking operate
+/// on the embeded blob, writing stages a new blob.
+pub trait NewEmbed: io::Read + io::Write + io::Seek {
+    /// Strips the embeded blob from the executable if it exist. Discards
+    /// uncommitted writes.
     fn strip(&mut self) -> io::Result<()>;
+    /// Replaces the embeded blob with everything written since the last 
+    /// commit. Does nothing if nothing was written.
+    fn commit(&mut self) -> io::Result<()>;
 }
 
 
 pub mod c;
+pub mod codec;
 pub mod auto;
 pub mod generic;
+pub mod new_generic;
+pub mod elf;
+pub mod script;
+pub mod region;
+pub mod lock;
+pub mod target;
+pub mod vfs;
+mod rewrite;
+pub mod trailer;
+mod lz;
+mod crypto;
+mod identity;
 
 
@@ -0,0 +1,185 @@
+//! Serialization of writers to the same executable.
+//!
+//! Executables are replaced through a rename (see rewrite.rs), so they
+//! can't be locked themselves: a lock on the old inode says nothing about
+//! the new one. Writers instead take an exclusive `flock` on a sibling lock
+//! file, `.<name>.ulc11.lock`, which is created on first use and left in
+//! place (removing it would let two writers lock different inodes).
+//!
+//! Readers don't need the lock: they always see either the old or the new
+//! executable as a whole. Read-modify-write sequences (load, then store)
+//! do, and can hold it across calls, since locks are reentrant within a
+//! thread. Different threads, like different processes, exclude each other.
+
+use std::fs;
+use std::io;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::path::PathBuf;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::OpenOptionsExt;
+use libc;
+use ::BUSY;
+
+extern {
+    fn flock(fd: libc::c_int, operation: libc::c_int) -> libc::c_int;
+}
+const LOCK_EX: libc::c_int = 2;
+const LOCK_NB: libc::c_int = 4;
+
+// identity of a lock file, however it was reached: device and inode
+type Key = (u64, u64);
+
+// lock files locked by this thread, with the number of `Lock`s held on each
+thread_local!(static HELD: RefCell<HashMap<Key, (usize, fs::File)>> = RefCell::new(HashMap::new()));
+
+/// Path of the lock file of the executable at `path`.
+pub fn lock_path(path: &Path) -> io::Result<PathBuf> {
+    match path.file_name().and_then(|n| n.to_str()) {
+        Some(n) => Ok(path.with_file_name(&format!(".{}.ulc11.lock", n))),
+        None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Executable path has no valid file name",
+                                    Some(format!("{}", path.display()))))
+    }
+}
+
+// opens (creating it if needed) the lock file at `path`. A lock file
+// created by another user may only be readable, which is enough to flock.
+fn open(path: &Path) -> io::Result<fs::File> {
+    match fs::OpenOptions::new().read(true).write(true).create(true).mode(0o644).open(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::PermissionDenied => fs::File::open(path),
+        r => r
+    }
+}
+
+/// An exclusive lock on an executable, released when dropped. A `Lock`
+/// belongs to the thread that took it.
+pub struct Lock {
+    key: Key,                       // the lock file
+    _thread: PhantomData<*const u8> // not Send: the count of held locks is per thread
+}
+impl Drop for Lock {
+    fn drop(&mut self) {
+        HELD.with(|h| {
+            let mut h = h.borrow_mut();
+            let last = match h.get_mut(&self.key) {
+                Some(&mut (ref mut count, _)) => {
+                    *count -= 1;
+                    *count == 0
+                },
+                None => false
+            };
+            if last {
+                h.remove(&self.key); // closing the file releases the flock
+            }
+        })
+    }
+}
+
+/// Locks the executable at `path` against other writers. If another
+/// thread or process holds the lock, waits for it if `wait` is set, and
+/// fails with `BUSY` (of kind `WouldBlock`) otherwise. Locking an
+/// executable this thread already locked succeeds at once.
+pub fn lock(path: &Path, wait: bool) -> io::Result<Lock> {
+    let lp = try!(lock_path(path));
+    let file = try!(open(&lp));
+
+    // the same executable can be reached through different paths: locks
+    // are told apart by the lock file they are on
+    let mut st: libc::stat = unsafe { ::std::mem::zeroed() };
+    if unsafe { libc::fstat(file.as_raw_fd(), &mut st) } < 0 {
+        let e = io::Error::last_os_error();
+        return Err(io::Error::new(e.kind(), "Could not lock executable", Some(format!("{}: {}", lp.display(), e))));
+    }
+    let key = (st.st_dev as u64, st.st_ino as u64);
+    let held = HELD.with(|h| match h.borrow_mut().get_mut(&key) {
+        Some(&mut (ref mut count, _)) => {
+            *count += 1;
+            true
+        },
+        None => false
+    });
+    if held {
+        // `file` is another open of the lock file: closing it keeps the flock
+        return Ok(Lock {key: key, _thread: PhantomData});
+    }
+
+    let operation = if wait {LOCK_EX} else {LOCK_EX | LOCK_NB};
+    while unsafe { flock(file.as_raw_fd(), operation) } < 0 {
+        let e = io::Error::last_os_error();
+        match e.raw_os_error() {
+            Some(libc::EINTR) => continue,
+            Some(libc::EWOULDBLOCK) => return Err(io::Error::new(io::ErrorKind::WouldBlock, BUSY,
+                                                                    Some(format!("{}", path.display())))),
+            _ => return Err(io::Error::new(e.kind(), "Could not lock executable",
+                                            Some(format!("{}: {}", lp.display(), e))))
+        }
+    }
+    HELD.with(|h| h.borrow_mut().insert(key, (1, file)));
+    Ok(Lock {key: key, _thread: PhantomData})
+}
+
+
+#[test]
+fn test_lock() {
+    use std::thread;
+    use std::error::Error;
+
+    let path = Path::new("/tmp/test_ulc11_lock");
+    let busy = |wait: bool| thread::spawn(move || match lock(Path::new("/tmp/test_ulc11_lock"), wait) {
+        Ok(_) => None,
+        Err(e) => Some((e.kind(), e.description().to_string()))
+    }).join().unwrap();
+
+    {
+        let l = lock(path, true).unwrap();
+        let again = lock(path, false).unwrap(); // reentrant
+        assert!(busy(false) == Some((io::ErrorKind::WouldBlock, BUSY.to_string())));
+        drop(again);
+        // through other paths to the same executable too
+        let dotted = lock(Path::new("/tmp/../tmp/./test_ulc11_lock"), false).unwrap();
+        let _ = fs::remove_file("/tmp/test_ulc11_lock_dir");
+        ::std::os::unix::fs::symlink("/tmp", "/tmp/test_ulc11_lock_dir").unwrap();
+        let linked = lock(Path::new("/tmp/test_ulc11_lock_dir/test_ulc11_lock"), true).unwrap();
+        fs::remove_file("/tmp/test_ulc11_lock_dir").unwrap();
+        drop(linked);
+        drop(dotted);
+        assert!(busy(false).is_some());
+        drop(l);
+    }
+    assert!(busy(false).is_none());
+    assert!(busy(true).is_none());
+    assert!(lock(Path::new("/"), true).is_err());
+    fs::remove_file(&lock_path(path).unwrap()).unwrap();
+}
+
+#[test]
+fn test_concurrent_stores() {
+    use std::thread;
+    use std::io::Write;
+    use ::ExecPath;
+    use ::Embed;
+    use ::generic::GenericEmbed;
+
+    let path = "/tmp/test_ulc11_concurrent";
+    let _ = fs::remove_file(path);
+    fs::File::create(path).unwrap().write_all(b"program").unwrap();
+
+    let threads: Vec<_> = (0..4).map(|_| thread::spawn(move || {
+        let mut e = GenericEmbed::new(ExecPath::File(PathBuf::from(path))).unwrap();
+        for _ in 0..25 {
+            let _l = lock(Path::new(path), true).unwrap();
+            let n = e.load().map(|d| d[0]).unwrap_or(0);
+            e.store(&[n + 1]).unwrap();
+        }
+    })).collect();
+    for t in threads.into_iter() {
+        t.join().unwrap();
+    }
+
+    let e = GenericEmbed::new(ExecPath::File(PathBuf::from(path))).unwrap();
+    assert!(e.load().unwrap() == [100]);
+    fs::remove_file(path).unwrap();
+    fs::remove_file(&lock_path(Path::new(path)).unwrap()).unwrap();
+}
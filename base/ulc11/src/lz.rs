@@ -0,0 +1,230 @@
+//! A small LZ77 codec, laid out like an LZ4 block.
+//!
+//! The compressed stream is a sequence of
+//!
+//! ```text
+//! [token: u8][literal length+][literals][offset: u16 LE][match length+]
+//! ```
+//!
+//! where the high nibble of the token is the literal length and the low
+//! nibble the match length minus `MIN_MATCH`. A nibble of 15 is extended by
+//! the following bytes, each added to it, until one is not 255. The last
+//! sequence has literals only, and ends the stream.
+
+use std::io;
+use std::cmp;
+
+const MIN_MATCH: usize = 4;
+const MAX_OFFSET: usize = 65535;
+const HASH_BITS: usize = 16;
+const CHAIN_MASK: usize = 65535;
+const NONE: usize = !0;
+
+/// The compression level used if none is specified.
+pub const DEFAULT_LEVEL: u32 = 6;
+/// The highest compression level.
+pub const MAX_LEVEL: u32 = 9;
+/// How many times larger than its compressed form data can be: each
+/// length byte past a token extends a match by at most 255 bytes.
+pub const MAX_RATIO: usize = 255;
+
+/// The most `compressed_len` bytes of compressed data can expand to.
+pub fn max_expansion(compressed_len: usize) -> usize {
+    compressed_len.saturating_mul(MAX_RATIO)
+}
+
+fn malformed(detail: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "Compressed data is malformed", Some(detail))
+}
+
+fn hash(b: &[u8]) -> usize {
+    let v = (b[0] as u64) | (b[1] as u64) << 8 | (b[2] as u64) << 16 | (b[3] as u64) << 24;
+    (((v * 2654435761) & 0xffffffff) >> (32 - HASH_BITS)) as usize
+}
+
+fn push_len(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 255 {
+        out.push(255);
+        n -= 255;
+    }
+    out.push(n as u8);
+}
+
+fn read_len(input: &[u8], i: &mut usize) -> io::Result<usize> {
+    let mut n = 0;
+    loop {
+        if *i >= input.len() {
+            return Err(malformed(format!("Length cut short at {}", *i)));
+        }
+        let b = input[*i];
+        *i += 1;
+        n += b as usize;
+        if b != 255 {
+            return Ok(n);
+        }
+    }
+}
+
+// appends a sequence: `literals` followed by an optional (offset, length) match
+fn emit(out: &mut Vec<u8>, literals: &[u8], m: Option<(usize, usize)>) {
+    let lit = literals.len();
+    let ml = match m {
+        Some((_, l)) => l - MIN_MATCH,
+        None => 0
+    };
+    out.push((cmp::min(lit, 15) << 4 | cmp::min(ml, 15)) as u8);
+    if lit >= 15 {
+        push_len(out, lit - 15);
+    }
+    out.push_all(literals);
+    if let Some((offset, _)) = m {
+        out.push(offset as u8);
+        out.push((offset >> 8) as u8);
+        if ml >= 15 {
+            push_len(out, ml - 15);
+        }
+    }
+}
+
+/// Compresses `data`. `level` ranges from 1 (fastest) to `MAX_LEVEL`
+/// (smallest), and bounds how many earlier occurences are tried for each
+/// match.
+pub fn compress(data: &[u8], level: u32) -> Vec<u8> {
+    let level = cmp::min(cmp::max(level, 1), MAX_LEVEL);
+    let attempts = 1usize << (level - 1) as usize;
+
+    let mut head: Vec<usize> = vec![NONE; 1 << HASH_BITS];  // latest position per hash
+    let mut prev: Vec<usize> = vec![NONE; CHAIN_MASK + 1];  // previous position with the same hash
+    let mut out: Vec<u8> = vec![];
+    let mut anchor = 0; // start of pending literals
+    let mut i = 0;
+
+    while i + MIN_MATCH <= data.len() {
+        // longest match among the latest `attempts` candidates
+        let mut best = (0, 0);
+        let mut cand = head[hash(&data[i..])];
+        let mut tries = attempts;
+        while cand != NONE && i - cand <= MAX_OFFSET && tries > 0 {
+            let mut l = 0;
+            while i + l < data.len() && data[cand + l] == data[i + l] {
+                l += 1;
+            }
+            if l > best.1 {
+                best = (i - cand, l);
+            }
+            cand = prev[cand & CHAIN_MASK];
+            tries -= 1;
+        }
+
+        let step = if best.1 >= MIN_MATCH {best.1} else {1};
+        for j in i..i + step {
+            if j + MIN_MATCH <= data.len() {
+                let h = hash(&data[j..]);
+                prev[j & CHAIN_MASK] = head[h];
+                head[h] = j;
+            }
+        }
+        if best.1 >= MIN_MATCH {
+            emit(&mut out, &data[anchor..i], Some(best));
+            anchor = i + step;
+        }
+        i += step;
+    }
+    emit(&mut out, &data[anchor..], None);
+    out
+}
+
+/// Decompresses `input`, which must expand to exactly `length` bytes.
+/// `length` is checked against what `input` can expand to before anything
+/// is allocated for it.
+pub fn decompress(input: &[u8], length: usize) -> io::Result<Vec<u8>> {
+    if length > max_expansion(input.len()) {
+        return Err(malformed(format!("{} bytes can't expand to {}", input.len(), length)));
+    }
+    let mut out: Vec<u8> = Vec::with_capacity(length);
+    let mut i = 0;
+    loop {
+        if i >= input.len() {
+            return Err(malformed(format!("Sequence missing at {}", i)));
+        }
+        let token = input[i];
+        i += 1;
+
+        let mut lit = (token >> 4) as usize;
+        if lit == 15 {
+            lit += try!(read_len(input, &mut i));
+        }
+        if lit > input.len() - i || out.len() + lit > length {
+            return Err(malformed(format!("{} literals at {} overrun the data", lit, i)));
+        }
+        out.push_all(&input[i..i + lit]);
+        i += lit;
+        if i == input.len() {
+            break;
+        }
+
+        if input.len() - i < 2 {
+            return Err(malformed(format!("Offset cut short at {}", i)));
+        }
+        let offset = input[i] as usize | (input[i + 1] as usize) << 8;
+        i += 2;
+        if offset == 0 || offset > out.len() {
+            return Err(malformed(format!("Offset {} points outside of the {} bytes decoded", offset, out.len())));
+        }
+        let mut ml = (token & 15) as usize;
+        if ml == 15 {
+            ml += try!(read_len(input, &mut i));
+        }
+        ml += MIN_MATCH;
+        if out.len() + ml > length {
+            return Err(malformed(format!("{} byte match at {} overruns the data", ml, i)));
+        }
+        // byte by byte, matches may overlap what they produce
+        let from = out.len() - offset;
+        for k in 0..ml {
+            let b = out[from + k];
+            out.push(b);
+        }
+    }
+
+    if out.len() != length {
+        return Err(malformed(format!("Expanded to {} bytes, expected {}", out.len(), length)));
+    }
+    Ok(out)
+}
+
+
+#[test]
+fn test_lz() {
+    let mut text = String::new();
+    for _ in 0..200 {
+        text.push_str("The quick brown fox jumps over the lazy dog. ");
+    }
+    let noise: Vec<u8> = (0..100000u32).map(|i| ((i * 7919) ^ (i >> 3)) as u8).collect();
+    let runs: Vec<u8> = (0..70000u32).map(|i| (i / 1000) as u8).collect();
+    let samples: Vec<&[u8]> = vec![&[], &[1], &[1, 2, 3, 4], text.as_bytes(), &noise, &runs, &[0u8; 300]];
+
+    for data in samples.iter() {
+        for level in 1..MAX_LEVEL + 1 {
+            let c = compress(data, level);
+            assert!(decompress(&c, data.len()).unwrap() == *data);
+        }
+    }
+    assert!(compress(text.as_bytes(), DEFAULT_LEVEL).len() < text.len() / 10);
+    assert!(compress(text.as_bytes(), MAX_LEVEL).len() <= compress(text.as_bytes(), 1).len());
+
+    // malformed input is refused, not trusted
+    let c = compress(text.as_bytes(), DEFAULT_LEVEL);
+    assert!(decompress(&c, text.len() - 1).is_err());
+    assert!(decompress(&c[..c.len() / 2], text.len()).is_err());
+    assert!(decompress(&[0x0f, 0x05, 0x00], 100).is_err()); // offset beyond the start
+    assert!(decompress(&[], 0).is_err());
+
+    // the expected length is bounded by the input, not trusted
+    assert!(decompress(&c, max_expansion(c.len()) + 1).is_err());
+    assert!(decompress(&c, !0).is_err());
+    let zeroes = vec![0u8; 1 << 20];
+    let c = compress(&zeroes, DEFAULT_LEVEL);
+    assert!(zeroes.len() <= max_expansion(c.len()));
+    assert!(decompress(&c, zeroes.len()).unwrap() == zeroes);
+}
@@ -3,214 +3,207 @@ use std::io;
 use std::io::Read;
 use std::io::Write;
 use std::io::Seek;
+use std::io::SeekFrom;
 use std::path::PathBuf;
-use std::os::unix::OpenOptionsExt;
-use ulc91::unsigned;
 use ::ExecPath;
 use ::NewEmbed;
+use ::rewrite;
+use ::lock;
+use ::trailer;
+use ::region::Region;
+use ulc91::utilities::Crc32;
 
-/* The module for the format-agnostic data embedder: GenericEmbed
-
-    +---------------+
-    |               |
-    |               |
-    |   Program     |
-    |               |
-    |               |
-    |---------------|           +---------------+       +----------------------+
-    |  Embeded Blob |    <-     |    AR Blob    |   <-  |  ulc91::archive blob |
-    +---------------+           |---------------|       +----------------------+
-                                |  ARB Length   |
-                                |---------------|
-                                |   MAGIC NO    |
-                                +---------------+
+/* The module for the streaming format-agnostic data embedder: GenericStream
 
-*/
+    Same on-disk format as GenericEmbed (see generic.rs), but nothing is ever
+    loaded into memory as a whole:
 
+    - reads and seeks go straight to the embeded region of the executable,
+    - writes go to a copy of the executable (without its old blob) that is
+      being built next to it, and which replaces it on commit.
 
-macro_rules! debug_try {
-     // 1/2^128, taken from the middle to avoid confusion with ^
-    ($e: expr) => (match $e {
-        Ok(r) => r,
-        Err(e) => panic!("))))))))))))))))))))))) error in {}:{}: {}", file!(), line!(), e.description())
-    })
-}
+*/
 
 // -------------------------------------------------------------------------
 
-const MARKER: [u8;18] = [   0x63, 0x29, 0x7a, 0x8a, 0x3f, 0x73, 0x03, 0xf8, 
-                            0x2f, 0xfe, 0x9b, 0x65, 0x20, 0x08, 0x96, 0x8d, 
-                            0x0e, 0x40];
-                            
-macro_rules! magic_marker {
-     // 1/2^128, taken from the middle to avoid confusion with ^
-    () => (&MARKER[1..17])
+/// `GenericStream` gives streaming access to the blob embeded by
+/// `GenericEmbed`. Memory use is constant regardless of the blob's size.
+///
+/// Read and Seek operate on the blob currently embeded in the executable
+/// (positions are relative to the start of the blob). Written data forms a
+/// new blob that replaces the embeded one once `commit` is called;
+/// uncommitted writes are discarded when the object goes out of scope.
+///
+/// Blobs are streamed as stored: written data is never compressed or
+/// encrypted, and a blob stored so by `GenericEmbed` reads as such.
+/// Only executables given by path (`ExecPath::File` or `This`) can be
+/// streamed.
+pub struct GenericStream {
+    filename: PathBuf,                          // Path to executable
+    embeded: bool,                              // Whether a blob was found
+    start: u64,                                 // Offset of the blob in the executable
+    region: Region,                             // The blob
+    staged: Option<(rewrite::Staged, u64, Crc32)> // New executable, and the length and checksum of the blob written to it
 }
+impl GenericStream {
+    /// Allocates a new GenericStream object. This function might fail if
+    /// an IO error occures.
+    pub fn new(executable: ExecPath) -> io::Result<GenericStream> {
+        let filename = try!(executable.resolve());
+        let (embeded, start, region) = try!(locate(&filename));
+        Ok(GenericStream {
+            filename: filename,
+            embeded: embeded,
+            start: start,
+            region: region,
+            staged: None
+        })
+    }
 
-const NEG_OFFSET_MAGIC: i64 = -16;
-const NEG_OFFSET_LEN: i64 = NEG_OFFSET_MAGIC-8;
-const NEG_OFFSET_ALL: i64 = NEG_OFFSET_LEN;
-
-// -------------------------------------------------------------------------
+    /// The length of the embeded blob.
+    pub fn len(&self) -> u64 {
+        self.region.len()
+    }
 
-// generates embeded blob as specified above
-fn gen_embed_blob(ar_blob: &[u8]) -> Vec<u8> {
-    let mut out: Vec<u8> = vec![];
-    out.push_all(ar_blob);
-    out.push_all(&unsigned::unsigned_to_le_bytes(ar_blob.len() as u64)); // length as 8-byte LE int
-    out.push_all(magic_marker!());
-    out
+    // reopens the executable after it has been replaced
+    fn reopen(&mut self) -> io::Result<()> {
+        let (embeded, start, region) = try!(locate(&self.filename));
+        self.embeded = embeded;
+        self.start = start;
+        self.region = region;
+        Ok(())
+    }
 }
 
-// -------------------------------------------------------------------------
+// finds the blob in the executable at `filename`. Returns whether one was
+// found, its offset, and the blob itself (empty if not found).
+fn locate(filename: &PathBuf) -> io::Result<(bool, u64, Region)> {
+    let mut fd = try!(fs::File::open(filename));
+    let len = try!(fd.metadata()).len();
+    match try!(trailer::read_trailer(&mut fd)) {
+        Some(t) => Ok((true, t.start, Region::new(fd, t.start, t.length))),
+        None => Ok((false, len, Region::new(fd, len, 0)))
+    }
+}
 
-// This function finds the negative offset (relative to the end of the 
-// file) of the embed blob and the blob's length. If a blob is not found,
-// None is returned.
-fn offset_and_length(e: &mut fs::File) -> io::Result<Option<(i64, u64)>> {
-    let mut b: Vec<u8> = vec![];
-    
-    // read the last 32 bytes of the executable to compare to the marker.
-    try!(e.seek(io::SeekFrom::End(NEG_OFFSET_MAGIC)));
-    try!(e.read_to_end(&mut b));
-    
-    // if no magic marker (see top of file) is present, assume no data 
-    // is embeded.
-    if b != magic_marker!() {
-        return Ok(Option::None);
+impl Read for GenericStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.region.read(buf)
     }
-    
-    // Read length of embeded data 
-    let mut dl: u64;
-    {
-        let mut l: [u8; 8] = [0u8; 8];
-        try!(e.seek(io::SeekFrom::End(NEG_OFFSET_LEN)));
-        if let Ok(count) = e.read(&mut l) {
-            if count != 8 {
-                return Result::Err(io::Error::new(io::ErrorKind::Other, "Bad len read", Option::None)); 
-            }
-        }
-        dl = unsigned::le_bytes_to_unsigned(&l);
+}
+
+impl Seek for GenericStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.region.seek(pos)
     }
-    Ok(Some((NEG_OFFSET_ALL - (dl as i64), dl)))
 }
 
-// -------------------------------------------------------------------------
+impl Write for GenericStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.staged.is_none() {
+            // first write since the last commit: start the new executable
+            // with everything that precedes the old blob. Staging locks the
+            // executable, so the blob is located again: another writer may
+            // have replaced it since it was opened.
+            let mut staged = try!(rewrite::Staged::new(&self.filename));
+            let (_, start, _) = try!(locate(&self.filename));
+            let mut fd = try!(fs::File::open(&self.filename));
+            try!(rewrite::copy_range(&mut fd, staged.file(), start));
+            self.staged = Some((staged, 0, Crc32::new()));
+        }
 
+        let &mut (ref mut staged, ref mut written, ref mut crc) = self.staged.as_mut().unwrap();
+        let n = try!(staged.file().write(buf));
+        *written += n as u64;
+        crc.update(&buf[..n]);
+        Ok(n)
+    }
 
-/// `GenericEmbed` deals with packing data into generic executables. 
-/// Executables that make use of this embedder must be uneffected by 
-/// arbitrary data appended to the end of the executable's file.
-/// (e.g. elf binaries)
-pub struct GenericEmbed {
-    filename: PathBuf,  // Path to executable
-}
-impl GenericEmbed {
-    /// Allocates a new GenericEmbed object. This function might fail if 
-    /// an IO error occures.
-    pub fn new(executable: ExecPath) -> io::Result<GenericEmbed> {
-        let filename = match executable {
-            ExecPath::File(p) => p,
-            ExecPath::This => fs::read_link("/proc/self/exe").unwrap()
-        };
-        Result::Ok(GenericEmbed {filename: filename})
+    fn flush(&mut self) -> io::Result<()> {
+        match self.staged {
+            Some((ref mut staged, _, _)) => staged.file().flush(),
+            None => Ok(())
+        }
     }
 }
-impl Embed for GenericEmbed {
-    fn load(&self) -> io::Result<Vec<u8>> {
-        let mut b: Vec<u8>;
-        let mut fd: fs::File;
-        let off_len: (i64, u64);
-        
-        fd = try!(fs::File::open(&self.filename));
-        
-        // get data offset and length if available
-        match try!(offset_and_length(&mut fd)) {
-            Some(ol) => off_len = ol,
-            None => return Result::Ok(vec![])
+
+impl NewEmbed for GenericStream {
+    fn strip(&mut self) -> io::Result<()> {
+        self.staged = None;
+        let _lock = try!(lock::lock(&self.filename, true));
+        try!(self.reopen());
+        if !self.embeded {
+            return Ok(());
         }
-        
-        // read the data from the file and return
-        b = vec![];
-        try!(fd.seek(io::SeekFrom::End(off_len.0)));
-        try!(fd.read_to_end(&mut b));
-        b.truncate(off_len.1 as usize);
-        Ok(b)
+
+        let keep = self.start;
+        try!(rewrite::replace(&self.filename, |src, dst| rewrite::copy_range(src, dst, keep)));
+        self.reopen()
     }
-    
-    
-    fn strip(&mut self) -> io::Result<()>{
-        let mut fd: fs::File;
-        
-        fd = try!(fs::File::open(&self.filename));        
-        
-        // Linux doesn't like the binary being modified while the process is 
-        // running (kernel doesn't load the entire elf into memory). As a work-
-        // around, I'll unlink the old one and write a new one.
-        // 
-        // TODO more effecient way than copying file into memory, stream plz 
-        // TODO failsafe way to do this: executable might delete itself without 
-        // saving new exec if error occurs while writing new copy
-    
-        let mut buf: Vec<u8> = vec![];
-        try!(fd.read_to_end(&mut buf));
-        drop(fd);
-        try!(fs::remove_file(&self.filename));
-        fd = try!(fs::OpenOptions::new()
-            .read(true).write(true).create(true).mode(0o700)
-            .open(&self.filename));
-        // copying old file to new
-        try!(fd.write_all(&buf));
-        
-        
-        // check if a blob is embeded in the exec, and remove by truncating
-        match try!(offset_and_length(&mut fd)) {
-            Some(ol) => try!(fd.set_len((try!(fd.metadata()).len() as i64 + ol.0) as u64)),
-            None => ()
+
+    fn commit(&mut self) -> io::Result<()> {
+        if let Some((mut staged, written, crc)) = self.staged.take() {
+            try!(staged.file().write_all(&trailer::gen_trailer(written, crc.finish(), 0, &[])));
+            try!(staged.commit());
+            try!(self.reopen());
         }
-        
         Ok(())
     }
-    
-    fn store(&mut self, data: &[u8]) -> io::Result<()> {
-        let mut fd: fs::File;
-        
-        // removes old data if preset
-        try!(self.strip());
-        
-        // wraps data in an understood format 
-        let blob = gen_embed_blob(data);
-        
-        // append the blob to the executable
-        fd = try!(fs::OpenOptions::new()
-                    .write(true)
-                    .append(true)
-                    .open(&self.filename));
-                    
-        if try!(fd.write(&blob)) != blob.len() {
-            Err(io::Error::new(io::ErrorKind::Other, "Bad blob write", Option::None))
-        }
-        else {
-            Ok(())
-        }
-    }
 }
 
 
 #[test]
-fn test_generic() {
-    let mut p = GenericEmbed::new(ExecPath::This).unwrap();
-    
-    assert!(p.load().unwrap().len() == 0);
-    assert!(p.store(&[1u8; 5]).is_ok());
-    assert!(p.load().unwrap() == [1u8; 5]);
-    match p.store(&[12u8; 12]) {
-        Err(e) => panic!("{}", e.detail().unwrap()),
-        _ => ()
+fn test_generic_stream() {
+    let path = "/tmp/test_generic_stream";
+    let _ = fs::remove_file(path);
+    fs::File::create(path).unwrap().write_all(&[0x7fu8; 1000]).unwrap();
+
+    let mut s = GenericStream::new(ExecPath::File(PathBuf::from(path))).unwrap();
+    assert!(s.len() == 0);
+
+    // stream in a few MBs, chunk by chunk
+    let chunk: Vec<u8> = (0..65536).map(|i| (i % 251) as u8).collect();
+    for _ in 0..48 {
+        s.write_all(&chunk).unwrap();
+    }
+    // not visible until committed
+    assert!(s.len() == 0);
+    s.commit().unwrap();
+    assert!(s.len() == 48*65536);
+
+    // random access
+    let mut buf = [0u8; 10];
+    s.seek(SeekFrom::Start(65536*3 + 250)).unwrap();
+    assert!(s.read(&mut buf).unwrap() == 10);
+    assert!(buf[0] == (250 % 251) as u8);
+    assert!(buf[1] == 0);
+    s.seek(SeekFrom::End(-1)).unwrap();
+    assert!(s.read(&mut buf).unwrap() == 1);
+    assert!(s.read(&mut buf).unwrap() == 0);
+    assert!(s.seek(SeekFrom::Current(-(48*65536 + 1))).is_err());
+
+    // replacing
+    s.write_all(&[1, 2, 3]).unwrap();
+    s.commit().unwrap();
+    let mut v: Vec<u8> = vec![];
+    s.read_to_end(&mut v).unwrap();
+    assert!(v == [1, 2, 3]);
+
+    // readable by GenericEmbed, and the program is intact
+    {
+        use ::Embed;
+        let g = ::generic::GenericEmbed::new(ExecPath::File(PathBuf::from(path))).unwrap();
+        assert!(g.load().unwrap() == [1, 2, 3]);
+        assert!(g.verify().is_ok());
+    }
+
+    // dropped without committing
+    {
+        let mut s = GenericStream::new(ExecPath::File(PathBuf::from(path))).unwrap();
+        s.write_all(&[9, 9]).unwrap();
     }
-    assert!(p.store(&[12u8; 12]).is_ok());
-    assert!(p.load().unwrap().len() == 12);
-    assert!(p.load().unwrap()[11] == 12u8);
-    assert!(p.strip().is_ok());
-    assert!(p.load().unwrap().len() == 0);
+    s.strip().unwrap();
+    assert!(s.len() == 0);
+    assert!(fs::metadata(path).unwrap().len() == 1000);
+    fs::remove_file(path).unwrap();
 }
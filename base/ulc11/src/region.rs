@@ -0,0 +1,178 @@
+//! Read-only access to the embeded blob of an executable, in place.
+
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::cmp;
+use std::ptr;
+use std::slice;
+use std::ops::Deref;
+use std::os::unix::io::AsRawFd;
+use libc;
+use ::target::Reader;
+
+
+/// A window into an executable covering its embeded blob. Positions are
+/// relative to the start of the blob, and reads stop at its end.
+///
+/// `Write` is implemented so that a `Region` can back a
+/// `ulc91::archive::file::ArchiveHandle`, but always fails: executables are
+/// only ever modified through an embedder.
+pub struct Region {
+    fd: Reader,     // The executable
+    start: u64,     // Offset of the blob in the executable
+    length: u64,    // Length of the blob
+    pos: u64        // Position within the blob
+}
+impl Region {
+    pub fn new<R: Into<Reader>>(fd: R, start: u64, length: u64) -> Region {
+        Region {fd: fd.into(), start: start, length: length, pos: 0}
+    }
+
+    /// The length of the blob.
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+    
+    /// The executable.
+    pub fn into_reader(self) -> Reader {
+        self.fd
+    }
+
+    /// Maps the blob into memory, read-only. Only blobs in executable
+    /// files can be mapped.
+    pub fn map(&self) -> io::Result<Mapping> {
+        if self.length == 0 {
+            return Ok(Mapping {ptr: ptr::null_mut(), size: 0, skip: 0, length: 0});
+        }
+        let fd = match self.fd {
+            Reader::File(ref f) => f.as_raw_fd(),
+            Reader::Image(..) => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                            "Only executable files can be mapped", None))
+        };
+
+        // mappings start at page boundaries
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+        let base = self.start - self.start % page;
+        let skip = (self.start - base) as usize;
+        let size = skip + self.length as usize;
+        let p = unsafe {
+            libc::mmap(ptr::null_mut(), size as libc::size_t, libc::PROT_READ, libc::MAP_PRIVATE,
+                        fd, base as libc::off_t)
+        };
+        if p == libc::MAP_FAILED {
+            let e = io::Error::last_os_error();
+            return Err(io::Error::new(e.kind(), "Could not map the embeded blob", Some(format!("{}", e))));
+        }
+        Ok(Mapping {ptr: p, size: size, skip: skip, length: self.length as usize})
+    }
+}
+
+impl Read for Region {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.length {
+            return Ok(0);
+        }
+        let max = cmp::min(buf.len() as u64, self.length - self.pos) as usize;
+        try!(self.fd.seek(SeekFrom::Start(self.start + self.pos)));
+        let n = try!(self.fd.read(&mut buf[..max]));
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Region {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new = match pos {
+            SeekFrom::Start(o) => o as i64,
+            SeekFrom::Current(o) => self.pos as i64 + o,
+            SeekFrom::End(o) => self.length as i64 + o
+        };
+        if new < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seeking before the start of the blob",
+                        Some(format!("Position {} requested", new))));
+        }
+        self.pos = new as u64;
+        Ok(self.pos)
+    }
+}
+
+impl Write for Region {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "Embeded blobs are read-only in place", None))
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+
+/// A read-only memory mapping of an embeded blob, made by `Region::map`.
+/// The blob is borrowed from the mapping (it derefs to `[u8]`), and is
+/// unmapped when the mapping is dropped.
+///
+/// Embedders replace executables rather than modify them in place, so the
+/// mapping keeps showing the blob as it was when mapped. Data isn't
+/// checked when mapped; see `Embed::verify`.
+pub struct Mapping {
+    ptr: *mut libc::c_void, // Start of the mapping, null if the blob is empty
+    size: usize,            // Length of the mapping
+    skip: usize,            // Offset of the blob in the mapping
+    length: usize           // Length of the blob
+}
+impl Mapping {
+    /// The length of the blob.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        if self.length == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts((self.ptr as *const u8).offset(self.skip as isize), self.length) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            unsafe { libc::munmap(self.ptr, self.size as libc::size_t) };
+        }
+    }
+}
+
+// the mapping is never written to
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+
+#[test]
+fn test_map() {
+    use std::fs;
+
+    let path = "/tmp/test_region_map";
+    let _ = fs::remove_file(path);
+    let image: Vec<u8> = (0..20000u32).map(|i| (i % 253) as u8).collect();
+    fs::File::create(path).unwrap().write_all(&image).unwrap();
+
+    // unaligned, spanning pages
+    let r = Region::new(fs::File::open(path).unwrap(), 4001, 9000);
+    let m = r.map().unwrap();
+    assert!(m.len() == 9000);
+    assert!(&m[..] == &image[4001..13001]);
+
+    // empty
+    let r = Region::new(fs::File::open(path).unwrap(), 100, 0);
+    assert!(r.map().unwrap().len() == 0);
+
+    // outlives the region and the file
+    drop(r);
+    fs::remove_file(path).unwrap();
+    assert!(m[0] == image[4001]);
+}
@@ -0,0 +1,279 @@
+//! Crash-safe replacement of executables.
+//!
+//! Linux doesn't like the binary being modified while the process is
+//! running (the kernel doesn't load the entire elf into memory), so new
+//! images are always written to a new inode: a temporary sibling of the
+//! executable is filled, synced, given the original's owner, mode and
+//! extended attributes, and then renamed over the original. At any point in
+//! time the path refers to either the complete old or the complete new
+//! executable.
+
+use std::fs;
+use std::io;
+use std::ffi::CString;
+use std::path::Path;
+use std::path::PathBuf;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::io::FromRawFd;
+use std::os::unix::ffi::OsStrExt;
+use libc;
+use ::lock;
+
+
+fn last_os_error(description: &'static str, path: &Path) -> io::Error {
+    let e = io::Error::last_os_error();
+    io::Error::new(e.kind(), description, Some(format!("{}: {}", path.display(), e)))
+}
+
+// Creates a new temporary sibling of `path`. Existing files are never
+// reused or removed: another stager may be writing them.
+fn create_temp(path: &Path) -> io::Result<(PathBuf, fs::File)> {
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(n) => n,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Executable path has no valid file name",
+                                            Some(format!("{}", path.display()))))
+    };
+    let pid = unsafe { libc::getpid() };
+    let mut n: u64 = 0;
+    loop {
+        let tmp = path.with_file_name(&format!(".{}.ulc11-{}-{}.tmp", name, pid, n));
+        let c = try!(CString::new(tmp.as_os_str().as_bytes()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput,
+                                            "Path contains a NUL byte", Some(format!("{}", tmp.display())))));
+        // O_EXCL, as OpenOptions can't ask for it
+        let fd = unsafe { libc::open(c.as_ptr(), libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC, 0o600) };
+        if fd >= 0 {
+            return Ok((tmp, unsafe { fs::File::from_raw_fd(fd) }));
+        }
+        if io::Error::last_os_error().raw_os_error() != Some(libc::EEXIST) {
+            return Err(last_os_error("Could not create temporary executable", &tmp));
+        }
+        n += 1;
+    }
+}
+
+// Copies the extended attributes of `src` onto `dst`. Filesystems without
+// xattr support are silently ignored.
+fn copy_xattrs(src: &fs::File, dst: &fs::File, path: &Path) -> io::Result<()> {
+    let sfd = src.as_raw_fd();
+    let dfd = dst.as_raw_fd();
+
+    let len = unsafe { libc::flistxattr(sfd, 0 as *mut libc::c_char, 0) };
+    if len < 0 {
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() == Some(libc::ENOTSUP) {
+            return Ok(());
+        }
+        return Err(last_os_error("Could not list extended attributes", path));
+    }
+    let mut names: Vec<u8> = vec![0; len as usize];
+    let len = unsafe { libc::flistxattr(sfd, names.as_mut_ptr() as *mut libc::c_char, names.len()) };
+    if len < 0 {
+        return Err(last_os_error("Could not list extended attributes", path));
+    }
+    names.truncate(len as usize);
+
+    for name in names.split(|b| *b == 0).filter(|n| n.len() > 0) {
+        let name = CString::new(name).unwrap(); // split on NULs, can't contain any
+        let vlen = unsafe { libc::fgetxattr(sfd, name.as_ptr(), 0 as *mut libc::c_void, 0) };
+        if vlen < 0 {
+            return Err(last_os_error("Could not read extended attribute", path));
+        }
+        let mut value: Vec<u8> = vec![0; vlen as usize];
+        let vlen = unsafe { libc::fgetxattr(sfd, name.as_ptr(), value.as_mut_ptr() as *mut libc::c_void, value.len()) };
+        if vlen < 0 {
+            return Err(last_os_error("Could not read extended attribute", path));
+        }
+        if unsafe { libc::fsetxattr(dfd, name.as_ptr(), value.as_ptr() as *const libc::c_void, vlen as usize, 0) } < 0 {
+            return Err(last_os_error("Could not copy extended attribute", path));
+        }
+    }
+    Ok(())
+}
+
+// Gives `dst` the owner, mode and xattrs of `src`. Ownership goes first:
+// chown clears the setuid/setgid bits and file capabilities. Writers that
+// may not give the file away keep it, like install(1) does, but then don't
+// get the setuid/setgid bits meant for the original owner.
+fn copy_attributes(src: &fs::File, dst: &fs::File, path: &Path) -> io::Result<()> {
+    let mut st: libc::stat = unsafe { ::std::mem::zeroed() };
+    if unsafe { libc::fstat(src.as_raw_fd(), &mut st) } < 0 {
+        return Err(last_os_error("Could not stat executable", path));
+    }
+    let mut mode = st.st_mode & 0o7777;
+    if unsafe { libc::fchown(dst.as_raw_fd(), st.st_uid, st.st_gid) } < 0 {
+        if io::Error::last_os_error().raw_os_error() != Some(libc::EPERM) {
+            return Err(last_os_error("Could not preserve executable owner", path));
+        }
+        mode &= !0o6000;
+    }
+    try!(copy_xattrs(src, dst, path));
+    if unsafe { libc::fchmod(dst.as_raw_fd(), mode) } < 0 {
+        return Err(last_os_error("Could not preserve executable mode", path));
+    }
+    Ok(())
+}
+
+/// A new executable being written next to the one at `path`. It replaces
+/// the original when committed, and is removed if dropped before that.
+/// The executable is locked against other writers meanwhile (see lock.rs).
+pub struct Staged {
+    path: PathBuf,          // the executable
+    tmp: PathBuf,           // its temporary sibling
+    file: Option<fs::File>, // open while staging
+    committed: bool,
+    _lock: lock::Lock       // held until dropped
+}
+impl Staged {
+    pub fn new(path: &Path) -> io::Result<Staged> {
+        let l = try!(lock::lock(path, true));
+        let (tmp, file) = try!(create_temp(path));
+        Ok(Staged {path: path.to_path_buf(), tmp: tmp, file: Some(file), committed: false, _lock: l})
+    }
+    
+    /// The new executable.
+    pub fn file(&mut self) -> &mut fs::File {
+        self.file.as_mut().unwrap() // only taken by commit, which consumes self
+    }
+    
+    /// Gives the new executable the owner, mode and xattrs of the original,
+    /// syncs it, and renames it over the original.
+    pub fn commit(self) -> io::Result<()> {
+        let path = self.path.clone();
+        self.commit_like(Some(&path))
+    }
+
+    /// Like `commit`, but gives the new executable the attributes of the
+    /// file at `model` instead, or leaves its own if `model` is `None`. The
+    /// original doesn't have to exist.
+    pub fn commit_like(mut self, model: Option<&Path>) -> io::Result<()> {
+        {
+            let dst = self.file();
+            if let Some(model) = model {
+                try!(copy_attributes(&try!(fs::File::open(model)), dst, model));
+            }
+            try!(dst.sync_all());
+        }
+        self.file = None;
+        try!(fs::rename(&self.tmp, &self.path));
+        self.committed = true;
+        
+        // make the rename itself durable
+        let dir = match self.path.parent() {
+            Some(d) if d != Path::new("") => d,
+            _ => Path::new(".")
+        };
+        try!(fs::File::open(dir)).sync_all()
+    }
+}
+impl Drop for Staged {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.file = None;
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// Replaces the file at `path` with the output of `fill`. `fill` gets the
+/// original file (positioned at its start) and the new, empty one.
+///
+/// If anything fails, the original file is left untouched. The file is
+/// locked against other writers while being replaced.
+pub fn replace<F>(path: &Path, fill: F) -> io::Result<()>
+    where F: FnOnce(&mut fs::File, &mut fs::File) -> io::Result<()>
+{
+    let mut staged = try!(Staged::new(path));
+    let mut src = try!(fs::File::open(path));
+    try!(fill(&mut src, staged.file()));
+    staged.commit()
+}
+
+/// Copies at most `length` bytes from `src` to `dst`, without buffering
+/// everything in memory.
+pub fn copy_range(src: &mut io::Read, mut dst: &mut io::Write, length: u64) -> io::Result<()> {
+    use std::io::Read;
+    let copied = try!(io::copy(&mut src.take(length), &mut dst));
+    if copied != length {
+        return Err(io::Error::new(io::ErrorKind::Other, "Executable shrunk while being copied",
+                    Some(format!("Copied {} of {} bytes", copied, length))));
+    }
+    Ok(())
+}
+
+
+#[test]
+fn test_replace() {
+    use std::io::Read;
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = Path::new("/tmp/test_ulc11_replace");
+    let _ = fs::remove_file(path);
+    let temps = || fs::read_dir("/tmp").unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .filter(|n| n.starts_with(".test_ulc11_replace.ulc11-"))
+        .count();
+    fs::File::create(path).unwrap().write_all(b"old").unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o751)).unwrap();
+
+    // failures leave the original (and nothing else) behind
+    let r = replace(path, |_, dst| {
+        try!(dst.write_all(b"half-written"));
+        Err(io::Error::new(io::ErrorKind::Other, "interrupted", None))
+    });
+    assert!(r.is_err());
+    let mut s = String::new();
+    fs::File::open(path).unwrap().read_to_string(&mut s).unwrap();
+    assert!(s == "old");
+    assert!(temps() == 0);
+
+    // success
+    replace(path, |src, dst| {
+        let len = try!(src.metadata()).len();
+        try!(copy_range(src, dst, len));
+        dst.write_all(b" new")
+    }).unwrap();
+    let mut s = String::new();
+    fs::File::open(path).unwrap().read_to_string(&mut s).unwrap();
+    assert!(s == "old new");
+    assert!(fs::metadata(path).unwrap().permissions().mode() & 0o7777 == 0o751);
+    assert!(temps() == 0);
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_concurrent_staging() {
+    use std::io::Read;
+    use std::io::Write;
+
+    let path = Path::new("/tmp/test_ulc11_staging");
+    fs::File::create(path).unwrap().write_all(b"old").unwrap();
+
+    // a file in the way of the first temporary name is left alone
+    let pid = unsafe { libc::getpid() };
+    let other = PathBuf::from(format!("/tmp/.test_ulc11_staging.ulc11-{}-0.tmp", pid));
+    fs::File::create(&other).unwrap().write_all(b"someone else's").unwrap();
+
+    // stagers of the same executable (the lock is reentrant) don't collide
+    let mut a = Staged::new(path).unwrap();
+    let mut b = Staged::new(path).unwrap();
+    assert!(a.tmp != other && b.tmp != other && a.tmp != b.tmp);
+    a.file().write_all(b"a").unwrap();
+    b.file().write_all(b"b").unwrap();
+    let b_tmp = b.tmp.clone();
+    drop(b);
+    assert!(fs::metadata(&b_tmp).is_err());
+    a.commit().unwrap();
+
+    let mut s = String::new();
+    fs::File::open(path).unwrap().read_to_string(&mut s).unwrap();
+    assert!(s == "a");
+    s.clear();
+    fs::File::open(&other).unwrap().read_to_string(&mut s).unwrap();
+    assert!(s == "someone else's");
+
+    fs::remove_file(&other).unwrap();
+    fs::remove_file(path).unwrap();
+    fs::remove_file(&lock::lock_path(path).unwrap()).unwrap();
+}
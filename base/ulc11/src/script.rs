@@ -0,0 +1,325 @@
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::io::Cursor;
+use std::path::Path;
+use ::ExecPath;
+use ::Embed;
+use ::codec;
+use ::identity;
+use ::target::Target;
+use ::trailer;
+use ::trailer::Trailer;
+use ::region::Region;
+use ulc91::utilities::base64_encode;
+use ulc91::utilities::base64_decode;
+
+/* The module for the script data embedder: ScriptEmbed
+
+    Interpreters read scripts to their end, so nothing can be appended to
+    them raw. Instead, the embeded blob (the same blob GenericEmbed appends,
+    trailer included) is base64 encoded into a block of comment lines:
+
+        #!/bin/sh
+        echo "the program"
+        # ulc11-payload-begin
+        # AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4v
+        # MDEyMzQ1Njc4OTo7PD0+P0BBQkNERUZHSElKS0xNTk9QUVJTVFVWV1hZWltcXV5f
+        # ulc11-payload-end
+
+    `#` starts a comment in sh, bash, python, perl and ruby alike, and no
+    line of the block can end a heredoc. The block is written at the end of
+    the script, and only found there: marker lines followed by anything
+    else (in a heredoc, or printed by an installer) belong to the script.
+    A script that doesn't end in a newline gets one before the block, and
+    the block then begins with `# ulc11-payload-begin-nl`, so that stripping
+    it gives back the script as it was.
+
+*/
+
+const BEGIN: &'static [u8] = b"# ulc11-payload-begin\n";
+const BEGIN_NL: &'static [u8] = b"# ulc11-payload-begin-nl\n";    // the newline before belongs to the block
+const END: &'static [u8] = b"# ulc11-payload-end\n";
+const LINE_WIDTH: usize = 64;
+
+// -------------------------------------------------------------------------
+
+/// Whether the file at `path` is a script (starts with `#!`).
+pub fn is_script(path: &Path) -> io::Result<bool> {
+    let mut magic: Vec<u8> = vec![];
+    try!(try!(fs::File::open(path)).take(2).read_to_end(&mut magic));
+    Ok(has_magic(&magic))
+}
+
+/// Whether `head`, the start of an image, is that of a script.
+pub fn has_magic(head: &[u8]) -> bool {
+    head.len() >= 2 && &head[..2] == b"#!"
+}
+
+// position of the last line of `script` that is `line` and ends at or
+// before `before`
+fn rfind_line(script: &[u8], line: &[u8], before: usize) -> Option<usize> {
+    let mut i = before;
+    while i >= line.len() {
+        let p = i - line.len();
+        if (p == 0 || script[p - 1] == b'\n') && &script[p..i] == line {
+            return Some(p);
+        }
+        i -= 1;
+    }
+    None
+}
+
+// whether `line` is one of the base64 lines `gen_block` writes
+fn encoded_line(line: &[u8]) -> bool {
+    line.len() > 2 && line.len() <= LINE_WIDTH + 2 && &line[..2] == b"# " &&
+        line[2..].iter().all(|c| match *c {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'+' | b'/' | b'=' => true,
+            _ => false
+        })
+}
+
+// finds the payload block at the end of `script`: where it starts (with
+// the newline that separates it, if it has one) and ends, and the blob it
+// holds. A block cut short still ends the script, but has no end marker.
+fn find_block(script: &[u8]) -> io::Result<Option<(usize, usize, Vec<u8>)>> {
+    let complete = rfind_line(script, END, script.len()) == Some(script.len().wrapping_sub(END.len()));
+    let end = if complete {script.len() - END.len()} else {script.len()};
+    let (begin, marker) = match (rfind_line(script, BEGIN, end), rfind_line(script, BEGIN_NL, end)) {
+        (Some(b), Some(n)) if b > n => (b, BEGIN),
+        (_, Some(n)) => (n, BEGIN_NL),
+        (Some(b), None) => (b, BEGIN),
+        (None, None) => return Ok(None)
+    };
+
+    let lines: Vec<&[u8]> = script[begin + marker.len()..end].split(|b| *b == b'\n').filter(|l| l.len() > 0).collect();
+    if !complete {
+        // a marker followed by comments that aren't base64 (e.g. in the
+        // script's documentation) belongs to the script; only lines as
+        // written by `gen_block` are taken for a block cut short
+        if lines.len() == 0 || !lines.iter().all(|l| encoded_line(l)) {
+            return Ok(None);
+        }
+        return Err(trailer::truncated(format!("Payload block at {} has no end", begin)));
+    }
+    let mut text = String::new();
+    for line in lines {
+        if !encoded_line(line) {
+            return Err(trailer::truncated(format!("Payload block at {} holds a foreign line", begin)));
+        }
+        text.push_str(&String::from_utf8_lossy(&line[2..]));
+    }
+    let begin = if marker == BEGIN_NL && begin > 0 {begin - 1} else {begin};
+    match base64_decode(&text) {
+        Some(blob) => Ok(Some((begin, script.len(), blob))),
+        None => Err(trailer::truncated(format!("Payload block at {} is not valid base64", begin)))
+    }
+}
+
+// the trailer of a blob found in a script
+fn blob_trailer(blob: &[u8]) -> io::Result<Trailer> {
+    match try!(trailer::read_trailer(&mut Cursor::new(blob))) {
+        Some(t) => Ok(t),
+        None => Err(trailer::truncated(format!("Payload block holds no trailer")))
+    }
+}
+
+// the payload block holding `blob`, to follow `script`
+fn gen_block(script: &[u8], blob: &[u8]) -> Vec<u8> {
+    let text = base64_encode(blob);
+    let mut out: Vec<u8> = vec![];
+    if script.len() > 0 && script[script.len() - 1] != b'\n' {
+        out.push(b'\n');
+        out.push_all(BEGIN_NL);
+    }
+    else {
+        out.push_all(BEGIN);
+    }
+    for line in text.as_bytes().chunks(LINE_WIDTH) {
+        out.push_all(b"# ");
+        out.push_all(line);
+        out.push(b'\n');
+    }
+    out.push_all(END);
+    out
+}
+
+// -------------------------------------------------------------------------
+
+/// `ScriptEmbed` deals with packing data into scripts, as a block of
+/// comment lines at their end. It works for any interpreter where `#`
+/// starts a comment.
+///
+/// The blob isn't stored raw, so `region` (and `map`) aren't supported.
+pub struct ScriptEmbed {
+    target: Target,             // The script
+    options: codec::Options     // How data is stored
+}
+impl ScriptEmbed {
+    /// Allocates a new ScriptEmbed object. This function might fail if
+    /// an IO error occures.
+    pub fn new(executable: ExecPath) -> io::Result<ScriptEmbed> {
+        let target = try!(executable.target());
+        Result::Ok(ScriptEmbed {target: target, options: codec::Options::new()})
+    }
+
+    // the embeded blob, and the script without it
+    fn blob(&self) -> io::Result<(Vec<u8>, Vec<u8>)> {
+        let script = try!(self.target.read_all());
+        match try!(find_block(&script)) {
+            Some((begin, end, blob)) => Ok((blob, [&script[..begin], &script[end..]].concat())),
+            None => Err(trailer::no_payload())
+        }
+    }
+}
+impl Embed for ScriptEmbed {
+    fn load(&self) -> io::Result<Vec<u8>> {
+        let (blob, rest) = try!(self.blob());
+        let t = try!(blob_trailer(&blob));
+        try!(identity::check(&t, &mut Cursor::new(&rest[..]), rest.len() as u64));
+        codec::decode(&mut Cursor::new(&blob[..]), &t, &self.options)
+    }
+
+    fn verify(&self) -> io::Result<()> {
+        let (blob, rest) = try!(self.blob());
+        let t = try!(blob_trailer(&blob));
+        try!(identity::check(&t, &mut Cursor::new(&rest[..]), rest.len() as u64));
+        trailer::verify(&mut Cursor::new(&blob[..]), &t)
+    }
+
+    fn info(&self) -> io::Result<Trailer> {
+        blob_trailer(&try!(self.blob()).0)
+    }
+
+    fn strip(&mut self) -> io::Result<()> {
+        let _lock = try!(self.target.lock(true));
+        let mut script = try!(self.target.read_all());
+        if let Some((begin, end, _)) = try!(find_block(&script)) {
+            script = [&script[..begin], &script[end..]].concat();
+            try!(self.target.replace(|_, dst| dst.write_all(&script)));
+        }
+        Ok(())
+    }
+
+    fn store_with(&mut self, data: &[u8], flags: u16) -> io::Result<()> {
+        let _lock = try!(self.target.lock(true));
+        let mut script = try!(self.target.read_all());
+        if let Some((begin, end, _)) = try!(find_block(&script)) {
+            script = [&script[..begin], &script[end..]].concat();
+        }
+        let id = if self.options.bind {Some(try!(identity::of(&mut Cursor::new(&script[..]), script.len() as u64)))} else {None};
+        let block = gen_block(&script, &try!(codec::encode_for(data, flags, &self.options, id)));
+        script.push_all(&block);
+        self.target.replace(|_, dst| dst.write_all(&script))
+    }
+
+    fn set_options(&mut self, options: codec::Options) {
+        self.options = options;
+    }
+
+    fn region(&self) -> io::Result<Option<(Region, Trailer)>> {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "Script payloads can't be read in place", None))
+    }
+}
+
+
+#[test]
+fn test_script() {
+    use std::path::PathBuf;
+    use std::error::Error;
+    use std::process::Command;
+    use std::os::unix::fs::PermissionsExt;
+    use ::NO_PAYLOAD;
+    use ::TRUNCATED;
+
+    let path = "/tmp/test_script_embed.sh";
+    let _ = fs::remove_file(path);
+    let program = b"#!/bin/sh\necho ran\ncat <<EOF\nheredoc\nEOF";
+    fs::File::create(path).unwrap().write_all(program).unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    assert!(is_script(Path::new(path)).unwrap());
+
+    let mut p = ScriptEmbed::new(ExecPath::File(PathBuf::from(path))).unwrap();
+    assert!(p.load().unwrap_err().description() == NO_PAYLOAD);
+    assert!(p.strip().is_ok());
+
+    let data: Vec<u8> = (0..1000).map(|i| (i % 256) as u8).collect();
+    assert!(p.store(&data).is_ok());
+    assert!(p.load().unwrap() == data);
+    assert!(p.verify().is_ok());
+    assert!(p.info().unwrap().length == 1000);
+    assert!(p.store(b"second").is_ok());
+    assert!(p.load().unwrap() == b"second");
+    assert!(p.region().is_err());
+
+    // the script still runs, and is still executable
+    if let Ok(o) = Command::new(path).output() {
+        assert!(o.status.success());
+        assert!(o.stdout == b"ran\nheredoc\n");
+    }
+    assert!(fs::metadata(path).unwrap().permissions().mode() & 0o777 == 0o755);
+
+    // a block followed by anything isn't the payload
+    fs::OpenOptions::new().append(true).open(path).unwrap().write_all(b"echo more\n").unwrap();
+    assert!(p.load().unwrap_err().description() == NO_PAYLOAD);
+    {
+        let mut s: Vec<u8> = vec![];
+        fs::File::open(path).unwrap().read_to_end(&mut s).unwrap();
+        fs::File::create(path).unwrap().write_all(&s[..s.len() - 10]).unwrap();
+    }
+    assert!(p.load().unwrap() == b"second");
+    assert!(p.store(b"third").is_ok());
+    assert!(p.load().unwrap() == b"third");
+
+    // damage
+    {
+        let mut s: Vec<u8> = vec![];
+        fs::File::open(path).unwrap().read_to_end(&mut s).unwrap();
+        let cut = rfind_line(&s, END, s.len()).unwrap();
+        fs::File::create(path).unwrap().write_all(&s[..cut]).unwrap();
+        assert!(p.load().unwrap_err().description() == TRUNCATED);
+        fs::File::create(path).unwrap().write_all(&s).unwrap();
+    }
+
+    assert!(p.strip().is_ok());
+    assert!(p.load().unwrap_err().description() == NO_PAYLOAD);
+    let mut s: Vec<u8> = vec![];
+    fs::File::open(path).unwrap().read_to_end(&mut s).unwrap();
+    assert!(&s[..] == &program[..]);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_script_markers() {
+    use std::path::PathBuf;
+    use std::error::Error;
+    use ::NO_PAYLOAD;
+
+    // scripts mentioning the markers themselves
+    let programs: [&[u8]; 5] = [
+        b"#!/bin/sh\ncat <<EOF\n# ulc11-payload-begin\n# AAAA\n# ulc11-payload-end\nEOF\necho done\n",
+        b"#!/bin/sh\n# ulc11-payload-begin\necho installing\n",
+        b"#!/bin/sh\necho '# ulc11-payload-end'\n# ulc11-payload-begin\n# ulc11-payload-end\necho done\n",
+        b"#!/bin/sh\necho done\n# The payload follows:\n# ulc11-payload-begin\n# (base64 lines)\n#\n",
+        b"#!/bin/sh\necho done\n# ulc11-payload-begin-nl\n# see above",
+    ];
+    let path = "/tmp/test_script_markers.sh";
+    for program in programs.iter() {
+        let _ = fs::remove_file(path);
+        fs::File::create(path).unwrap().write_all(program).unwrap();
+        let mut p = ScriptEmbed::new(ExecPath::File(PathBuf::from(path))).unwrap();
+        assert!(p.load().unwrap_err().description() == NO_PAYLOAD);
+        p.strip().unwrap();
+
+        p.store(b"payload").unwrap();
+        assert!(p.load().unwrap() == b"payload");
+        p.store(b"again").unwrap();
+        assert!(p.load().unwrap() == b"again");
+        p.strip().unwrap();
+        let mut s: Vec<u8> = vec![];
+        fs::File::open(path).unwrap().read_to_end(&mut s).unwrap();
+        assert!(&s[..] == *program);
+    }
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,303 @@
+//! Where embedders find the executable they operate on: a file, or an
+//! image held elsewhere (see `ExecPath`).
+//!
+//! Files are replaced through a rename, under a lock (see rewrite.rs and
+//! lock.rs). Other images are rewritten in place, and aren't locked:
+//! sharing them between writers is up to their owner.
+
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Cursor;
+use std::mem;
+use std::any::Any;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::os::unix::io::RawFd;
+use std::os::unix::io::FromRawFd;
+use ::ExecPath;
+use ::rewrite;
+use ::lock;
+
+/// An executable image that embedders can read and rewrite in place: any
+/// `Read + Write + Seek`, such as a `Cursor<Vec<u8>>` or an open `File`.
+///
+/// Only `Cursor<Vec<u8>>`s and files can be truncated. Other images only
+/// take rewrites that leave them at least as long as they were; anything
+/// that would shrink them (stripping, storing less data) fails with
+/// `InvalidInput` and leaves them untouched.
+pub trait Image: Read + Write + Seek {
+    /// The image itself, to tell the images that can be truncated.
+    fn as_any(&mut self) -> &mut Any;
+}
+impl<T: Read + Write + Seek + Any> Image for T {
+    fn as_any(&mut self) -> &mut Any {
+        self
+    }
+}
+
+// whether `i` can be truncated
+fn truncatable(i: &mut Image) -> bool {
+    let a = i.as_any();
+    a.is::<Cursor<Vec<u8>>>() || a.is::<fs::File>() || a.is::<FdImage>()
+}
+
+// truncates `i`, which must be `truncatable`, to `length` bytes
+fn truncate(i: &mut Image, length: u64) -> io::Result<()> {
+    let a = i.as_any();
+    if let Some(c) = a.downcast_mut::<Cursor<Vec<u8>>>() {
+        c.get_mut().truncate(length as usize);
+        return Ok(());
+    }
+    if let Some(f) = a.downcast_mut::<fs::File>() {
+        return f.set_len(length);
+    }
+    match a.downcast_mut::<FdImage>() {
+        Some(f) => f.file.as_mut().unwrap().set_len(length),
+        None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Image can't be truncated", None))
+    }
+}
+
+// an open file descriptor that belongs to the caller, and so isn't closed
+struct FdImage {
+    file: Option<fs::File>  // only taken on drop
+}
+impl Read for FdImage {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {self.file.as_mut().unwrap().read(buf)}
+}
+impl Write for FdImage {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {self.file.as_mut().unwrap().write(buf)}
+    fn flush(&mut self) -> io::Result<()> {self.file.as_mut().unwrap().flush()}
+}
+impl Seek for FdImage {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {self.file.as_mut().unwrap().seek(pos)}
+}
+impl Drop for FdImage {
+    fn drop(&mut self) {
+        mem::forget(self.file.take());
+    }
+}
+
+/// The executable an embedder operates on.
+pub enum Target {
+    File(PathBuf),
+    Image(Rc<RefCell<Image>>)
+}
+impl Target {
+    /// A target for the open file descriptor `fd`, which must be readable,
+    /// writable and seekable. It is left open.
+    pub fn fd(fd: RawFd) -> Target {
+        let file = unsafe { fs::File::from_raw_fd(fd) };
+        Target::Image(Rc::new(RefCell::new(FdImage {file: Some(file)})))
+    }
+
+    /// An `ExecPath` for this same executable.
+    pub fn exec_path(&self) -> ExecPath {
+        match *self {
+            Target::File(ref p) => ExecPath::File(p.clone()),
+            Target::Image(ref i) => ExecPath::Image(i.clone())
+        }
+    }
+
+    /// Opens the executable for reading.
+    pub fn open(&self) -> io::Result<Reader> {
+        match *self {
+            Target::File(ref p) => Ok(Reader::File(try!(fs::File::open(p)))),
+            Target::Image(ref i) => Ok(Reader::Image(i.clone(), 0))
+        }
+    }
+
+    /// Reads the whole executable.
+    pub fn read_all(&self) -> io::Result<Vec<u8>> {
+        let mut buf: Vec<u8> = vec![];
+        try!(try!(self.open()).read_to_end(&mut buf));
+        Ok(buf)
+    }
+
+    /// Reads the first `length` bytes of the executable, or all of it if
+    /// it is shorter.
+    pub fn head(&self, length: u64) -> io::Result<Vec<u8>> {
+        let mut buf: Vec<u8> = vec![];
+        try!(try!(self.open()).take(length).read_to_end(&mut buf));
+        Ok(buf)
+    }
+
+    /// Locks the executable against other writers (see lock.rs). Only
+    /// files are locked; `None` is returned for other images.
+    pub fn lock(&self, wait: bool) -> io::Result<Option<lock::Lock>> {
+        match *self {
+            Target::File(ref p) => lock::lock(p, wait).map(Some),
+            Target::Image(_) => Ok(None)
+        }
+    }
+
+    /// Replaces the executable with the output of `fill`, which gets the
+    /// original executable (positioned at its start) and the new, empty
+    /// one. Files are replaced as a whole (see `rewrite::replace`); other
+    /// images are overwritten once `fill` succeeds.
+    pub fn replace<F>(&self, fill: F) -> io::Result<()>
+        where F: FnOnce(&mut Read, &mut Write) -> io::Result<()>
+    {
+        match *self {
+            Target::File(ref p) => rewrite::replace(p, |src, dst| fill(src, dst)),
+            Target::Image(ref i) => {
+                let mut out: Vec<u8> = vec![];
+                try!(fill(&mut Reader::Image(i.clone(), 0), &mut out));
+                let mut i = i.borrow_mut();
+                let length = try!(i.seek(SeekFrom::End(0)));
+                let shrinks = (out.len() as u64) < length;
+                if shrinks && !truncatable(&mut *i) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image can't be truncated",
+                                Some(format!("Rewrite needs it shrunk from {} to {} bytes", length, out.len()))));
+                }
+                try!(i.seek(SeekFrom::Start(0)));
+                try!(i.write_all(&out));
+                if shrinks {
+                    try!(truncate(&mut *i, out.len() as u64));
+                }
+                i.flush()
+            }
+        }
+    }
+}
+
+/// A reader of the executable, with a position of its own.
+pub enum Reader {
+    File(fs::File),
+    Image(Rc<RefCell<Image>>, u64)
+}
+impl From<fs::File> for Reader {
+    fn from(f: fs::File) -> Reader {
+        Reader::File(f)
+    }
+}
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Reader::File(ref mut f) => f.read(buf),
+            Reader::Image(ref i, ref mut pos) => {
+                let mut i = i.borrow_mut();
+                try!(i.seek(SeekFrom::Start(*pos)));
+                let n = try!(i.read(buf));
+                *pos += n as u64;
+                Ok(n)
+            }
+        }
+    }
+}
+impl Seek for Reader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match *self {
+            Reader::File(ref mut f) => f.seek(pos),
+            Reader::Image(ref i, ref mut p) => {
+                let new = match pos {
+                    SeekFrom::Start(o) => o as i64,
+                    SeekFrom::Current(o) => *p as i64 + o,
+                    SeekFrom::End(o) => try!(i.borrow_mut().seek(SeekFrom::End(0))) as i64 + o
+                };
+                if new < 0 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seeking before the start of the image",
+                                Some(format!("Position {} requested", new))));
+                }
+                *p = new as u64;
+                Ok(*p)
+            }
+        }
+    }
+}
+
+
+// a stream that isn't one of the images known to be truncatable
+#[cfg(test)]
+struct Growing(Cursor<Vec<u8>>);
+#[cfg(test)]
+impl Read for Growing {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {self.0.read(buf)}
+}
+#[cfg(test)]
+impl Write for Growing {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {self.0.write(buf)}
+    fn flush(&mut self) -> io::Result<()> {self.0.flush()}
+}
+#[cfg(test)]
+impl Seek for Growing {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {self.0.seek(pos)}
+}
+
+#[test]
+fn test_targets() {
+    use std::path::Path;
+    use std::os::unix::io::AsRawFd;
+    use ::Embed;
+    use ::auto::AutoEmbed;
+    use ::elf;
+
+    // an ELF image in memory
+    let mut exe: Vec<u8> = vec![];
+    fs::File::open("/proc/self/exe").unwrap().read_to_end(&mut exe).unwrap();
+    let image = Rc::new(RefCell::new(Cursor::new(exe.clone())));
+    {
+        let mut p = AutoEmbed::new(ExecPath::Image(image.clone())).unwrap();
+        p.insert("config", b"in memory").unwrap();
+        assert!(p.get("config").unwrap().unwrap() == b"in memory");
+        assert!(p.lock(false).unwrap().is_none());
+        assert!(p.map().is_err());
+    }
+    assert!(elf::has_magic(image.borrow().get_ref()));
+    let stored = image.borrow().get_ref().len();
+    assert!(stored > exe.len());
+    {
+        let mut p = AutoEmbed::new(ExecPath::Image(image.clone())).unwrap();
+        assert!(p.list().unwrap() == vec!["config".to_string()]);
+        p.strip().unwrap();
+        assert!(p.list().unwrap().len() == 0);
+    }
+    assert!(image.borrow().get_ref().len() < stored);
+
+    // anything else in memory, generically
+    let image = Rc::new(RefCell::new(Cursor::new(b"not an executable".to_vec())));
+    {
+        let mut p = AutoEmbed::new(ExecPath::Image(image.clone())).unwrap();
+        p.store(&[1, 2, 3]).unwrap();
+        assert!(p.load().unwrap() == [1, 2, 3]);
+        p.strip().unwrap();
+    }
+    assert!(image.borrow().get_ref() == b"not an executable");
+
+    // any other stream, as long as it doesn't have to shrink
+    let image = Rc::new(RefCell::new(Growing(Cursor::new(b"not an executable".to_vec()))));
+    {
+        let mut p = AutoEmbed::new(ExecPath::Image(image.clone())).unwrap();
+        p.store(&[1, 2, 3]).unwrap();
+        assert!(p.load().unwrap() == [1, 2, 3]);
+        p.store(&[4, 5, 6]).unwrap();
+        assert!(p.load().unwrap() == [4, 5, 6]);
+        let before = image.borrow().0.get_ref().clone();
+        assert!(p.strip().unwrap_err().kind() == io::ErrorKind::InvalidInput);
+        assert!(p.store(&[7]).unwrap_err().kind() == io::ErrorKind::InvalidInput);
+        assert!(*image.borrow().0.get_ref() == before);
+        assert!(p.load().unwrap() == [4, 5, 6]);
+    }
+
+    // an open file descriptor, left open
+    let path = "/tmp/test_ulc11_fd";
+    let _ = fs::remove_file(path);
+    fs::File::create(path).unwrap().write_all(b"#!/bin/sh\necho hi\n").unwrap();
+    let f = fs::OpenOptions::new().read(true).write(true).open(path).unwrap();
+    {
+        let mut p = AutoEmbed::new(ExecPath::Fd(f.as_raw_fd())).unwrap();
+        p.store(b"fd data").unwrap();
+        assert!(p.load().unwrap() == b"fd data");
+    }
+    let mut p = AutoEmbed::new(ExecPath::Fd(f.as_raw_fd())).unwrap();
+    assert!(p.load().unwrap() == b"fd data");
+    p.strip().unwrap();
+    assert!(f.metadata().unwrap().len() == 18);
+    assert!(fs::metadata(&lock::lock_path(Path::new(path)).unwrap()).is_err());
+    drop(f);
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,419 @@
+//! The trailer that follows embeded blobs (see the diagram in generic.rs).
+//!
+//! Two formats are understood; only the second is written:
+//!
+//! ```text
+//! [data][length: u64 LE][MARKER[1..17]]                   (unchecked)
+//! [data][extensions][length: u64][crc32: u32][ext_len: u32]
+//!       [flags: u16][version: u16][V_MARKER]                (all LE)
+//! ```
+//!
+//! The first is reported as version 0. In the versioned format the
+//! version sits right before the marker, so that later versions may lay out
+//! everything before it differently; a reader refuses versions newer than
+//! `VERSION`. `flags` says how the data is to be interpreted (see the
+//! `FLAG_` constants) and the extension area holds optional records of the
+//! form `[tag: u16][len: u32][value]`, which readers skip if they don't
+//! know the tag.
+//!
+//! Trailers are read from the end of a stream: an executable for
+//! `GenericEmbed`, or a `Region` covering a section for `ElfEmbed`.
+
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use ulc91::unsigned;
+use ulc91::utilities::Crc32;
+use ::NO_PAYLOAD;
+use ::TRUNCATED;
+use ::CHECKSUM_MISMATCH;
+use ::UNSUPPORTED_FORMAT;
+
+const MARKER: [u8;18] = [   0x63, 0x29, 0x7a, 0x8a, 0x3f, 0x73, 0x03, 0xf8,
+                            0x2f, 0xfe, 0x9b, 0x65, 0x20, 0x08, 0x96, 0x8d,
+                            0x0e, 0x40];
+
+macro_rules! magic_marker {
+     // 1/2^128, taken from the middle to avoid confusion with ^
+    () => (&MARKER[1..17])
+}
+
+const V_MARKER: [u8;16] = [     0x4b, 0xe3, 0x90, 0x1d, 0x76, 0xa8, 0x25, 0xce,
+                                0x5f, 0x02, 0xb9, 0x64, 0xd1, 0x3a, 0x87, 0xe0];
+
+const MAGIC_LEN: u64 = 16;
+const LEGACY_TRAILER_LEN: u64 = MAGIC_LEN + 8;
+const V_TRAILER_LEN: u64 = MAGIC_LEN + 8 + 4 + 4 + 2 + 2;
+
+/// The newest trailer format version understood, and the one written.
+pub const VERSION: u16 = 1;
+
+/// The data is compressed.
+pub const FLAG_COMPRESSED: u16 = 0x0001;
+/// The data is encrypted.
+pub const FLAG_ENCRYPTED: u16 = 0x0002;
+/// The data is a `ulc91::archive` of named resources.
+pub const FLAG_ARCHIVE: u16 = 0x0004;
+
+// flags that are understood; data with any other flag set can't be loaded
+const KNOWN_FLAGS: u16 = FLAG_ARCHIVE | FLAG_COMPRESSED | FLAG_ENCRYPTED;
+
+/// Extension holding the length of compressed data once decompressed, as
+/// a u64 LE.
+pub const EXT_ORIGINAL_LENGTH: u16 = 1;
+/// Extension holding the nonce encrypted data was sealed with.
+pub const EXT_NONCE: u16 = 2;
+/// Extension holding how the key of encrypted data was derived from a
+/// passphrase: the PBKDF2 iteration count as a u32 LE, and the salt.
+pub const EXT_KDF: u16 = 3;
+/// Extension holding the identity of the executable the data was stored
+/// in (see identity.rs): a kind byte, then a GNU build ID or a SHA-256
+/// digest.
+pub const EXT_IDENTITY: u16 = 4;
+
+// -------------------------------------------------------------------------
+
+/// Where the data preceding a trailer is, how to check it and how to
+/// interpret it.
+#[derive(Debug)]
+pub struct Trailer {
+    pub version: u16,                       // Format version, 0 for trailers without one
+    pub flags: u16,                         // FLAG_ bits
+    pub start: u64,                         // Offset of the data in the stream
+    pub length: u64,                        // Length of the data
+    pub checksum: Option<u32>,              // CRC32 of the data, None if unchecked
+    pub extensions: Vec<(u16, Vec<u8>)>     // Extension records: tag, value
+}
+impl Trailer {
+    /// Describes `length` bytes at `start` that come without a trailer.
+    pub fn unchecked(start: u64, length: u64) -> Trailer {
+        Trailer {version: 0, flags: 0, start: start, length: length, checksum: None, extensions: vec![]}
+    }
+
+    /// Whether all the bits of `flag` are set.
+    pub fn has(&self, flag: u16) -> bool {
+        self.flags & flag == flag
+    }
+
+    /// The value of the first extension record tagged `tag`, if any.
+    pub fn extension(&self, tag: u16) -> Option<&[u8]> {
+        self.extensions.iter().find(|&&(t, _)| t == tag).map(|&(_, ref v)| &v[..])
+    }
+
+    /// The length of the data once loaded: its length before compression,
+    /// if it was compressed.
+    pub fn original_length(&self) -> u64 {
+        match self.extension(EXT_ORIGINAL_LENGTH) {
+            Some(v) if self.has(FLAG_COMPRESSED) && v.len() == 8 => unsigned::from_le_bytes::<u64>(v),
+            _ => self.length
+        }
+    }
+}
+
+pub fn no_payload() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, NO_PAYLOAD, None)
+}
+
+pub fn truncated(detail: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, TRUNCATED, Some(detail))
+}
+
+pub fn checksum_mismatch(expected: u32, found: u32) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, CHECKSUM_MISMATCH,
+                    Some(format!("Expected CRC32 {:08x}, found {:08x}", expected, found)))
+}
+
+pub fn unsupported(detail: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, UNSUPPORTED_FORMAT, Some(detail))
+}
+
+/// Generates the trailer, extension area included, that follows `length`
+/// bytes of data with the CRC32 `checksum`.
+pub fn gen_trailer(length: u64, checksum: u32, flags: u16, extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+    let mut out: Vec<u8> = vec![];
+    for &(tag, ref value) in extensions.iter() {
+        out.push_all(&unsigned::to_le_bytes(tag));
+        out.push_all(&unsigned::to_le_bytes(value.len() as u32));
+        out.push_all(value);
+    }
+    let ext_len = out.len() as u32;
+
+    out.push_all(&unsigned::to_le_bytes(length)); // length as 8-byte LE int
+    out.push_all(&unsigned::to_le_bytes(checksum));
+    out.push_all(&unsigned::to_le_bytes(ext_len));
+    out.push_all(&unsigned::to_le_bytes(flags));
+    out.push_all(&unsigned::to_le_bytes(VERSION));
+    out.push_all(&V_MARKER);
+    out
+}
+
+/// Generates the embeded blob: `ar_blob` followed by its trailer.
+pub fn gen_embed_blob(ar_blob: &[u8], flags: u16, extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+    let mut crc = Crc32::new();
+    crc.update(ar_blob);
+
+    let mut out: Vec<u8> = vec![];
+    out.push_all(ar_blob);
+    out.push_all(&gen_trailer(ar_blob.len() as u64, crc.finish(), flags, extensions));
+    out
+}
+
+// -------------------------------------------------------------------------
+
+fn read_exact_at<T: Read + Seek>(e: &mut T, offset: u64, length: u64) -> io::Result<Vec<u8>> {
+    let mut b: Vec<u8> = vec![];
+    try!(e.seek(SeekFrom::Start(offset)));
+    try!(e.by_ref().take(length).read_to_end(&mut b));
+    if b.len() as u64 != length {
+        return Err(truncated(format!("Read {} of {} bytes at offset {}", b.len(), length, offset)));
+    }
+    Ok(b)
+}
+
+// splits an extension area into its records
+fn parse_extensions(area: &[u8]) -> io::Result<Vec<(u16, Vec<u8>)>> {
+    let mut records: Vec<(u16, Vec<u8>)> = vec![];
+    let mut i = 0;
+    while i < area.len() {
+        if area.len() - i < 6 {
+            return Err(truncated(format!("Extension record header cut short at {} of {}", i, area.len())));
+        }
+        let tag = unsigned::from_le_bytes::<u16>(&area[i..i+2]);
+        let len = unsigned::from_le_bytes::<u32>(&area[i+2..i+6]) as usize;
+        i += 6;
+        if len > area.len() - i {
+            return Err(truncated(format!("Extension record {} claims {} bytes, only {} left", tag, len, area.len() - i)));
+        }
+        records.push((tag, area[i..i+len].to_vec()));
+        i += len;
+    }
+    Ok(records)
+}
+
+// reads the fields of the versioned format; `end` is the length of `e`
+fn read_versioned<T: Read + Seek>(e: &mut T, end: u64) -> io::Result<Trailer> {
+    if end < MAGIC_LEN + 2 {
+        return Err(truncated(format!("{} byte stream can't hold a trailer version", end)));
+    }
+    let v = try!(read_exact_at(e, end - MAGIC_LEN - 2, 2));
+    let version = unsigned::from_le_bytes::<u16>(&v[..]);
+    if version == 0 || version > VERSION {
+        return Err(unsupported(format!("Trailer version {}, newest understood is {}", version, VERSION)));
+    }
+
+    if end < V_TRAILER_LEN {
+        return Err(truncated(format!("{} byte stream can't hold a {} byte trailer", end, V_TRAILER_LEN)));
+    }
+    let fields = try!(read_exact_at(e, end - V_TRAILER_LEN, V_TRAILER_LEN - MAGIC_LEN - 2));
+    let length = unsigned::from_le_bytes::<u64>(&fields[0..8]);
+    let checksum = unsigned::from_le_bytes::<u32>(&fields[8..12]);
+    let ext_len = unsigned::from_le_bytes::<u32>(&fields[12..16]) as u64;
+    let flags = unsigned::from_le_bytes::<u16>(&fields[16..18]);
+
+    let before = end - V_TRAILER_LEN;
+    if ext_len > before || length > before - ext_len {
+        return Err(truncated(format!("Trailer claims {} bytes of data and {} of extensions, only {} precede it",
+                                        length, ext_len, before)));
+    }
+    let area = try!(read_exact_at(e, before - ext_len, ext_len));
+    let extensions = try!(parse_extensions(&area));
+
+    Ok(Trailer {
+        version: version,
+        flags: flags,
+        start: before - ext_len - length,
+        length: length,
+        checksum: Some(checksum),
+        extensions: extensions
+    })
+}
+
+/// Reads the trailer at the end of `e`. If no trailer is present, None is
+/// returned. Fails with `TRUNCATED` if the data the trailer describes
+/// doesn't fit in the stream, and with `UNSUPPORTED_FORMAT` if the trailer
+/// is newer than this library.
+pub fn read_trailer<T: Read + Seek>(e: &mut T) -> io::Result<Option<Trailer>> {
+    let end = try!(e.seek(SeekFrom::End(0)));
+    if end < MAGIC_LEN {
+        return Ok(None);
+    }
+
+    // if no magic marker (see top of file) is present, assume no data
+    // is embeded.
+    let magic = try!(read_exact_at(e, end - MAGIC_LEN, MAGIC_LEN));
+    if magic[..] == V_MARKER[..] {
+        return read_versioned(e, end).map(Some);
+    }
+    if magic[..] != *magic_marker!() {
+        return Ok(None);
+    }
+
+    let trailer_len = LEGACY_TRAILER_LEN;
+    if end < trailer_len {
+        return Err(truncated(format!("{} byte stream can't hold a {} byte trailer", end, trailer_len)));
+    }
+    let fields = try!(read_exact_at(e, end - trailer_len, trailer_len - MAGIC_LEN));
+    let length = unsigned::from_le_bytes::<u64>(&fields[0..8]);
+
+    if length > end - trailer_len {
+        return Err(truncated(format!("Trailer claims {} bytes of data, only {} precede it", length, end - trailer_len)));
+    }
+    Ok(Some(Trailer::unchecked(end - trailer_len - length, length)))
+}
+
+/// Compares `data`, as described by `t`, to its checksum.
+pub fn check(data: &[u8], t: &Trailer) -> io::Result<()> {
+    if let Some(expected) = t.checksum {
+        let mut crc = Crc32::new();
+        crc.update(data);
+        if crc.finish() != expected {
+            return Err(checksum_mismatch(expected, crc.finish()));
+        }
+    }
+    Ok(())
+}
+
+/// Streams the data described by `t` out of `e` and compares it to its
+/// checksum, without loading it.
+pub fn verify<T: Read + Seek>(e: &mut T, t: &Trailer) -> io::Result<()> {
+    let expected = match t.checksum {
+        Some(c) => c,
+        None => return Ok(()) // unchecked format, nothing to compare to
+    };
+
+    let mut crc = Crc32::new();
+    let mut buf: Vec<u8> = vec![0; 65536];
+    let mut left = t.length;
+    try!(e.seek(SeekFrom::Start(t.start)));
+    while left > 0 {
+        let want = if left < buf.len() as u64 {left as usize} else {buf.len()};
+        let n = try!(e.read(&mut buf[..want]));
+        if n == 0 {
+            return Err(truncated(format!("Data ended {} bytes early", left)));
+        }
+        crc.update(&buf[..n]);
+        left -= n as u64;
+    }
+
+    if crc.finish() != expected {
+        return Err(checksum_mismatch(expected, crc.finish()));
+    }
+    Ok(())
+}
+
+/// Reads the data described by `t` out of `e` as stored, checking it (see
+/// `codec::decode` to undo transformations). Fails with
+/// `UNSUPPORTED_FORMAT` if the data is flagged in a way that isn't
+/// understood.
+pub fn load<T: Read + Seek>(e: &mut T, t: &Trailer) -> io::Result<Vec<u8>> {
+    if t.flags & !KNOWN_FLAGS != 0 {
+        return Err(unsupported(format!("Unknown flags {:04x}", t.flags & !KNOWN_FLAGS)));
+    }
+    let b = try!(read_exact_at(e, t.start, t.length));
+    try!(check(&b, t));
+    Ok(b)
+}
+
+
+#[test]
+fn test_trailer() {
+    use std::io::Cursor;
+    use std::error::Error;
+
+    let mut image: Vec<u8> = vec![0x7f; 100];
+    image.push_all(&gen_embed_blob(&[1, 2, 3, 4], 0, &[]));
+
+    // sound
+    {
+        let mut c = Cursor::new(&image[..]);
+        let t = read_trailer(&mut c).unwrap().unwrap();
+        assert!(t.start == 100 && t.length == 4);
+        assert!(t.version == VERSION && t.flags == 0 && t.extensions.len() == 0);
+        assert!(verify(&mut c, &t).is_ok());
+        assert!(load(&mut c, &t).unwrap() == [1, 2, 3, 4]);
+    }
+    // nothing embeded
+    {
+        let mut c = Cursor::new(&image[..100]);
+        assert!(read_trailer(&mut c).unwrap().is_none());
+    }
+    // corrupted
+    {
+        let mut corrupt = image.clone();
+        corrupt[101] = 0;
+        let mut c = Cursor::new(&corrupt[..]);
+        let t = read_trailer(&mut c).unwrap().unwrap();
+        assert!(verify(&mut c, &t).unwrap_err().description() == CHECKSUM_MISMATCH);
+        assert!(load(&mut c, &t).unwrap_err().description() == CHECKSUM_MISMATCH);
+    }
+    // truncated: the length no longer fits
+    {
+        let cut = &image[98..];
+        let mut c = Cursor::new(cut);
+        assert!(read_trailer(&mut c).is_ok());
+        let cut = &image[102..];
+        let mut c = Cursor::new(cut);
+        assert!(read_trailer(&mut c).unwrap_err().description() == TRUNCATED);
+    }
+    // legacy trailers are still read
+    {
+        let mut legacy: Vec<u8> = vec![0x7f; 10];
+        legacy.push_all(&[5, 6]);
+        legacy.push_all(&unsigned::to_le_bytes(2u64));
+        legacy.push_all(magic_marker!());
+        let mut c = Cursor::new(&legacy[..]);
+        let t = read_trailer(&mut c).unwrap().unwrap();
+        assert!(t.checksum.is_none() && t.version == 0);
+        assert!(load(&mut c, &t).unwrap() == [5, 6]);
+    }
+}
+
+#[test]
+fn test_versioned_trailer() {
+    use std::io::Cursor;
+    use std::error::Error;
+
+    // flags and extensions survive a round trip
+    let ext = vec![(7u16, vec![1u8, 2, 3]), (9u16, vec![]), (0xfffeu16, vec![4u8])];
+    let mut image: Vec<u8> = vec![0x7f; 10];
+    image.push_all(&gen_embed_blob(b"data", FLAG_ARCHIVE, &ext));
+    {
+        let mut c = Cursor::new(&image[..]);
+        let t = read_trailer(&mut c).unwrap().unwrap();
+        assert!(t.start == 10 && t.length == 4);
+        assert!(t.has(FLAG_ARCHIVE) && !t.has(FLAG_COMPRESSED));
+        assert!(t.extension(7).unwrap() == [1, 2, 3]);
+        assert!(t.extension(9).unwrap().len() == 0);
+        assert!(t.extension(8).is_none());
+        assert!(t.extensions == ext);
+        assert!(load(&mut c, &t).unwrap() == b"data");
+    }
+    // data flagged in ways that aren't understood is refused
+    {
+        let mut odd: Vec<u8> = vec![];
+        odd.push_all(&gen_embed_blob(b"data", 0x8000, &[]));
+        let mut c = Cursor::new(&odd[..]);
+        let t = read_trailer(&mut c).unwrap().unwrap();
+        assert!(t.flags == 0x8000);
+        assert!(t.original_length() == 4);
+        assert!(verify(&mut c, &t).is_ok());
+        assert!(load(&mut c, &t).unwrap_err().description() == UNSUPPORTED_FORMAT);
+    }
+    // so are newer versions
+    {
+        let mut newer = image.clone();
+        let at = newer.len() - MAGIC_LEN as usize - 2;
+        newer[at] = (VERSION + 1) as u8;
+        let mut c = Cursor::new(&newer[..]);
+        assert!(read_trailer(&mut c).unwrap_err().description() == UNSUPPORTED_FORMAT);
+    }
+    // a corrupted extension length
+    {
+        let mut bad = image.clone();
+        let at = bad.len() - V_TRAILER_LEN as usize + 12; // ext_len
+        bad[at] += 1;
+        let mut c = Cursor::new(&bad[..]);
+        assert!(read_trailer(&mut c).unwrap_err().description() == TRUNCATED);
+    }
+}
@@ -0,0 +1,930 @@
+//! A read-only virtual filesystem over embeded resources.
+//!
+//! `pack_dir` embeds a directory tree as a resource archive (see
+//! `AutoEmbed`): each regular file is a resource named by its path from the
+//! root of the tree, e.g. `static/css/app.css`. The kinds, modes and
+//! modification times of files, directories and symbolic links are kept in
+//! an extra resource, `TABLE`, which also holds the targets of links and
+//! records directories, so that empty ones survive.
+//!
+//! `Vfs` reads it back: `open("/static/css/app.css")` gives a `File` that
+//! reads the resource in place, as `AutoEmbed::resources` does, and
+//! `extract_to` restores the tree on disk.
+
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::path::Path;
+use std::path::PathBuf;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use libc;
+use ulc91::archive::Archive;
+use ulc91::blob;
+use ulc91::unsigned;
+use ::ExecPath;
+use ::Embed;
+use ::auto::AutoEmbed;
+use ::auto::Resources;
+use ::trailer;
+use ::trailer::FLAG_ARCHIVE;
+
+extern {
+    fn utimensat(dirfd: libc::c_int, pathname: *const libc::c_char, times: *const libc::timespec,
+                 flags: libc::c_int) -> libc::c_int;
+}
+const AT_FDCWD: libc::c_int = -100;
+const AT_SYMLINK_NOFOLLOW: libc::c_int = 0x100;
+
+/// Name of the resource holding the metadata of files, directories and
+/// links. No path can clash with it, as paths can't hold NUL bytes.
+pub const TABLE: &'static str = "\0vfs";
+
+/// Description of the error returned when an embeded path, or the target of
+/// an embeded link, would lead out of the tree it is extracted to.
+pub const UNSAFE_PATH: &'static str = "Embeded path leads out of the filesystem";
+
+// kinds of entries, as in `st_mode`, kept apart from the permission bits
+const KIND_MASK: u32 = 0o170000;
+const KIND_FILE: u32 = 0o100000;
+const KIND_DIR: u32 = 0o040000;
+const KIND_LINK: u32 = 0o120000;
+const PERMISSIONS: u32 = 0o7777;
+
+/// The permission bits `extract_to` gives extracted files and directories:
+/// setuid, setgid and sticky bits are left out (see `extract_to_with`).
+pub const EXTRACT_PERMISSIONS: u32 = 0o777;
+
+// mode of directories missing from the table, the root included
+const DEFAULT_DIR_MODE: u32 = 0o755;
+// mode of files missing from the table
+const DEFAULT_FILE_MODE: u32 = 0o644;
+// the most links followed resolving a path, as Linux's MAXSYMLINKS
+const MAX_LINKS: usize = 40;
+
+/// Normalizes `path`: relative paths are taken from the root, `.` and empty
+/// components are dropped, and `..` goes up (but not above the root). The
+/// result has no leading or trailing `/`; the root is the empty string.
+pub fn normalize(path: &str) -> String {
+    let mut components: Vec<&str> = vec![];
+    for c in path.split('/') {
+        match c {
+            "" | "." => {},
+            ".." => { components.pop(); },
+            c => components.push(c)
+        }
+    }
+    components.connect("/")
+}
+
+// the parent of normalized path `path`, which isn't the root
+fn parent(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(i) => &path[..i],
+        None => ""
+    }
+}
+
+// `path` joined to normalized path `at`
+fn join(at: &str, path: &str) -> String {
+    if at.len() == 0 {path.to_string()} else {format!("{}/{}", at, path)}
+}
+
+fn not_found(path: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "No such file or directory", Some(format!("/{}", path)))
+}
+
+fn last_os_error(description: &'static str, path: &Path) -> io::Error {
+    let e = io::Error::last_os_error();
+    io::Error::new(e.kind(), description, Some(format!("{}: {}", path.display(), e)))
+}
+
+fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput,
+                                            "Path contains a NUL byte", Some(format!("{}", path.display()))))
+}
+
+// sets the access and modification times of `path`, not following links
+fn set_mtime(path: &Path, mtime: (i64, u32)) -> io::Result<()> {
+    let cpath = try!(c_path(path));
+    let t = libc::timespec {tv_sec: mtime.0 as libc::time_t, tv_nsec: mtime.1 as libc::c_long};
+    let times = [t, t];
+    if unsafe { utimensat(AT_FDCWD, cpath.as_ptr(), times.as_ptr(), AT_SYMLINK_NOFOLLOW) } < 0 {
+        return Err(last_os_error("Could not set modification time", path));
+    }
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------
+
+// An entry of the table: the mode (kind included) and modification time of
+// a path, and the target of links.
+struct Record {
+    mode: u32,
+    mtime: Option<(i64, u32)>,
+    target: Option<String>
+}
+
+// The table: a blob of packets, one per path, each itself a blob of the 4
+// byte mode, the path, the 12 byte modification time (seconds, then
+// nanoseconds) and, for links, the target. Trailing fields may be missing.
+fn gen_table(records: &HashMap<String, Record>) -> Vec<u8> {
+    let mut paths: Vec<&String> = records.keys().collect();
+    paths.sort();
+    let entries: Vec<Vec<u8>> = paths.into_iter().map(|path| {
+        let r = records.get(path).unwrap();
+        let mut fields: Vec<Vec<u8>> = vec![unsigned::to_le_bytes::<u32>(r.mode), path.as_bytes().to_vec()];
+        if let Some((sec, nsec)) = r.mtime {
+            let mut t = unsigned::to_le_bytes::<u64>(sec as u64);
+            t.push_all(&unsigned::to_le_bytes::<u32>(nsec));
+            fields.push(t);
+            if let Some(ref target) = r.target {
+                fields.push(target.as_bytes().to_vec());
+            }
+        }
+        blob::pack(fields)
+    }).collect();
+    blob::pack(entries)
+}
+
+fn parse_table(table: &[u8]) -> io::Result<HashMap<String, Record>> {
+    let broken = |detail: &str| io::Error::new(io::ErrorKind::InvalidInput, "Embeded filesystem table is corrupted",
+                                                Some(detail.to_string()));
+    let mut records: HashMap<String, Record> = HashMap::new();
+    for e in try!(blob::unpack(table).map_err(|_| broken("Entries are cut short"))).into_iter() {
+        let fields = try!(blob::unpack(&e).map_err(|_| broken("Fields are cut short")));
+        if fields.len() < 2 || fields[0].len() != 4 {
+            return Err(broken("Entry has no mode or path"));
+        }
+        let mtime = match fields.get(2) {
+            Some(t) if t.len() == 12 => Some((unsigned::from_le_bytes::<u64>(&t[..8]) as i64,
+                                              unsigned::from_le_bytes::<u32>(&t[8..]))),
+            Some(_) => return Err(broken("Modification time has the wrong length")),
+            None => None
+        };
+        let target = match fields.get(3) {
+            Some(t) => Some(try!(String::from_utf8(t.clone()).map_err(|_| broken("Link target is not valid UTF-8")))),
+            None => None
+        };
+        let path = try!(String::from_utf8(fields[1].clone()).map_err(|_| broken("Path is not valid UTF-8")));
+        records.insert(path, Record {mode: unsigned::from_le_bytes::<u32>(&fields[0]), mtime: mtime, target: target});
+    }
+    Ok(records)
+}
+
+// adds the tree under `dir`, at normalized path `at`, to `ar` and `records`
+fn add_tree(ar: &mut Archive, records: &mut HashMap<String, Record>, dir: &Path, at: &str) -> io::Result<()> {
+    for entry in try!(fs::read_dir(dir)) {
+        let source = try!(entry).path();
+        let path = match source.file_name().and_then(|n| n.to_str()) {
+            Some(n) => join(at, n),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "File name is not valid UTF-8",
+                                                Some(format!("{}", source.display()))))
+        };
+
+        let mut st: libc::stat = unsafe { ::std::mem::zeroed() };
+        if unsafe { libc::lstat(try!(c_path(&source)).as_ptr(), &mut st) } < 0 {
+            return Err(last_os_error("Could not stat file", &source));
+        }
+        let mode = st.st_mode as u32;
+        let mtime = Some((st.st_mtime as i64, st.st_mtime_nsec as u32));
+        // other kinds (devices, sockets, ...) are skipped
+        match mode & KIND_MASK {
+            KIND_DIR => {
+                records.insert(path.clone(), Record {mode: mode, mtime: mtime, target: None});
+                try!(add_tree(ar, records, &source, &path));
+            },
+            KIND_FILE => {
+                let mut data: Vec<u8> = vec![];
+                try!(try!(fs::File::open(&source)).read_to_end(&mut data));
+                ar.insert(path.clone(), data);
+                records.insert(path, Record {mode: mode, mtime: mtime, target: None});
+            },
+            KIND_LINK => {
+                let target = match try!(fs::read_link(&source)).to_str() {
+                    Some(t) => t.to_string(),
+                    None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Link target is not valid UTF-8",
+                                                        Some(format!("{}", source.display()))))
+                };
+                records.insert(path, Record {mode: mode, mtime: mtime, target: Some(target)});
+            },
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Embeds the directory tree under `dir` into the executable of `embed`,
+/// replacing whatever was embeded. Symbolic links are kept as links. It is
+/// stored with the options of `embed` (e.g. compressed).
+pub fn pack_dir(embed: &mut AutoEmbed, dir: &Path) -> io::Result<()> {
+    let mut ar = try!(Archive::new(None).map_err(|e| io::Error::new(io::ErrorKind::Other,
+                                            "Could not create archive", Some(e.connect(": ")))));
+    let mut records: HashMap<String, Record> = HashMap::new();
+    try!(add_tree(&mut ar, &mut records, dir, ""));
+    ar.insert(TABLE.to_string(), gen_table(&records));
+
+    let _lock = try!(embed.lock(true));
+    embed.store_with(&ar.to_bytes(), FLAG_ARCHIVE)
+}
+
+// ----------------------------------------------------------------------------
+
+/// What `Vfs::extract_to` does with files (and links) that already exist.
+/// Directories that already exist are always merged into.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overwrite {
+    /// Keeps them.
+    Skip,
+    /// Replaces them, each at once.
+    Replace,
+    /// Fails with `AlreadyExists`, before anything is extracted.
+    Fail
+}
+
+/// Metadata of a file, directory or symbolic link in a `Vfs`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metadata {
+    len: u64,
+    mode: u32,                  // kind and permission bits
+    mtime: Option<(i64, u32)>
+}
+impl Metadata {
+    /// Length of the file, 0 for directories and links.
+    pub fn len(&self) -> u64 {self.len}
+    /// Permission bits, as in `st_mode & 0o7777`.
+    pub fn mode(&self) -> u32 {self.mode & PERMISSIONS}
+    pub fn is_dir(&self) -> bool {self.mode & KIND_MASK == KIND_DIR}
+    pub fn is_file(&self) -> bool {self.mode & KIND_MASK == KIND_FILE}
+    pub fn is_symlink(&self) -> bool {self.mode & KIND_MASK == KIND_LINK}
+    /// Modification time, in seconds and nanoseconds since the epoch, if
+    /// it was recorded.
+    pub fn modified(&self) -> Option<(i64, u32)> {self.mtime}
+}
+
+enum Kind {
+    File(String, u64),  // resource name and length
+    Dir(Vec<String>),   // sorted entries
+    Link(String)        // target
+}
+
+struct Entry {
+    kind: Kind,
+    mode: u32,                  // kind and permission bits
+    mtime: Option<(i64, u32)>
+}
+
+/// A read-only filesystem over the resources embeded in an executable (see
+/// the module docs). Paths are `/` separated, and taken from the root
+/// whether they start with `/` or not (see `normalize`). Symbolic links are
+/// followed within the filesystem, absolute ones from its root.
+///
+/// Resources embeded by other means than `pack_dir` are files too, with
+/// default modes, so any resource archive can be browsed. Names that aren't
+/// normalized paths (e.g. `../x`) can't be reached.
+pub struct Vfs {
+    resources: Rc<RefCell<Resources>>,
+    entries: HashMap<String, Entry>,
+    unreachable: Vec<String>    // names no path leads to, sorted
+}
+impl Vfs {
+    /// Opens the filesystem embeded in `executable`.
+    pub fn new(executable: ExecPath) -> io::Result<Vfs> {
+        Vfs::with_embed(&try!(AutoEmbed::new(executable)))
+    }
+
+    /// Opens the filesystem embeded in the executable of `embed`, with its
+    /// options (e.g. the key of an encrypted filesystem).
+    pub fn with_embed(embed: &AutoEmbed) -> io::Result<Vfs> {
+        let mut resources = try!(embed.resources());
+        let records = match resources.len(TABLE) {
+            Some(len) => {
+                // `resources` checked that the table lies within the blob
+                let mut table: Vec<u8> = vec![0; len as usize];
+                let mut read = 0;
+                while read < table.len() {
+                    match try!(resources.read_at(TABLE, &mut table[read..], read as u64)) {
+                        0 => return Err(trailer::truncated(format!("Filesystem table is cut short"))),
+                        n => read += n
+                    }
+                }
+                try!(parse_table(&table))
+            },
+            None => HashMap::new()
+        };
+
+        let mut vfs = Vfs {resources: Rc::new(RefCell::new(resources)), entries: HashMap::new(), unreachable: vec![]};
+        vfs.entries.insert(String::new(), Entry {kind: Kind::Dir(vec![]), mode: KIND_DIR | DEFAULT_DIR_MODE, mtime: None});
+
+        // directories and links, then files; parents sort before children
+        let mut paths: Vec<&String> = records.keys().collect();
+        paths.sort();
+        for path in paths.into_iter() {
+            let r = records.get(path).unwrap();
+            let kind = match (r.mode & KIND_MASK, &r.target) {
+                (KIND_DIR, _) => Kind::Dir(vec![]),
+                (KIND_LINK, &Some(ref t)) => Kind::Link(t.clone()),
+                _ => continue // files come with their resource
+            };
+            vfs.add(path, Entry {kind: kind, mode: r.mode, mtime: r.mtime});
+        }
+        let names = vfs.resources.borrow().names();
+        for name in names.into_iter().filter(|n| *n != TABLE) {
+            let len = vfs.resources.borrow().len(&name).unwrap();
+            let (mode, mtime) = match records.get(&name) {
+                Some(r) => (KIND_FILE | (r.mode & PERMISSIONS), r.mtime),
+                None => (KIND_FILE | DEFAULT_FILE_MODE, None)
+            };
+            vfs.add(&name.clone(), Entry {kind: Kind::File(name, len), mode: mode, mtime: mtime});
+        }
+        for (_, e) in vfs.entries.iter_mut() {
+            if let Kind::Dir(ref mut names) = e.kind {
+                names.sort();
+            }
+        }
+        vfs.unreachable.sort();
+        Ok(vfs)
+    }
+
+    // adds `entry` at `path`, and the directories leading to it. Paths that
+    // aren't normalized, or clash with another entry, are unreachable.
+    fn add(&mut self, path: &str, entry: Entry) {
+        if path.len() == 0 || normalize(path) != path || !self.add_parent(parent(path)) {
+            self.unreachable.push(path.to_string());
+            return;
+        }
+        if let Some(e) = self.entries.get_mut(path) {
+            match (&e.kind, &entry.kind) {
+                (&Kind::Dir(_), &Kind::Dir(_)) => {},
+                _ => {
+                    self.unreachable.push(path.to_string());
+                    return;
+                }
+            }
+            e.mode = entry.mode; // was only implied by its entries
+            e.mtime = entry.mtime;
+            return;
+        }
+        let name = path[parent(path).len()..].trim_left_matches('/').to_string();
+        if let Kind::Dir(ref mut names) = self.entries.get_mut(parent(path)).unwrap().kind {
+            names.push(name);
+        }
+        self.entries.insert(path.to_string(), entry);
+    }
+
+    // makes sure there is a directory at `path`; false if something else is
+    fn add_parent(&mut self, path: &str) -> bool {
+        match self.entries.get(path).map(|e| match e.kind {Kind::Dir(_) => true, _ => false}) {
+            Some(is_dir) => is_dir,
+            None => {
+                self.add(path, Entry {kind: Kind::Dir(vec![]), mode: KIND_DIR | DEFAULT_DIR_MODE, mtime: None});
+                self.entries.contains_key(path)
+            }
+        }
+    }
+
+    // the normalized path `path` leads to, following links on the way, and
+    // the last one too if `follow`
+    fn resolve(&self, path: &str, follow: bool) -> io::Result<String> {
+        let mut links = 0;
+        let mut rest: Vec<String> = normalize(path).split('/').rev().filter(|c| c.len() > 0)
+                                                    .map(|c| c.to_string()).collect();
+        let mut at = String::new();
+        while let Some(c) = rest.pop() {
+            let next = join(&at, &c);
+            match self.entries.get(&next) {
+                Some(&Entry {kind: Kind::Link(ref target), ..}) if follow || rest.len() > 0 => {
+                    links += 1;
+                    if links > MAX_LINKS {
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Too many levels of symbolic links",
+                                                    Some(format!("/{}", normalize(path)))));
+                    }
+                    let base = if target.starts_with("/") {String::new()} else {at};
+                    rest.extend(normalize(&join(&base, target)).split('/').rev().filter(|c| c.len() > 0)
+                                                                .map(|c| c.to_string()));
+                    at = String::new();
+                },
+                Some(&Entry {kind: Kind::Dir(_), ..}) => at = next,
+                Some(_) if rest.len() == 0 => at = next,
+                Some(_) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not a directory",
+                                                        Some(format!("/{}", next)))),
+                None => return Err(not_found(&next))
+            }
+        }
+        Ok(at)
+    }
+
+    // whether the link at normalized path `path` leads out of the tree once
+    // written out. Its target is walked the way the kernel will, following
+    // the links it passes through: `..` after a link goes up from where the
+    // link leads, not from the link. Link loops don't escape, the kernel
+    // gives up on them too.
+    fn link_escapes(&self, path: &str, target: &str) -> bool {
+        let mut links = 0;
+        let mut rest: Vec<String> = target.split('/').rev().map(|c| c.to_string()).collect();
+        let mut at: Vec<String> = parent(path).split('/').filter(|c| c.len() > 0).map(|c| c.to_string()).collect();
+        if target.starts_with("/") {
+            return true;
+        }
+        while let Some(c) = rest.pop() {
+            match &c[..] {
+                "" | "." => {},
+                ".." => if at.pop().is_none() {
+                    return true;
+                },
+                _ => {
+                    let next = join(&at.connect("/"), &c);
+                    match self.entries.get(&next) {
+                        Some(&Entry {kind: Kind::Link(ref t), ..}) => {
+                            links += 1;
+                            if links > MAX_LINKS {
+                                return false;
+                            }
+                            if t.starts_with("/") {
+                                return true;
+                            }
+                            rest.extend(t.split('/').rev().map(|c| c.to_string()));
+                        },
+                        _ => at.push(c)
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Metadata of the file, directory or link at `path`. A link at `path`
+    /// itself isn't followed.
+    pub fn metadata(&self, path: &str) -> io::Result<Metadata> {
+        let path = try!(self.resolve(path, false));
+        let e = self.entries.get(&path).unwrap();
+        let len = match e.kind {
+            Kind::File(_, len) => len,
+            _ => 0
+        };
+        Ok(Metadata {len: len, mode: e.mode, mtime: e.mtime})
+    }
+
+    /// Whether a file, directory or link exists at `path`.
+    pub fn exists(&self, path: &str) -> bool {
+        self.metadata(path).is_ok()
+    }
+
+    /// Target of the link at `path`.
+    pub fn read_link(&self, path: &str) -> io::Result<String> {
+        let path = try!(self.resolve(path, false));
+        match self.entries.get(&path).unwrap().kind {
+            Kind::Link(ref target) => Ok(target.clone()),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Not a symbolic link", Some(format!("/{}", path))))
+        }
+    }
+
+    /// Names of the entries of the directory at `path`, sorted.
+    pub fn read_dir(&self, path: &str) -> io::Result<Vec<String>> {
+        let path = try!(self.resolve(path, true));
+        match self.entries.get(&path).unwrap().kind {
+            Kind::Dir(ref names) => Ok(names.clone()),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Not a directory", Some(format!("/{}", path))))
+        }
+    }
+
+    /// Opens the file at `path` for reading. Files can be open at the same
+    /// time, but not shared between threads.
+    pub fn open(&self, path: &str) -> io::Result<File> {
+        let path = try!(self.resolve(path, true));
+        match self.entries.get(&path).unwrap().kind {
+            Kind::File(ref name, len) => Ok(File {resources: self.resources.clone(), name: name.clone(), len: len, pos: 0}),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Is a directory", Some(format!("/{}", path))))
+        }
+    }
+
+    /// Reads the whole file at `path`.
+    pub fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        let mut data: Vec<u8> = vec![];
+        try!(try!(self.open(path)).read_to_end(&mut data));
+        Ok(data)
+    }
+
+    /// Extracts the filesystem into the directory `dir`, with the modes,
+    /// modification times and links it was packed with. Modes are masked
+    /// with `EXTRACT_PERMISSIONS`.
+    ///
+    /// The tree is first written to a staging directory next to `dir`. If
+    /// `dir` doesn't exist, the staging directory is then renamed to it, so
+    /// that `dir` appears complete or not at all. Otherwise the tree is
+    /// merged into `dir`: each new file, link or whole new directory is
+    /// renamed into place, and existing ones are treated as `overwrite`
+    /// says. Existing directories keep their mode.
+    ///
+    /// Fails with `UNSAFE_PATH`, before writing anything, if an embeded
+    /// name or link would lead out of `dir`, and with `AlreadyExists` if
+    /// a directory is in the way of a file (or the other way around).
+    pub fn extract_to(&self, dir: &Path, overwrite: Overwrite) -> io::Result<()> {
+        self.extract_to_with(dir, overwrite, EXTRACT_PERMISSIONS)
+    }
+
+    /// Like `extract_to`, but keeps the permission bits in `mask` instead.
+    /// Setuid and setgid bits (`0o6000`) can be restored this way, for an
+    /// archive that is trusted with them.
+    pub fn extract_to_with(&self, dir: &Path, overwrite: Overwrite, mask: u32) -> io::Result<()> {
+        let mask = mask & PERMISSIONS;
+        if let Some(name) = self.unreachable.first() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, UNSAFE_PATH, Some(format!("{:?}", name))));
+        }
+        for (path, e) in self.entries.iter() {
+            if let Kind::Link(ref target) = e.kind {
+                if self.link_escapes(path, target) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, UNSAFE_PATH,
+                                                Some(format!("/{} -> {}", path, target))));
+                }
+            }
+        }
+
+        let mut paths: Vec<&String> = self.entries.keys().filter(|p| p.len() > 0).collect();
+        paths.sort();
+        let exists = match fs::metadata(dir) {
+            Ok(m) if m.is_dir() => true,
+            Ok(_) => return Err(io::Error::new(io::ErrorKind::AlreadyExists, "Not a directory",
+                                                Some(format!("{}", dir.display())))),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e)
+        };
+        if exists {
+            try!(self.merge(&paths, Path::new(""), dir, overwrite, mask, true));
+        }
+
+        let staging = try!(create_staging(dir));
+        let mut renamed = false;
+        let result = self.stage(&paths, &staging, mask).and_then(|_| {
+            if exists {
+                self.merge(&paths, &staging, dir, overwrite, mask, false)
+            }
+            else {
+                try!(self.finish_dirs(&paths, &staging, "", mask));
+                try!(fs::rename(&staging, dir));
+                renamed = true;
+                self.finish_dir("", dir, mask)
+            }
+        });
+        if !renamed {
+            // once renamed, the name may already be another extraction's
+            let _ = fs::remove_dir_all(&staging);
+        }
+        result
+    }
+
+    // writes the tree under `root`, file modes masked with `mask`.
+    // Directories are left writable, for `finish_dirs` to fix once they are
+    // filled.
+    fn stage(&self, paths: &[&String], root: &Path, mask: u32) -> io::Result<()> {
+        for path in paths.iter() {
+            let e = self.entries.get(*path).unwrap();
+            let dst = root.join(path);
+            match e.kind {
+                Kind::Dir(_) => try!(fs::create_dir(&dst)),
+                Kind::File(..) => {
+                    let mut out = try!(fs::File::create(&dst));
+                    try!(io::copy(&mut try!(self.open(path)), &mut out));
+                    try!(out.sync_all());
+                    try!(fs::set_permissions(&dst, fs::Permissions::from_mode(e.mode & mask)));
+                },
+                Kind::Link(ref target) => try!(::std::os::unix::fs::symlink(target, &dst))
+            }
+            match (&e.kind, e.mtime) {
+                (&Kind::Dir(_), _) | (_, None) => {},
+                (_, Some(t)) => try!(set_mtime(&dst, t))
+            }
+        }
+        Ok(())
+    }
+
+    // gives the directories strictly under `under`, staged at `root`, their
+    // modes (masked with `mask`) and modification times, deepest first
+    fn finish_dirs(&self, paths: &[&String], root: &Path, under: &str, mask: u32) -> io::Result<()> {
+        let prefix = format!("{}/", under);
+        for path in paths.iter().rev().filter(|p| under.len() == 0 || p.starts_with(&prefix)) {
+            if let Kind::Dir(_) = self.entries.get(*path).unwrap().kind {
+                try!(self.finish_dir(path, &root.join(path), mask));
+            }
+        }
+        Ok(())
+    }
+
+    // gives the directory at `path`, written to `dst`, its mode (masked
+    // with `mask`) and time
+    fn finish_dir(&self, path: &str, dst: &Path, mask: u32) -> io::Result<()> {
+        let e = self.entries.get(path).unwrap();
+        try!(fs::set_permissions(dst, fs::Permissions::from_mode(e.mode & mask)));
+        match e.mtime {
+            Some(t) => set_mtime(dst, t),
+            None => Ok(())
+        }
+    }
+
+    // moves the tree staged at `root` into the existing directory `dir`, or
+    // only checks that it can be if `dry`
+    fn merge(&self, paths: &[&String], root: &Path, dir: &Path, overwrite: Overwrite, mask: u32, dry: bool) -> io::Result<()> {
+        let mut moved: Vec<String> = vec![]; // new directories, moved as a whole
+        for path in paths.iter() {
+            if moved.iter().any(|m| path.starts_with(&format!("{}/", m))) {
+                continue;
+            }
+            let e = self.entries.get(*path).unwrap();
+            let dst = dir.join(path);
+            let existing = match fs::symlink_metadata(&dst) {
+                Ok(m) => Some(m.is_dir()),
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err)
+            };
+            let in_the_way = || io::Error::new(io::ErrorKind::AlreadyExists, "File is in the way of extraction",
+                                                Some(format!("{}", dst.display())));
+            match (&e.kind, existing) {
+                (&Kind::Dir(_), None) => {
+                    if !dry {
+                        try!(self.finish_dirs(paths, root, path, mask));
+                        try!(fs::rename(&root.join(path), &dst));
+                        try!(self.finish_dir(path, &dst, mask));
+                    }
+                    moved.push(path.to_string());
+                },
+                (&Kind::Dir(_), Some(true)) => {},
+                (&Kind::Dir(_), Some(false)) | (_, Some(true)) => return Err(in_the_way()),
+                (_, None) => if !dry {
+                    try!(fs::rename(&root.join(path), &dst));
+                },
+                (_, Some(false)) => match overwrite {
+                    Overwrite::Skip => {},
+                    Overwrite::Replace => if !dry {
+                        try!(fs::rename(&root.join(path), &dst));
+                    },
+                    Overwrite::Fail => return Err(io::Error::new(io::ErrorKind::AlreadyExists, "File already exists",
+                                                                    Some(format!("{}", dst.display()))))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Creates a new staging sibling of `dir`. Existing directories are never
+// reused or removed: another extraction may be writing them.
+fn create_staging(dir: &Path) -> io::Result<PathBuf> {
+    let name = match dir.file_name().and_then(|n| n.to_str()) {
+        Some(n) => n,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Directory path has no valid file name",
+                                            Some(format!("{}", dir.display()))))
+    };
+    let pid = unsafe { libc::getpid() };
+    let mut n: u64 = 0;
+    loop {
+        let staging = dir.with_file_name(&format!(".{}.ulc11-{}-{}.staging", name, pid, n));
+        match fs::create_dir(&staging) {
+            Ok(()) => return Ok(staging),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e)
+        }
+    }
+}
+
+/// A file open in a `Vfs`, read in place.
+pub struct File {
+    resources: Rc<RefCell<Resources>>,
+    name: String,
+    len: u64,
+    pos: u64
+}
+impl File {
+    /// Length of the file.
+    pub fn len(&self) -> u64 {self.len}
+}
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len {
+            return Ok(0);
+        }
+        let n = try!(self.resources.borrow_mut().read_at(&self.name, buf, self.pos));
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new = match pos {
+            SeekFrom::Start(o) => o as i64,
+            SeekFrom::Current(o) => self.pos as i64 + o,
+            SeekFrom::End(o) => self.len as i64 + o
+        };
+        if new < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seeking before the start of the file",
+                        Some(format!("Position {} requested", new))));
+        }
+        self.pos = new as u64;
+        Ok(self.pos)
+    }
+}
+
+
+#[test]
+fn test_vfs() {
+    use std::io::Write;
+
+    let root = "/tmp/test_ulc11_vfs_tree";
+    let exe = "/tmp/test_ulc11_vfs";
+    let _ = fs::remove_dir_all(root);
+    let _ = fs::remove_file(exe);
+    fs::create_dir_all(&format!("{}/static/css", root)).unwrap();
+    fs::create_dir_all(&format!("{}/empty", root)).unwrap();
+    fs::File::create(&format!("{}/static/css/app.css", root)).unwrap().write_all(b"body { margin: 0 }").unwrap();
+    fs::File::create(&format!("{}/index.html", root)).unwrap().write_all(b"<html></html>").unwrap();
+    fs::File::create(&format!("{}/run.sh", root)).unwrap().write_all(b"#!/bin/sh\n").unwrap();
+    fs::set_permissions(&format!("{}/run.sh", root), fs::Permissions::from_mode(0o750)).unwrap();
+    fs::set_permissions(&format!("{}/empty", root), fs::Permissions::from_mode(0o700)).unwrap();
+    ::std::os::unix::fs::symlink("static/css", &format!("{}/css", root)).unwrap();
+    fs::copy("/proc/self/exe", exe).unwrap();
+
+    {
+        let mut e = AutoEmbed::new(ExecPath::File(PathBuf::from(exe))).unwrap();
+        pack_dir(&mut e, Path::new(root)).unwrap();
+    }
+    let vfs = Vfs::new(ExecPath::File(PathBuf::from(exe))).unwrap();
+
+    // listing
+    assert!(vfs.read_dir("/").unwrap() == vec!["css".to_string(), "empty".to_string(), "index.html".to_string(),
+                                               "run.sh".to_string(), "static".to_string()]);
+    assert!(vfs.read_dir("/static").unwrap() == vec!["css".to_string()]);
+    assert!(vfs.read_dir("static/css/").unwrap() == vec!["app.css".to_string()]);
+    assert!(vfs.read_dir("/css").unwrap() == vec!["app.css".to_string()]);
+    assert!(vfs.read_dir("/empty").unwrap().len() == 0);
+    assert!(vfs.read_dir("/index.html").is_err());
+    assert!(vfs.read_dir("/missing").unwrap_err().kind() == io::ErrorKind::NotFound);
+
+    // metadata
+    let m = vfs.metadata("/static/css/app.css").unwrap();
+    assert!(m.is_file() && m.len() == 18 && m.mode() == fs::metadata(&format!("{}/index.html", root)).unwrap().permissions().mode() as u32 & 0o7777);
+    assert!(m.modified().is_some());
+    assert!(vfs.metadata("/run.sh").unwrap().mode() == 0o750);
+    assert!(vfs.metadata("/empty").unwrap().is_dir() && vfs.metadata("/empty").unwrap().mode() == 0o700);
+    assert!(vfs.metadata("/").unwrap().is_dir());
+    assert!(vfs.metadata("/css").unwrap().is_symlink());
+    assert!(vfs.read_link("/css").unwrap() == "static/css");
+    assert!(vfs.read_link("/run.sh").is_err());
+    assert!(!vfs.exists("/static/app.css"));
+    assert!(!vfs.exists("/index.html/x"));
+
+    // reading and seeking
+    assert!(vfs.read("/static/./css/../css/app.css").unwrap() == b"body { margin: 0 }");
+    assert!(vfs.read("/css/app.css").unwrap() == b"body { margin: 0 }");
+    let mut f = vfs.open("/static/css/app.css").unwrap();
+    let mut g = vfs.open("/index.html").unwrap();
+    let mut b = [0u8; 4];
+    f.seek(SeekFrom::Start(7)).unwrap();
+    assert!(f.read(&mut b).unwrap() == 4);
+    assert!(&b == b"marg");
+    assert!(g.read(&mut b).unwrap() == 4);
+    assert!(&b == b"<htm");
+    f.seek(SeekFrom::End(-2)).unwrap();
+    let mut rest: Vec<u8> = vec![];
+    f.read_to_end(&mut rest).unwrap();
+    assert!(rest == b" }");
+    f.seek(SeekFrom::Current(10)).unwrap();
+    assert!(f.read(&mut b).unwrap() == 0);
+    assert!(f.seek(SeekFrom::Current(-100)).is_err());
+    assert!(vfs.open("/static").is_err());
+    assert!(vfs.open("/missing").err().unwrap().kind() == io::ErrorKind::NotFound);
+
+    // plain resources are files with default modes
+    {
+        let mut e = AutoEmbed::new(ExecPath::File(PathBuf::from(exe))).unwrap();
+        e.strip().unwrap();
+        e.insert("docs/readme", b"hi").unwrap();
+    }
+    let vfs = Vfs::new(ExecPath::File(PathBuf::from(exe))).unwrap();
+    assert!(vfs.read_dir("/").unwrap() == vec!["docs".to_string()]);
+    let m = vfs.metadata("/docs/readme").unwrap();
+    assert!(m.is_file() && m.len() == 2 && m.mode() == 0o644 && m.modified().is_none());
+    assert!(vfs.read("docs/readme").unwrap() == b"hi");
+
+    fs::remove_dir_all(root).unwrap();
+    fs::remove_file(exe).unwrap();
+}
+
+#[test]
+fn test_extract() {
+    use std::io::Write;
+    use std::error::Error;
+    use std::os::unix::fs::MetadataExt;
+
+    let root = "/tmp/test_ulc11_extract_tree";
+    let out = "/tmp/test_ulc11_extract_out";
+    let exe = "/tmp/test_ulc11_extract";
+    for d in [root, out].iter() {
+        let _ = fs::remove_dir_all(d);
+    }
+    let _ = fs::remove_file(exe);
+    let stagings = || fs::read_dir("/tmp").unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .filter(|n| n.starts_with(".test_ulc11_extract_out.ulc11-"))
+        .count();
+    fs::create_dir_all(&format!("{}/bin", root)).unwrap();
+    fs::create_dir_all(&format!("{}/share/locked", root)).unwrap();
+    fs::File::create(&format!("{}/bin/helper", root)).unwrap().write_all(b"#!/bin/sh\necho help\n").unwrap();
+    fs::File::create(&format!("{}/share/data", root)).unwrap().write_all(b"data").unwrap();
+    fs::File::create(&format!("{}/share/locked/key", root)).unwrap().write_all(b"key").unwrap();
+    fs::set_permissions(&format!("{}/bin/helper", root), fs::Permissions::from_mode(0o4755)).unwrap();
+    ::std::os::unix::fs::symlink("bin/helper", &format!("{}/helper", root)).unwrap();
+    set_mtime(Path::new(&format!("{}/share/data", root)), (1000000000, 5)).unwrap();
+    set_mtime(Path::new(&format!("{}/helper", root)), (1200000000, 0)).unwrap();
+    fs::set_permissions(&format!("{}/share/locked", root), fs::Permissions::from_mode(0o500)).unwrap();
+    set_mtime(Path::new(&format!("{}/share", root)), (1100000000, 0)).unwrap();
+    fs::copy("/proc/self/exe", exe).unwrap();
+
+    let mut e = AutoEmbed::new(ExecPath::File(PathBuf::from(exe))).unwrap();
+    pack_dir(&mut e, Path::new(root)).unwrap();
+    let vfs = Vfs::with_embed(&e).unwrap();
+
+    // into a new directory
+    e.extract_to(Path::new(out), Overwrite::Fail).unwrap();
+    let read = |p: &str| {
+        let mut b: Vec<u8> = vec![];
+        fs::File::open(&format!("{}/{}", out, p)).unwrap().read_to_end(&mut b).unwrap();
+        b
+    };
+    assert!(read("bin/helper") == b"#!/bin/sh\necho help\n");
+    assert!(read("helper") == b"#!/bin/sh\necho help\n");
+    assert!(read("share/locked/key") == b"key");
+    assert!(fs::read_link(&format!("{}/helper", out)).unwrap() == Path::new("bin/helper"));
+    assert!(fs::metadata(&format!("{}/bin/helper", out)).unwrap().permissions().mode() & 0o7777 == 0o755);
+    assert!(fs::metadata(&format!("{}/share/locked", out)).unwrap().permissions().mode() & 0o777 == 0o500);
+    let m = fs::metadata(&format!("{}/share/data", out)).unwrap();
+    assert!(m.mtime() == 1000000000 && m.mtime_nsec() == 5);
+    assert!(fs::symlink_metadata(&format!("{}/helper", out)).unwrap().mtime() == 1200000000);
+    assert!(fs::metadata(&format!("{}/share", out)).unwrap().mtime() == 1100000000);
+    assert!(stagings() == 0);
+
+    // setuid bits only when asked for
+    let suid = "/tmp/test_ulc11_extract_suid";
+    let _ = fs::remove_dir_all(suid);
+    vfs.extract_to_with(Path::new(suid), Overwrite::Fail, 0o7777).unwrap();
+    assert!(fs::metadata(&format!("{}/bin/helper", suid)).unwrap().permissions().mode() & 0o7777 == 0o4755);
+    fs::set_permissions(&format!("{}/share/locked", suid), fs::Permissions::from_mode(0o700)).unwrap();
+    fs::remove_dir_all(suid).unwrap();
+
+    // into an existing one, with a directory in the way of the first
+    // staging name left alone
+    let pid = unsafe { libc::getpid() };
+    let other = format!("/tmp/.test_ulc11_extract_out.ulc11-{}-0.staging", pid);
+    fs::create_dir(&other).unwrap();
+    fs::File::create(&format!("{}/theirs", other)).unwrap().write_all(b"theirs").unwrap();
+    fs::File::create(&format!("{}/share/data", out)).unwrap().write_all(b"changed").unwrap();
+    fs::File::create(&format!("{}/mine", out)).unwrap().write_all(b"mine").unwrap();
+    fs::remove_file(&format!("{}/bin/helper", out)).unwrap();
+    let err = vfs.extract_to(Path::new(out), Overwrite::Fail).unwrap_err();
+    assert!(err.kind() == io::ErrorKind::AlreadyExists);
+    assert!(fs::metadata(&format!("{}/bin/helper", out)).is_err()); // nothing extracted
+    vfs.extract_to(Path::new(out), Overwrite::Skip).unwrap();
+    assert!(read("share/data") == b"changed");
+    assert!(read("bin/helper") == b"#!/bin/sh\necho help\n");
+    vfs.extract_to(Path::new(out), Overwrite::Replace).unwrap();
+    assert!(read("share/data") == b"data");
+    assert!(read("mine") == b"mine");
+    assert!(fs::metadata(&format!("{}/theirs", other)).unwrap().len() == 6);
+    fs::remove_dir_all(&other).unwrap();
+    assert!(stagings() == 0);
+
+    // in the way
+    fs::remove_file(&format!("{}/share/data", out)).unwrap();
+    fs::create_dir(&format!("{}/share/data", out)).unwrap();
+    assert!(vfs.extract_to(Path::new(out), Overwrite::Replace).unwrap_err().kind() == io::ErrorKind::AlreadyExists);
+
+    // out of the tree
+    ::std::os::unix::fs::symlink("../../etc", &format!("{}/bin/up", root)).unwrap();
+    pack_dir(&mut e, Path::new(root)).unwrap();
+    let vfs = Vfs::with_embed(&e).unwrap();
+    assert!(vfs.extract_to(Path::new(out), Overwrite::Replace).unwrap_err().description() == UNSAFE_PATH);
+    // through another link: p/.. is the parent of where p leads
+    fs::remove_file(&format!("{}/bin/up", root)).unwrap();
+    ::std::os::unix::fs::symlink(".", &format!("{}/p", root)).unwrap();
+    ::std::os::unix::fs::symlink("p/..", &format!("{}/t", root)).unwrap();
+    pack_dir(&mut e, Path::new(root)).unwrap();
+    let vfs = Vfs::with_embed(&e).unwrap();
+    assert!(vfs.extract_to(Path::new(out), Overwrite::Replace).unwrap_err().description() == UNSAFE_PATH);
+    assert!(fs::metadata(&format!("{}/t", out)).is_err());
+    e.strip().unwrap();
+    e.insert("../evil", b"evil").unwrap();
+    let vfs = Vfs::with_embed(&e).unwrap();
+    assert!(!vfs.exists("/evil"));
+    assert!(vfs.extract_to(Path::new(out), Overwrite::Replace).unwrap_err().description() == UNSAFE_PATH);
+    assert!(fs::metadata("/tmp/evil").is_err());
+
+    for d in [root, out].iter() {
+        fs::set_permissions(&format!("{}/share/locked", d), fs::Permissions::from_mode(0o700)).unwrap();
+        fs::remove_dir_all(d).unwrap();
+    }
+    fs::remove_file(exe).unwrap();
+}
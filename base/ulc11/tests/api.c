@@ -0,0 +1,67 @@
+#define _POSIX_SOURCE
+
+#include <stddef.h>
+#include <stdint.h>
+#include <stdbool.h>
+#include <string.h>
+#include <assert.h>
+
+#include <stdio.h>
+
+#include "ulc11.h"
+
+/* Exercises the C interface on the executable given as argument, which
+ * must not have data embeded. */
+int main(int argc, char **argv){
+    ExecBlob *e;
+    uint64_t length;
+    uint8_t *data;
+    
+    assert(argc == 2);
+    
+    /* errors are reported */
+    assert(ulc11_last_error() == NULL);
+    assert(!ulc11_new("/nonexistent/ulc11"));
+    assert(ulc11_last_error_code() == ULC11_ERR_NOT_FOUND);
+    assert(ulc11_last_error() && strlen(ulc11_last_error()) > 0);
+    
+    assert(e = ulc11_new(argv[1]));
+    assert(!ulc11_data_verify(e));
+    assert(ulc11_last_error_code() == ULC11_ERR_NO_PAYLOAD);
+    
+    /* named resources */
+    assert(ulc11_resource_insert(e, "greeting", (const uint8_t *) "hello", 5));
+    assert(ulc11_resource_insert(e, "answer", (const uint8_t[1]) {42}, 1));
+    assert(ulc11_data_verify(e));
+    
+    assert(data = ulc11_resource_names(e, &length));
+    assert(length == 16 && memcmp(data, "answer\0greeting\0", 16) == 0);
+    ulc11_data_free(data);
+    
+    assert(data = ulc11_resource_get(e, "greeting", &length));
+    assert(length == 5 && memcmp(data, "hello", 5) == 0);
+    ulc11_data_free(data);
+    
+    assert(ulc11_resource_remove(e, "greeting"));
+    assert(!ulc11_resource_get(e, "greeting", &length));
+    assert(ulc11_last_error_code() == ULC11_ERR_NO_PAYLOAD);
+    assert(!ulc11_resource_remove(e, "greeting"));
+    assert(!ulc11_resource_get(e, NULL, &length));
+    assert(ulc11_last_error_code() == ULC11_ERR_INVALID);
+    
+    /* raw blobs */
+    assert(ulc11_data_store(e, (const uint8_t *) "raw", 3));
+    assert(data = ulc11_data_load(e, &length));
+    assert(length == 3 && memcmp(data, "raw", 3) == 0);
+    ulc11_data_free(data);
+    assert(!ulc11_resource_names(e, &length));
+    assert(ulc11_last_error_code() == ULC11_ERR_INVALID);
+    
+    assert(ulc11_data_strip(e));
+    assert(data = ulc11_data_load(e, &length));
+    assert(length == 0);
+    ulc11_data_free(data);
+    
+    ulc11_free(e);
+    return 0;
+}
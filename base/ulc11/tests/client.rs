@@ -3,9 +3,11 @@ extern crate ulc11;
 
 use ulc11::ExecPath;
 use ulc11::Embed;
+use ulc11::NO_PAYLOAD;
 use ulc11::auto::AutoEmbed;
 
 use std::env::args;
+use std::error::Error;
 
 #[allow(dead_code)]
 fn main() {
@@ -14,7 +16,11 @@ fn main() {
     }
     else {
         let mut p = AutoEmbed::new(ExecPath::This).unwrap();
-        let mut d = p.load().unwrap();
+        let mut d = match p.load() {
+            Ok(d) => d,
+            Err(ref e) if e.description() == NO_PAYLOAD => vec![],
+            Err(e) => panic!("{}", e)
+        };
         if d.len() == 0 {
             d.push(0u8);
         }
@@ -117,7 +117,7 @@ mute() {
 
 cc=$(require gcc clang)
 valgrind=$(require valgrind)
-require_files "client.c" "client.rs"
+require_files "client.c" "client.rs" "api.c"
 
 cargo clean
 cargo build || die "Build unsuccesfull"
@@ -126,14 +126,18 @@ SO=$(ls ../target/libulc11*.so) || die "No shared object found in ../target"
 RLIB=$(ls ../target/libulc11*.rlib) || die "No rust library found in ../target"
 
 rustc -g -o "rust-client" -L ../target -L ../target/deps "client.rs" || die "failed to compile rust code"
-gcc -std=c99 -pedantic -g -o "c-client" "client.c" "../target/$SO" || die "failed to compile c code"
+gcc -std=c99 -pedantic -g -I ../include -o "c-client" "client.c" "../target/$SO" || die "failed to compile c code"
+gcc -std=c99 -pedantic -g -I ../include -o "c-api" "api.c" "../target/$SO" || die "failed to compile c api test"
 
 leak_check() {
-    valgrind --error-exitcode=1 --tool=memcheck "--errors-for-leak-kinds=all" "$1" || die "'$1' memory check failed"
+    valgrind --error-exitcode=1 --tool=memcheck "--errors-for-leak-kinds=all" "$@" || die "'$*' memory check failed"
 }
 leak_check "./rust-client"
 leak_check "./c-client"
 
+cp "./c-api" "./c-api-target"
+leak_check "./c-api" "./c-api-target"
+
 clean() {
     $1 "clean" || die "failed to clean '$1'"
 }
@@ -153,5 +157,6 @@ exec_iter_test "./c-client"
 
 rm "./rust-client"
 rm "./c-client"
+rm "./c-api" "./c-api-target"
 
 ok
@@ -1,138 +1,389 @@
+//! Error numbers returned by the kernel.
+//!
+//! Wrappers return `Result<T>`: `Err` holds the `Errno` a system call
+//! failed with, and `Ok` what it returned otherwise (see `check`). `Errno`s
+//! print as `strerror` does, and convert into `std::io::Error` with a
+//! matching `ErrorKind`. Calls interrupted by a signal fail with `EINTR`;
+//! `retry` repeats them.
+
+use std::io;
+use std::fmt;
+use std::error::Error;
+
+/// An error number, as in `errno(3)`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Errno(pub isize);
+
+/// The result of a system call wrapper.
+pub type Result<T> = ::std::result::Result<T, Errno>;
+
+/// Turns the raw return value `r` of a system call into a result: negative
+/// values are errors.
+pub fn check(r: isize) -> Result<isize> {
+    if r < 0 {Err(Errno(-r))} else {Ok(r)}
+}
+
+/// Calls `f` until it doesn't fail with `EINTR`.
+pub fn retry<T, F: FnMut() -> Result<T>>(mut f: F) -> Result<T> {
+    loop {
+        match f() {
+            Err(EINTR) => continue,
+            r => return r
+        }
+    }
+}
+
+impl Errno {
+    /// The name of the error number, e.g. "ENOENT". Empty for unknown
+    /// numbers.
+    pub fn name(&self) -> &'static str {
+        info(self.0).map(|i| i.0).unwrap_or("")
+    }
+
+    /// The description `strerror` gives for the error number, e.g. "No such
+    /// file or directory".
+    pub fn message(&self) -> &'static str {
+        info(self.0).map(|i| i.1).unwrap_or("Unknown error")
+    }
+
+    /// The `io::ErrorKind` the error number corresponds to.
+    pub fn kind(&self) -> io::ErrorKind {
+        use std::io::ErrorKind::*;
+        match *self {
+            ENOENT                  => NotFound,
+            EPERM | EACCES          => PermissionDenied,
+            ECONNREFUSED            => ConnectionRefused,
+            ECONNRESET              => ConnectionReset,
+            ECONNABORTED            => ConnectionAborted,
+            ENOTCONN                => NotConnected,
+            EADDRINUSE              => AddrInUse,
+            EADDRNOTAVAIL           => AddrNotAvailable,
+            EPIPE                   => BrokenPipe,
+            EEXIST                  => AlreadyExists,
+            EAGAIN                  => WouldBlock,
+            EINVAL                  => InvalidInput,
+            ETIMEDOUT               => TimedOut,
+            EINTR                   => Interrupted,
+            _                       => Other
+        }
+    }
+}
+
+impl fmt::Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match info(self.0) {
+            Some((_, m)) => write!(f, "{}", m),
+            None => write!(f, "Unknown error {}", self.0)
+        }
+    }
+}
+
+impl Error for Errno {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
+
+impl From<Errno> for io::Error {
+    fn from(e: Errno) -> io::Error {
+        io::Error::new(e.kind(), e.message(), Some(format!("{} (errno {})", e.name(), e.0)))
+    }
+}
+
+// name and description of the error number `n`
+fn info(n: isize) -> Option<(&'static str, &'static str)> {
+    Some(match Errno(n) {
+        EPERM           => ("EPERM", "Operation not permitted"),
+        ENOENT          => ("ENOENT", "No such file or directory"),
+        ESRCH           => ("ESRCH", "No such process"),
+        EINTR           => ("EINTR", "Interrupted system call"),
+        EIO             => ("EIO", "Input/output error"),
+        ENXIO           => ("ENXIO", "No such device or address"),
+        E2BIG           => ("E2BIG", "Argument list too long"),
+        ENOEXEC         => ("ENOEXEC", "Exec format error"),
+        EBADF           => ("EBADF", "Bad file descriptor"),
+        ECHILD          => ("ECHILD", "No child processes"),
+        EAGAIN          => ("EAGAIN", "Resource temporarily unavailable"),
+        ENOMEM          => ("ENOMEM", "Cannot allocate memory"),
+        EACCES          => ("EACCES", "Permission denied"),
+        EFAULT          => ("EFAULT", "Bad address"),
+        ENOTBLK         => ("ENOTBLK", "Block device required"),
+        EBUSY           => ("EBUSY", "Device or resource busy"),
+        EEXIST          => ("EEXIST", "File exists"),
+        EXDEV           => ("EXDEV", "Invalid cross-device link"),
+        ENODEV          => ("ENODEV", "No such device"),
+        ENOTDIR         => ("ENOTDIR", "Not a directory"),
+        EISDIR          => ("EISDIR", "Is a directory"),
+        EINVAL          => ("EINVAL", "Invalid argument"),
+        ENFILE          => ("ENFILE", "Too many open files in system"),
+        EMFILE          => ("EMFILE", "Too many open files"),
+        ENOTTY          => ("ENOTTY", "Inappropriate ioctl for device"),
+        ETXTBSY         => ("ETXTBSY", "Text file busy"),
+        EFBIG           => ("EFBIG", "File too large"),
+        ENOSPC          => ("ENOSPC", "No space left on device"),
+        ESPIPE          => ("ESPIPE", "Illegal seek"),
+        EROFS           => ("EROFS", "Read-only file system"),
+        EMLINK          => ("EMLINK", "Too many links"),
+        EPIPE           => ("EPIPE", "Broken pipe"),
+        EDOM            => ("EDOM", "Numerical argument out of domain"),
+        ERANGE          => ("ERANGE", "Numerical result out of range"),
+        EDEADLK         => ("EDEADLK", "Resource deadlock avoided"),
+        ENAMETOOLONG    => ("ENAMETOOLONG", "File name too long"),
+        ENOLCK          => ("ENOLCK", "No locks available"),
+        ENOSYS          => ("ENOSYS", "Function not implemented"),
+        ENOTEMPTY       => ("ENOTEMPTY", "Directory not empty"),
+        ELOOP           => ("ELOOP", "Too many levels of symbolic links"),
+        ENOMSG          => ("ENOMSG", "No message of desired type"),
+        EIDRM           => ("EIDRM", "Identifier removed"),
+        ECHRNG          => ("ECHRNG", "Channel number out of range"),
+        EL2NSYNC        => ("EL2NSYNC", "Level 2 not synchronized"),
+        EL3HLT          => ("EL3HLT", "Level 3 halted"),
+        EL3RST          => ("EL3RST", "Level 3 reset"),
+        ELNRNG          => ("ELNRNG", "Link number out of range"),
+        EUNATCH         => ("EUNATCH", "Protocol driver not attached"),
+        ENOCSI          => ("ENOCSI", "No CSI structure available"),
+        EL2HLT          => ("EL2HLT", "Level 2 halted"),
+        EBADE           => ("EBADE", "Invalid exchange"),
+        EBADR           => ("EBADR", "Invalid request descriptor"),
+        EXFULL          => ("EXFULL", "Exchange full"),
+        ENOANO          => ("ENOANO", "No anode"),
+        EBADRQC         => ("EBADRQC", "Invalid request code"),
+        EBADSLT         => ("EBADSLT", "Invalid slot"),
+        EBFONT          => ("EBFONT", "Bad font file format"),
+        ENOSTR          => ("ENOSTR", "Device not a stream"),
+        ENODATA         => ("ENODATA", "No data available"),
+        ETIME           => ("ETIME", "Timer expired"),
+        ENOSR           => ("ENOSR", "Out of streams resources"),
+        ENONET          => ("ENONET", "Machine is not on the network"),
+        ENOPKG          => ("ENOPKG", "Package not installed"),
+        EREMOTE         => ("EREMOTE", "Object is remote"),
+        ENOLINK         => ("ENOLINK", "Link has been severed"),
+        EADV            => ("EADV", "Advertise error"),
+        ESRMNT          => ("ESRMNT", "Srmount error"),
+        ECOMM           => ("ECOMM", "Communication error on send"),
+        EPROTO          => ("EPROTO", "Protocol error"),
+        EMULTIHOP       => ("EMULTIHOP", "Multihop attempted"),
+        EDOTDOT         => ("EDOTDOT", "RFS specific error"),
+        EBADMSG         => ("EBADMSG", "Bad message"),
+        EOVERFLOW       => ("EOVERFLOW", "Value too large for defined data type"),
+        ENOTUNIQ        => ("ENOTUNIQ", "Name not unique on network"),
+        EBADFD          => ("EBADFD", "File descriptor in bad state"),
+        EREMCHG         => ("EREMCHG", "Remote address changed"),
+        ELIBACC         => ("ELIBACC", "Can not access a needed shared library"),
+        ELIBBAD         => ("ELIBBAD", "Accessing a corrupted shared library"),
+        ELIBSCN         => ("ELIBSCN", ".lib section in a.out corrupted"),
+        ELIBMAX         => ("ELIBMAX", "Attempting to link in too many shared libraries"),
+        ELIBEXEC        => ("ELIBEXEC", "Cannot exec a shared library directly"),
+        EILSEQ          => ("EILSEQ", "Invalid or incomplete multibyte or wide character"),
+        ERESTART        => ("ERESTART", "Interrupted system call should be restarted"),
+        ESTRPIPE        => ("ESTRPIPE", "Streams pipe error"),
+        EUSERS          => ("EUSERS", "Too many users"),
+        ENOTSOCK        => ("ENOTSOCK", "Socket operation on non-socket"),
+        EDESTADDRREQ    => ("EDESTADDRREQ", "Destination address required"),
+        EMSGSIZE        => ("EMSGSIZE", "Message too long"),
+        EPROTOTYPE      => ("EPROTOTYPE", "Protocol wrong type for socket"),
+        ENOPROTOOPT     => ("ENOPROTOOPT", "Protocol not available"),
+        EPROTONOSUPPORT => ("EPROTONOSUPPORT", "Protocol not supported"),
+        ESOCKTNOSUPPORT => ("ESOCKTNOSUPPORT", "Socket type not supported"),
+        EOPNOTSUPP      => ("EOPNOTSUPP", "Operation not supported"),
+        EPFNOSUPPORT    => ("EPFNOSUPPORT", "Protocol family not supported"),
+        EAFNOSUPPORT    => ("EAFNOSUPPORT", "Address family not supported by protocol"),
+        EADDRINUSE      => ("EADDRINUSE", "Address already in use"),
+        EADDRNOTAVAIL   => ("EADDRNOTAVAIL", "Cannot assign requested address"),
+        ENETDOWN        => ("ENETDOWN", "Network is down"),
+        ENETUNREACH     => ("ENETUNREACH", "Network is unreachable"),
+        ENETRESET       => ("ENETRESET", "Network dropped connection on reset"),
+        ECONNABORTED    => ("ECONNABORTED", "Software caused connection abort"),
+        ECONNRESET      => ("ECONNRESET", "Connection reset by peer"),
+        ENOBUFS         => ("ENOBUFS", "No buffer space available"),
+        EISCONN         => ("EISCONN", "Transport endpoint is already connected"),
+        ENOTCONN        => ("ENOTCONN", "Transport endpoint is not connected"),
+        ESHUTDOWN       => ("ESHUTDOWN", "Cannot send after transport endpoint shutdown"),
+        ETOOMANYREFS    => ("ETOOMANYREFS", "Too many references: cannot splice"),
+        ETIMEDOUT       => ("ETIMEDOUT", "Connection timed out"),
+        ECONNREFUSED    => ("ECONNREFUSED", "Connection refused"),
+        EHOSTDOWN       => ("EHOSTDOWN", "Host is down"),
+        EHOSTUNREACH    => ("EHOSTUNREACH", "No route to host"),
+        EALREADY        => ("EALREADY", "Operation already in progress"),
+        EINPROGRESS     => ("EINPROGRESS", "Operation now in progress"),
+        ESTALE          => ("ESTALE", "Stale file handle"),
+        EUCLEAN         => ("EUCLEAN", "Structure needs cleaning"),
+        ENOTNAM         => ("ENOTNAM", "Not a XENIX named type file"),
+        ENAVAIL         => ("ENAVAIL", "No XENIX semaphores available"),
+        EISNAM          => ("EISNAM", "Is a named type file"),
+        EREMOTEIO       => ("EREMOTEIO", "Remote I/O error"),
+        EDQUOT          => ("EDQUOT", "Disk quota exceeded"),
+        ENOMEDIUM       => ("ENOMEDIUM", "No medium found"),
+        EMEDIUMTYPE     => ("EMEDIUMTYPE", "Wrong medium type"),
+        ECANCELED       => ("ECANCELED", "Operation canceled"),
+        ENOKEY          => ("ENOKEY", "Required key not available"),
+        EKEYEXPIRED     => ("EKEYEXPIRED", "Key has expired"),
+        EKEYREVOKED     => ("EKEYREVOKED", "Key has been revoked"),
+        EKEYREJECTED    => ("EKEYREJECTED", "Key was rejected by service"),
+        EOWNERDEAD      => ("EOWNERDEAD", "Owner died"),
+        ENOTRECOVERABLE => ("ENOTRECOVERABLE", "State not recoverable"),
+        ERFKILL         => ("ERFKILL", "Operation not possible due to RF-kill"),
+        EHWPOISON       => ("EHWPOISON", "Memory page has hardware error"),
+        _ => return None
+    })
+}
+
+
 // asm-generic/errno-base.h
-pub const EPERM: isize         = 1;
-pub const ENOENT: isize        = 2;
-pub const ESRCH: isize         = 3;
-pub const EINTR: isize         = 4;
-pub const EIO: isize           = 5;
-pub const ENXIO: isize         = 6;
-pub const E2BIG: isize         = 7;
-pub const ENOEXEC: isize       = 8;
-pub const EBADF: isize         = 9;
-pub const ECHILD: isize        = 10;
-pub const EAGAIN: isize        = 11;
-pub const ENOMEM: isize        = 12;
-pub const EACCES: isize        = 13;
-pub const EFAULT: isize        = 14;
-pub const ENOTBLK: isize       = 15;
-pub const EBUSY: isize         = 16;
-pub const EEXIST: isize        = 17;
-pub const EXDEV: isize         = 18;
-pub const ENODEV: isize        = 19;
-pub const ENOTDIR: isize       = 20;
-pub const EISDIR: isize        = 21;
-pub const EINVAL: isize        = 22;
-pub const ENFILE: isize        = 23;
-pub const EMFILE: isize        = 24;
-pub const ENOTTY: isize        = 25;
-pub const ETXTBSY: isize       = 26;
-pub const EFBIG: isize         = 27;
-pub const ENOSPC: isize        = 28;
-pub const ESPIPE: isize        = 29;
-pub const EROFS: isize         = 30;
-pub const EMLINK: isize        = 31;
-pub const EPIPE: isize         = 32;
-pub const EDOM: isize          = 33;
-pub const ERANGE: isize        = 34;
+pub const EPERM: Errno         = Errno(1);
+pub const ENOENT: Errno        = Errno(2);
+pub const ESRCH: Errno         = Errno(3);
+pub const EINTR: Errno         = Errno(4);
+pub const EIO: Errno           = Errno(5);
+pub const ENXIO: Errno         = Errno(6);
+pub const E2BIG: Errno         = Errno(7);
+pub const ENOEXEC: Errno       = Errno(8);
+pub const EBADF: Errno         = Errno(9);
+pub const ECHILD: Errno        = Errno(10);
+pub const EAGAIN: Errno        = Errno(11);
+pub const ENOMEM: Errno        = Errno(12);
+pub const EACCES: Errno        = Errno(13);
+pub const EFAULT: Errno        = Errno(14);
+pub const ENOTBLK: Errno       = Errno(15);
+pub const EBUSY: Errno         = Errno(16);
+pub const EEXIST: Errno        = Errno(17);
+pub const EXDEV: Errno         = Errno(18);
+pub const ENODEV: Errno        = Errno(19);
+pub const ENOTDIR: Errno       = Errno(20);
+pub const EISDIR: Errno        = Errno(21);
+pub const EINVAL: Errno        = Errno(22);
+pub const ENFILE: Errno        = Errno(23);
+pub const EMFILE: Errno        = Errno(24);
+pub const ENOTTY: Errno        = Errno(25);
+pub const ETXTBSY: Errno       = Errno(26);
+pub const EFBIG: Errno         = Errno(27);
+pub const ENOSPC: Errno        = Errno(28);
+pub const ESPIPE: Errno        = Errno(29);
+pub const EROFS: Errno         = Errno(30);
+pub const EMLINK: Errno        = Errno(31);
+pub const EPIPE: Errno         = Errno(32);
+pub const EDOM: Errno          = Errno(33);
+pub const ERANGE: Errno        = Errno(34);
 
 
 // asm-generic/errno.h
-pub const EDEADLK:isize            = 35;
-pub const ENAMETOOLONG:isize       = 36;
-pub const ENOLCK:isize             = 37;
-pub const ENOSYS:isize             = 38;
-pub const ENOTEMPTY:isize          = 39;
-pub const ELOOP:isize              = 40;
-pub const EWOULDBLOCK:isize        = EAGAIN;
-pub const ENOMSG:isize             = 42;
-pub const EIDRM:isize              = 43;
-pub const ECHRNG:isize             = 44;
-pub const EL2NSYNC:isize           = 45;
-pub const EL3HLT:isize             = 46;
-pub const EL3RST:isize             = 47;
-pub const ELNRNG:isize             = 48;
-pub const EUNATCH:isize            = 49;
-pub const ENOCSI:isize             = 50;
-pub const EL2HLT:isize             = 51;
-pub const EBADE:isize              = 52;
-pub const EBADR:isize              = 53;
-pub const EXFULL:isize             = 54;
-pub const ENOANO:isize             = 55;
-pub const EBADRQC:isize            = 56;
-pub const EBADSLT:isize            = 57;
-pub const EDEADLOCK:isize          = EDEADLK;
-pub const EBFONT:isize             = 59;
-pub const ENOSTR:isize             = 60;
-pub const ENODATA:isize            = 61;
-pub const ETIME:isize              = 62;
-pub const ENOSR:isize              = 63;
-pub const ENONET:isize             = 64;
-pub const ENOPKG:isize             = 65;
-pub const EREMOTE:isize            = 66;
-pub const ENOLINK:isize            = 67;
-pub const EADV:isize               = 68;
-pub const ESRMNT:isize             = 69;
-pub const ECOMM:isize              = 70;
-pub const EPROTO:isize             = 71;
-pub const EMULTIHOP:isize          = 72;
-pub const EDOTDOT:isize            = 73;
-pub const EBADMSG:isize            = 74;
-pub const EOVERFLOW:isize          = 75;
-pub const ENOTUNIQ:isize           = 76;
-pub const EBADFD:isize             = 77;
-pub const EREMCHG:isize            = 78;
-pub const ELIBACC:isize            = 79;
-pub const ELIBBAD:isize            = 80;
-pub const ELIBSCN:isize            = 81;
-pub const ELIBMAX:isize            = 82;
-pub const ELIBEXEC:isize           = 83;
-pub const EILSEQ:isize             = 84;
-pub const ERESTART:isize           = 85;
-pub const ESTRPIPE:isize           = 86;
-pub const EUSERS:isize             = 87;
-pub const ENOTSOCK:isize           = 88;
-pub const EDESTADDRREQ:isize       = 89;
-pub const EMSGSIZE:isize           = 90;
-pub const EPROTOTYPE:isize         = 91;
-pub const ENOPROTOOPT:isize        = 92;
-pub const EPROTONOSUPPORT:isize    = 93;
-pub const ESOCKTNOSUPPORT:isize    = 94;
-pub const EOPNOTSUPP:isize         = 95;
-pub const EPFNOSUPPORT:isize       = 96;
-pub const EAFNOSUPPORT:isize       = 97;
-pub const EADDRINUSE:isize         = 98;
-pub const EADDRNOTAVAIL:isize      = 99;
-pub const ENETDOWN:isize           = 100;
-pub const ENETUNREACH:isize        = 101;
-pub const ENETRESET:isize          = 102;
-pub const ECONNABORTED:isize       = 103;
-pub const ECONNRESET:isize         = 104;
-pub const ENOBUFS:isize            = 105;
-pub const EISCONN:isize            = 106;
-pub const ENOTCONN:isize           = 107;
-pub const ESHUTDOWN:isize          = 108;
-pub const ETOOMANYREFS:isize       = 109;
-pub const ETIMEDOUT:isize          = 110;
-pub const ECONNREFUSED:isize       = 111;
-pub const EHOSTDOWN:isize          = 112;
-pub const EHOSTUNREACH:isize       = 113;
-pub const EALREADY:isize           = 114;
-pub const EINPROGRESS:isize        = 115;
-pub const ESTALE:isize             = 116;
-pub const EUCLEAN:isize            = 117;
-pub const ENOTNAM:isize            = 118;
-pub const ENAVAIL:isize            = 119;
-pub const EISNAM:isize             = 120;
-pub const EREMOTEIO:isize          = 121;
-pub const EDQUOT:isize             = 122;
-pub const ENOMEDIUM:isize          = 123;
-pub const EMEDIUMTYPE:isize        = 124;
-pub const ECANCELED:isize          = 125;
-pub const ENOKEY:isize             = 126;
-pub const EKEYEXPIRED:isize        = 127;
-pub const EKEYREVOKED:isize        = 128;
-pub const EKEYREJECTED:isize       = 129;
-pub const EOWNERDEAD:isize         = 130;
-pub const ENOTRECOVERABLE:isize    = 131;
-pub const ERFKILL:isize            = 132;
-pub const EHWPOISON:isize          = 133;
+pub const EDEADLK: Errno            = Errno(35);
+pub const ENAMETOOLONG: Errno       = Errno(36);
+pub const ENOLCK: Errno             = Errno(37);
+pub const ENOSYS: Errno             = Errno(38);
+pub const ENOTEMPTY: Errno          = Errno(39);
+pub const ELOOP: Errno              = Errno(40);
+pub const EWOULDBLOCK: Errno        = EAGAIN;
+pub const ENOMSG: Errno             = Errno(42);
+pub const EIDRM: Errno              = Errno(43);
+pub const ECHRNG: Errno             = Errno(44);
+pub const EL2NSYNC: Errno           = Errno(45);
+pub const EL3HLT: Errno             = Errno(46);
+pub const EL3RST: Errno             = Errno(47);
+pub const ELNRNG: Errno             = Errno(48);
+pub const EUNATCH: Errno            = Errno(49);
+pub const ENOCSI: Errno             = Errno(50);
+pub const EL2HLT: Errno             = Errno(51);
+pub const EBADE: Errno              = Errno(52);
+pub const EBADR: Errno              = Errno(53);
+pub const EXFULL: Errno             = Errno(54);
+pub const ENOANO: Errno             = Errno(55);
+pub const EBADRQC: Errno            = Errno(56);
+pub const EBADSLT: Errno            = Errno(57);
+pub const EDEADLOCK: Errno          = EDEADLK;
+pub const EBFONT: Errno             = Errno(59);
+pub const ENOSTR: Errno             = Errno(60);
+pub const ENODATA: Errno            = Errno(61);
+pub const ETIME: Errno              = Errno(62);
+pub const ENOSR: Errno              = Errno(63);
+pub const ENONET: Errno             = Errno(64);
+pub const ENOPKG: Errno             = Errno(65);
+pub const EREMOTE: Errno            = Errno(66);
+pub const ENOLINK: Errno            = Errno(67);
+pub const EADV: Errno               = Errno(68);
+pub const ESRMNT: Errno             = Errno(69);
+pub const ECOMM: Errno              = Errno(70);
+pub const EPROTO: Errno             = Errno(71);
+pub const EMULTIHOP: Errno          = Errno(72);
+pub const EDOTDOT: Errno            = Errno(73);
+pub const EBADMSG: Errno            = Errno(74);
+pub const EOVERFLOW: Errno          = Errno(75);
+pub const ENOTUNIQ: Errno           = Errno(76);
+pub const EBADFD: Errno             = Errno(77);
+pub const EREMCHG: Errno            = Errno(78);
+pub const ELIBACC: Errno            = Errno(79);
+pub const ELIBBAD: Errno            = Errno(80);
+pub const ELIBSCN: Errno            = Errno(81);
+pub const ELIBMAX: Errno            = Errno(82);
+pub const ELIBEXEC: Errno           = Errno(83);
+pub const EILSEQ: Errno             = Errno(84);
+pub const ERESTART: Errno           = Errno(85);
+pub const ESTRPIPE: Errno           = Errno(86);
+pub const EUSERS: Errno             = Errno(87);
+pub const ENOTSOCK: Errno           = Errno(88);
+pub const EDESTADDRREQ: Errno       = Errno(89);
+pub const EMSGSIZE: Errno           = Errno(90);
+pub const EPROTOTYPE: Errno         = Errno(91);
+pub const ENOPROTOOPT: Errno        = Errno(92);
+pub const EPROTONOSUPPORT: Errno    = Errno(93);
+pub const ESOCKTNOSUPPORT: Errno    = Errno(94);
+pub const EOPNOTSUPP: Errno         = Errno(95);
+pub const EPFNOSUPPORT: Errno       = Errno(96);
+pub const EAFNOSUPPORT: Errno       = Errno(97);
+pub const EADDRINUSE: Errno         = Errno(98);
+pub const EADDRNOTAVAIL: Errno      = Errno(99);
+pub const ENETDOWN: Errno           = Errno(100);
+pub const ENETUNREACH: Errno        = Errno(101);
+pub const ENETRESET: Errno          = Errno(102);
+pub const ECONNABORTED: Errno       = Errno(103);
+pub const ECONNRESET: Errno         = Errno(104);
+pub const ENOBUFS: Errno            = Errno(105);
+pub const EISCONN: Errno            = Errno(106);
+pub const ENOTCONN: Errno           = Errno(107);
+pub const ESHUTDOWN: Errno          = Errno(108);
+pub const ETOOMANYREFS: Errno       = Errno(109);
+pub const ETIMEDOUT: Errno          = Errno(110);
+pub const ECONNREFUSED: Errno       = Errno(111);
+pub const EHOSTDOWN: Errno          = Errno(112);
+pub const EHOSTUNREACH: Errno       = Errno(113);
+pub const EALREADY: Errno           = Errno(114);
+pub const EINPROGRESS: Errno        = Errno(115);
+pub const ESTALE: Errno             = Errno(116);
+pub const EUCLEAN: Errno            = Errno(117);
+pub const ENOTNAM: Errno            = Errno(118);
+pub const ENAVAIL: Errno            = Errno(119);
+pub const EISNAM: Errno             = Errno(120);
+pub const EREMOTEIO: Errno          = Errno(121);
+pub const EDQUOT: Errno             = Errno(122);
+pub const ENOMEDIUM: Errno          = Errno(123);
+pub const EMEDIUMTYPE: Errno        = Errno(124);
+pub const ECANCELED: Errno          = Errno(125);
+pub const ENOKEY: Errno             = Errno(126);
+pub const EKEYEXPIRED: Errno        = Errno(127);
+pub const EKEYREVOKED: Errno        = Errno(128);
+pub const EKEYREJECTED: Errno       = Errno(129);
+pub const EOWNERDEAD: Errno         = Errno(130);
+pub const ENOTRECOVERABLE: Errno    = Errno(131);
+pub const ERFKILL: Errno            = Errno(132);
+pub const EHWPOISON: Errno          = Errno(133);
+
+
+#[test]
+fn test_errno() {
+    assert!(check(3) == Ok(3));
+    assert!(check(-2) == Err(ENOENT));
+    assert!(format!("{}", ENOENT) == "No such file or directory");
+    assert!(format!("{}", Errno(4095)) == "Unknown error 4095");
+    assert!(EWOULDBLOCK.name() == "EAGAIN" && EHWPOISON.name() == "EHWPOISON");
+    assert!(ENOENT.description() == "No such file or directory");
+
+    let e: io::Error = From::from(EACCES);
+    assert!(e.kind() == io::ErrorKind::PermissionDenied);
+    assert!(e.description() == "Permission denied");
+    let e: io::Error = From::from(ENOSPC);
+    assert!(e.kind() == io::ErrorKind::Other);
 
+    let mut n = 0;
+    assert!(retry(|| {n += 1; if n < 3 {Err(EINTR)} else {Ok(n)}}) == Ok(3));
+    assert!(retry(|| -> Result<()> {Err(EBADF)}) == Err(EBADF));
+}
@@ -0,0 +1,237 @@
+//! Owned file descriptors.
+//!
+//! An `Fd` closes its descriptor when dropped, so early returns can't leak
+//! it. `BorrowedFd` is a view of a descriptor owned elsewhere, valid for as
+//! long as its owner. Both read, write and seek like `std::fs::File`; the
+//! free functions in `fs::file` and `io` remain the raw layer underneath.
+
+use std::io;
+use std::marker::PhantomData;
+use std::os::unix::io::RawFd;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::io::FromRawFd;
+use std::os::unix::io::IntoRawFd;
+use errno::Result;
+use fs::file;
+use fs::stat;
+use ::File;
+use ::Dir;
+
+
+/// An open file descriptor, closed when dropped.
+pub struct Fd {
+    fd: File
+}
+
+/// A file descriptor borrowed from an `Fd`, or from whatever else owns it.
+#[derive(Clone, Copy)]
+pub struct BorrowedFd<'a> {
+    fd: File,
+    _owner: PhantomData<&'a Fd>
+}
+
+impl Fd {
+    /// Opens `pathname`, as `fs::file::open` does.
+    pub fn open(pathname: &str, flags: usize, mode: usize) -> Result<Fd> {
+        file::open(pathname, flags, mode).map(|fd| Fd {fd: fd})
+    }
+
+    /// Opens `pathname` relative to `dirfd`, as `fs::file::openat` does.
+    pub fn openat(dirfd: Dir, pathname: &str, flags: usize, mode: usize) -> Result<Fd> {
+        file::openat(dirfd, pathname, flags, mode).map(|fd| Fd {fd: fd})
+    }
+
+    /// The raw descriptor, which stays owned by `self`.
+    pub fn raw(&self) -> File {
+        self.fd
+    }
+
+    /// A view of the descriptor that can't outlive `self`.
+    pub fn borrow(&self) -> BorrowedFd {
+        BorrowedFd {fd: self.fd, _owner: PhantomData}
+    }
+
+    /// Closes the descriptor, reporting the errors that dropping it ignores.
+    pub fn close(self) -> Result<()> {
+        file::close(self.into_raw_fd() as File)
+    }
+
+    pub fn fsync(&self) -> Result<()> {
+        file::fsync(self.fd)
+    }
+
+    pub fn fdatasync(&self) -> Result<()> {
+        file::fdatasync(self.fd)
+    }
+
+    pub fn ftruncate(&self, length: u64) -> Result<()> {
+        file::ftruncate(self.fd, length)
+    }
+
+    pub fn flock(&self, operation: usize) -> Result<()> {
+        file::flock(self.fd, operation)
+    }
+
+    pub fn fstat(&self) -> Result<stat::Stat> {
+        stat::fstat(self.fd)
+    }
+}
+
+impl Drop for Fd {
+    fn drop(&mut self) {
+        let _ = file::close(self.fd);
+    }
+}
+
+impl<'a> BorrowedFd<'a> {
+    /// Borrows the raw descriptor `fd`. The caller must keep it open for
+    /// the lifetime of the view.
+    pub unsafe fn from_raw(fd: File) -> BorrowedFd<'a> {
+        BorrowedFd {fd: fd, _owner: PhantomData}
+    }
+
+    /// The raw descriptor.
+    pub fn raw(&self) -> File {
+        self.fd
+    }
+}
+
+impl AsRawFd for Fd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd as RawFd
+    }
+}
+
+impl FromRawFd for Fd {
+    unsafe fn from_raw_fd(fd: RawFd) -> Fd {
+        Fd {fd: fd as File}
+    }
+}
+
+impl IntoRawFd for Fd {
+    fn into_raw_fd(self) -> RawFd {
+        let fd = self.fd;
+        ::std::mem::forget(self);
+        fd as RawFd
+    }
+}
+
+impl<'a> AsRawFd for BorrowedFd<'a> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd as RawFd
+    }
+}
+
+
+// the std traits, on raw descriptors
+
+fn read(fd: File, buf: &mut [u8]) -> io::Result<usize> {
+    Ok(try!(::io::read(fd, buf)))
+}
+
+fn write(fd: File, buf: &[u8]) -> io::Result<usize> {
+    Ok(try!(::io::write(fd, buf)))
+}
+
+fn seek(fd: File, pos: io::SeekFrom) -> io::Result<u64> {
+    use fs::flags::*;
+    let (offset, whence) = match pos {
+        io::SeekFrom::Start(u) => (u as isize, SEEK_SET),
+        io::SeekFrom::End(i) => (i as isize, SEEK_END),
+        io::SeekFrom::Current(i) => (i as isize, SEEK_CUR)
+    };
+    Ok(try!(file::lseek(fd, offset, whence)))
+}
+
+impl io::Read for Fd {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        read(self.fd, buf)
+    }
+}
+
+impl io::Write for Fd {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        write(self.fd, buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for Fd {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        seek(self.fd, pos)
+    }
+}
+
+impl<'a> io::Read for BorrowedFd<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        read(self.fd, buf)
+    }
+}
+
+impl<'a> io::Write for BorrowedFd<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        write(self.fd, buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> io::Seek for BorrowedFd<'a> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        seek(self.fd, pos)
+    }
+}
+
+
+#[test]
+fn test_fd() {
+    use std::io::Read;
+    use std::io::Write;
+    use std::io::Seek;
+    use std::io::SeekFrom;
+    use errno::*;
+    use fs::flags::*;
+    use fs::ln;
+
+    let path = "/tmp/test_ulc12_fd";
+    let _ = ln::unlink(path);
+    assert!(Fd::open(path, O_RDONLY, 0).err() == Some(ENOENT));
+
+    let mut f = Fd::open(path, O_RDWR|O_CREAT, 0o640).unwrap();
+    f.write_all(b"hello world").unwrap();
+    assert!(f.seek(SeekFrom::Start(6)).unwrap() == 6);
+    let mut s = String::new();
+    f.borrow().read_to_string(&mut s).unwrap();
+    assert!(s == "world");
+    assert!(f.borrow().seek(SeekFrom::End(-5)).unwrap() == 6);
+    f.borrow().write_all(b"there").unwrap();
+    assert!(f.fstat().unwrap().st_size == 11);
+    f.ftruncate(5).unwrap();
+    assert!(f.fstat().unwrap().st_size == 5);
+
+    drop(f);
+
+    // dropping closes: the reading end of a pipe sees the end of the stream
+    // once its only writer is gone. Closed numbers aren't looked at, other
+    // tests may have reused them.
+    let (mut r, w) = ::process::pipe2(O_CLOEXEC).unwrap();
+    drop(w);
+    assert!(r.read(&mut [0u8; 1]).unwrap() == 0);
+
+    // ownership can be given up and taken back
+    let (mut r, w) = ::process::pipe2(O_CLOEXEC).unwrap();
+    let fd = w.into_raw_fd();
+    assert!(stat::fstat(fd as File).is_ok());
+    let w = unsafe { Fd::from_raw_fd(fd) };
+    assert!(w.as_raw_fd() == fd && w.borrow().as_raw_fd() == fd);
+    w.borrow().write_all(b"x").unwrap();
+    assert!(w.close().is_ok());
+    let mut s = String::new();
+    r.read_to_string(&mut s).unwrap();
+    assert!(s == "x");
+
+    ln::unlink(path).unwrap();
+}
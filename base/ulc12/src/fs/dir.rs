@@ -0,0 +1,217 @@
+//! Directories: creating them, changing into them, and listing them.
+//!
+//! `Entries` lists a directory with `getdents64`, yielding the name, inode
+//! and type of each entry. Like the other `*at` calls (`file::openat`,
+//! `ln::unlinkat`, `ln::renameat2`, `ln::readlinkat`), `mkdirat` and
+//! `Entries::openat` work relative to an open directory, so paths under it
+//! can't be redirected by renaming its ancestors.
+
+use errno::*;
+use fs::flags::*;
+use fs::stat::FileType;
+use fd::Fd;
+use ::File;
+use ::Dir;
+
+
+pub fn mkdirat(dirfd: Dir, pathname: &str, mode: usize) -> Result<()> {
+    use std::ffi::CString;
+    match CString::new(pathname) {
+        Ok(path) => {
+            checked_syscall!(MKDIRAT, dirfd, path.as_ptr(), mode).map(|_| ())
+        },
+        Err(_) => {
+            Err(ENOENT)
+        }
+    }
+}
+
+pub fn mkdir(pathname: &str, mode: usize) -> Result<()> {
+    mkdirat(AT_FDCWD, pathname, mode)
+}
+
+pub fn fchdir(fd: Dir) -> Result<()> {
+    checked_syscall!(FCHDIR, fd).map(|_| ())
+}
+
+/// Reads `linux_dirent64` records of the directory `fd` into `buf`.
+/// Returns the number of bytes read, 0 at the end of the directory.
+pub fn getdents64(fd: Dir, buf: &mut [u8]) -> Result<usize> {
+    checked_syscall!(GETDENTS64, fd, buf.as_mut_ptr(), buf.len()).map(|n| n as usize)
+}
+
+
+// the fixed part of linux/dirent.h's linux_dirent64, followed by the name
+#[repr(C)]
+struct Dirent64 {
+    d_ino       :u64,
+    d_off       :i64,
+    d_reclen    :u16,
+    d_type      :u8
+}
+const NAME_OFFSET: usize = 19;
+
+/// An entry of a directory.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub ino     :u64,
+    pub d_type  :u8,            // one of DT_*
+    pub name    :Vec<u8>
+}
+
+impl Entry {
+    /// The name, if it is valid UTF-8.
+    pub fn name_str(&self) -> Option<&str> {
+        ::std::str::from_utf8(&self.name).ok()
+    }
+
+    /// The type of the entry. `None` if the filesystem doesn't report types
+    /// (`DT_UNKNOWN`), in which case `stat::fstatat` tells.
+    pub fn file_type(&self) -> Option<FileType> {
+        if self.d_type == DT_UNKNOWN {
+            None
+        }
+        else {
+            Some(FileType::from_mode((self.d_type as u32) << 12))
+        }
+    }
+}
+
+/// The entries of an open directory, except "." and "..".
+pub struct Entries {
+    fd      :Fd,
+    buf     :Vec<u64>,          // u64s keep the records aligned
+    pos     :usize,
+    len     :usize,
+    done    :bool
+}
+
+const BUF_SIZE: usize = 4096;
+
+impl Entries {
+    /// Lists the directory `fd`, from its current position.
+    pub fn new(fd: Fd) -> Entries {
+        Entries {fd: fd, buf: vec![0; BUF_SIZE / 8], pos: 0, len: 0, done: false}
+    }
+
+    /// Opens the directory `pathname`, relative to `dirfd`, for listing.
+    pub fn openat(dirfd: Dir, pathname: &str) -> Result<Entries> {
+        Fd::openat(dirfd, pathname, O_RDONLY|O_DIRECTORY|O_CLOEXEC, 0).map(Entries::new)
+    }
+
+    pub fn open(pathname: &str) -> Result<Entries> {
+        Entries::openat(AT_FDCWD, pathname)
+    }
+
+    /// The directory being listed.
+    pub fn fd(&self) -> File {
+        self.fd.raw()
+    }
+
+    fn bytes(&mut self) -> &mut [u8] {
+        use std::slice;
+        let len = self.buf.len() * 8;
+        unsafe { slice::from_raw_parts_mut(self.buf.as_mut_ptr() as *mut u8, len) }
+    }
+}
+
+impl Iterator for Entries {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Result<Entry>> {
+        loop {
+            if self.pos >= self.len {
+                if self.done {
+                    return None;
+                }
+                let fd = self.fd.raw();
+                match getdents64(fd, self.bytes()) {
+                    Ok(0) => {self.done = true; return None;},
+                    Ok(n) => {self.pos = 0; self.len = n;},
+                    Err(e) => {self.done = true; return Some(Err(e));}
+                }
+            }
+
+            let pos = self.pos;
+            let (ino, reclen, d_type, name) = {
+                let rec = &self.bytes()[pos..];
+                let d = unsafe { &*(rec.as_ptr() as *const Dirent64) };
+                let reclen = d.d_reclen as usize;
+                let name: Vec<u8> = rec[NAME_OFFSET..reclen].iter().take_while(|b| **b != 0).cloned().collect();
+                (d.d_ino, reclen, d.d_type, name)
+            };
+            self.pos += reclen;
+
+            if &name[..] != b"." && &name[..] != b".." {
+                return Some(Ok(Entry {ino: ino, d_type: d_type, name: name}));
+            }
+        }
+    }
+}
+
+
+#[test]
+fn test_dir() {
+    use std::collections::HashMap;
+    use fs::file;
+    use fs::ln;
+    use fs::stat;
+
+    let root = "/tmp/test_ulc12_dir";
+    for n in ["a/x", "b", "c", "l"].iter() {
+        let _ = ln::unlinkat(AT_FDCWD, &format!("{}/{}", root, n), 0);
+    }
+    for n in ["a", "e", ""].iter() {
+        let _ = ln::unlinkat(AT_FDCWD, &format!("{}/{}", root, n), AT_REMOVEDIR);
+    }
+
+    mkdir(root, 0o755).unwrap();
+    assert!(mkdir(root, 0o755) == Err(EEXIST));
+    let dir = Fd::open(root, O_RDONLY|O_DIRECTORY, 0).unwrap();
+    let d = dir.raw();
+    mkdirat(d, "a", 0o755).unwrap();
+    mkdirat(d, "e", 0o700).unwrap();
+    file::close(file::openat(d, "a/x", O_WRONLY|O_CREAT, 0o644).unwrap()).unwrap();
+    file::close(file::openat(d, "b", O_WRONLY|O_CREAT, 0o644).unwrap()).unwrap();
+    ln::symlinkat("b", d, "l").unwrap();
+    assert!(ln::readlinkat(d, "l").unwrap() == "b");
+
+    let mut found: HashMap<Vec<u8>, Entry> = HashMap::new();
+    for e in Entries::openat(d, ".").unwrap() {
+        let e = e.unwrap();
+        found.insert(e.name.clone(), e);
+    }
+    assert!(found.len() == 4);
+    assert!(found[&b"b"[..]].ino == stat::fstatat(d, "b", 0).unwrap().st_ino);
+    assert!(found[&b"b"[..]].name_str() == Some("b"));
+    for &(n, t) in [("a", FileType::Directory), ("b", FileType::Regular), ("l", FileType::Symlink)].iter() {
+        let e = &found[n.as_bytes()];
+        assert!(e.file_type().is_none() || e.file_type() == Some(t));
+    }
+
+    // renames relative to the directory
+    assert!(ln::renameat2(d, "b", d, "a/x", RENAME_NOREPLACE) == Err(EEXIST));
+    ln::renameat2(d, "b", d, "c", RENAME_NOREPLACE).unwrap();
+    assert!(stat::fstatat(d, "b", 0).err() == Some(ENOENT));
+    match ln::renameat2(d, "a", d, "e", RENAME_EXCHANGE) {
+        Err(EINVAL) => {}, // filesystems without exchange support
+        r => {
+            r.unwrap();
+            assert!(stat::fstatat(d, "e/x", 0).is_ok());
+            ln::renameat2(d, "e", d, "a", RENAME_EXCHANGE).unwrap();
+        }
+    }
+
+    // removal
+    assert!(ln::unlinkat(d, "a", 0) == Err(EISDIR));
+    assert!(ln::unlinkat(d, "a", AT_REMOVEDIR) == Err(ENOTEMPTY));
+    ln::unlinkat(d, "a/x", 0).unwrap();
+    ln::unlinkat(d, "a", AT_REMOVEDIR).unwrap();
+    ln::unlinkat(d, "e", AT_REMOVEDIR).unwrap();
+    ln::unlinkat(d, "c", 0).unwrap();
+    ln::unlinkat(d, "l", 0).unwrap();
+    assert!(Entries::new(Fd::openat(d, ".", O_RDONLY|O_DIRECTORY, 0).unwrap()).count() == 0);
+    drop(dir);
+    ln::unlinkat(AT_FDCWD, root, AT_REMOVEDIR).unwrap();
+    assert!(Entries::open(root).err() == Some(ENOENT));
+}
@@ -0,0 +1,298 @@
+//! `fcntl`, and a function per command.
+//!
+//! Record locks come in two kinds, both described by a `Flock`. POSIX locks
+//! (`setlk`, ...) belong to the process, and all of them on a file go when
+//! it closes any descriptor of it. Open file description locks (`ofd_setlk`,
+//! ...) belong to the open file, like `flock` locks, and only go when its
+//! last descriptor closes; they also conflict between opens of the same
+//! file within a process.
+
+use errno::*;
+use fs::flags::*;
+use ::File;
+use ::Dir;
+
+
+/// The raw call, for commands without a function below.
+pub fn fcntl(fd: File, cmd: usize, arg: usize) -> Result<isize> {
+    checked_syscall!(FCNTL, fd, cmd, arg)
+}
+
+
+/// Duplicates `fd` onto the lowest free descriptor from `min`.
+pub fn dupfd(fd: File, min: File) -> Result<File> {
+    fcntl(fd, F_DUPFD, min as usize)
+}
+
+/// As `dupfd`, with `FD_CLOEXEC` set on the copy.
+pub fn dupfd_cloexec(fd: File, min: File) -> Result<File> {
+    fcntl(fd, F_DUPFD_CLOEXEC, min as usize)
+}
+
+/// The descriptor flags (`FD_CLOEXEC`).
+pub fn getfd(fd: File) -> Result<usize> {
+    fcntl(fd, F_GETFD, 0).map(|f| f as usize)
+}
+
+pub fn setfd(fd: File, flags: usize) -> Result<()> {
+    fcntl(fd, F_SETFD, flags).map(|_| ())
+}
+
+/// The access mode (`flags & O_ACCMODE`) and status flags of the open file.
+pub fn getfl(fd: File) -> Result<usize> {
+    fcntl(fd, F_GETFL, 0).map(|f| f as usize)
+}
+
+/// Sets the status flags of the open file. Only `O_APPEND`, `O_ASYNC`,
+/// `O_DIRECT`, `O_NOATIME` and `O_NONBLOCK` can change; the rest of `flags`
+/// is ignored.
+pub fn setfl(fd: File, flags: usize) -> Result<()> {
+    fcntl(fd, F_SETFL, flags).map(|_| ())
+}
+
+pub fn set_cloexec(fd: File, on: bool) -> Result<()> {
+    setfd(fd, if on {FD_CLOEXEC} else {0})
+}
+
+pub fn set_nonblock(fd: File, on: bool) -> Result<()> {
+    let flags = try!(getfl(fd));
+    setfl(fd, if on {flags | O_NONBLOCK} else {flags & !O_NONBLOCK})
+}
+
+
+/// The kind of a record lock or lease.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LockType {
+    Read,                       // F_RDLCK
+    Write,                      // F_WRLCK
+    Unlock                      // F_UNLCK
+}
+
+impl LockType {
+    pub fn from_raw(t: usize) -> Option<LockType> {
+        match t {
+            F_RDLCK => Some(LockType::Read),
+            F_WRLCK => Some(LockType::Write),
+            F_UNLCK => Some(LockType::Unlock),
+            _ => None
+        }
+    }
+
+    pub fn raw(self) -> usize {
+        match self {
+            LockType::Read => F_RDLCK,
+            LockType::Write => F_WRLCK,
+            LockType::Unlock => F_UNLCK
+        }
+    }
+}
+
+/// A byte-range lock, as asm-generic/fcntl.h's `struct flock` (the same on
+/// x86_64, aarch64 and riscv64).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct Flock {
+    pub l_type      :i16,       // F_RDLCK, F_WRLCK or F_UNLCK
+    pub l_whence    :i16,       // SEEK_SET, SEEK_CUR or SEEK_END
+    pub l_start     :i64,
+    pub l_len       :i64,       // 0: up to the end of the file, however long
+    pub l_pid       :i32        // the holder of a conflicting lock; -1 for OFD locks
+}
+
+impl Flock {
+    /// A lock of `len` bytes from the offset `start`; to the end of the file
+    /// if `len` is 0. `l_pid` is 0, as OFD locks require.
+    pub fn new(t: LockType, start: i64, len: i64) -> Flock {
+        Flock {l_type: t.raw() as i16, l_whence: SEEK_SET as i16, l_start: start, l_len: len, l_pid: 0}
+    }
+
+    pub fn lock_type(&self) -> Option<LockType> {
+        LockType::from_raw(self.l_type as usize)
+    }
+}
+
+fn getlk_cmd(fd: File, cmd: usize, lock: &Flock) -> Result<Option<Flock>> {
+    let mut l = *lock;
+    try!(fcntl(fd, cmd, &mut l as *mut Flock as usize));
+    Ok(if l.l_type as usize == F_UNLCK {None} else {Some(l)})
+}
+
+fn setlk_cmd(fd: File, cmd: usize, lock: &Flock) -> Result<()> {
+    fcntl(fd, cmd, lock as *const Flock as usize).map(|_| ())
+}
+
+/// A POSIX lock that would keep `lock` from being taken, if any. Locks of
+/// this process never conflict.
+pub fn getlk(fd: File, lock: &Flock) -> Result<Option<Flock>> {
+    getlk_cmd(fd, F_GETLK, lock)
+}
+
+/// Takes (or, with `LockType::Unlock`, releases) a POSIX lock. Fails with
+/// `EAGAIN` or `EACCES` if another lock is in the way.
+pub fn setlk(fd: File, lock: &Flock) -> Result<()> {
+    setlk_cmd(fd, F_SETLK, lock)
+}
+
+/// As `setlk`, waiting for conflicting locks to go. Fails with `EDEADLK`
+/// if that would deadlock, and `EINTR` if a signal interrupts the wait.
+pub fn setlkw(fd: File, lock: &Flock) -> Result<()> {
+    setlk_cmd(fd, F_SETLKW, lock)
+}
+
+/// As `getlk`, for open file description locks. Locks of other opens of
+/// the file conflict, even within this process.
+pub fn ofd_getlk(fd: File, lock: &Flock) -> Result<Option<Flock>> {
+    getlk_cmd(fd, F_OFD_GETLK, lock)
+}
+
+/// As `setlk`, for open file description locks. `lock.l_pid` must be 0.
+pub fn ofd_setlk(fd: File, lock: &Flock) -> Result<()> {
+    setlk_cmd(fd, F_OFD_SETLK, lock)
+}
+
+pub fn ofd_setlkw(fd: File, lock: &Flock) -> Result<()> {
+    setlk_cmd(fd, F_OFD_SETLKW, lock)
+}
+
+
+/// Takes or releases a lease on the regular file `fd`. The holder is sent
+/// `SIGIO` (or the `setsig` signal) when another process opens or truncates
+/// the file in a conflicting way, and has /proc/sys/fs/lease-break-time
+/// seconds to release the lease.
+pub fn setlease(fd: File, lease: LockType) -> Result<()> {
+    fcntl(fd, F_SETLEASE, lease.raw()).map(|_| ())
+}
+
+/// The lease held on `fd`; `LockType::Unlock` if none.
+pub fn getlease(fd: File) -> Result<LockType> {
+    let l = try!(fcntl(fd, F_GETLEASE, 0));
+    LockType::from_raw(l as usize).ok_or(EINVAL)
+}
+
+/// Asks for a signal when an entry of the directory `fd` is touched as
+/// `events` (`DN_*`) describes. Without `DN_MULTISHOT` only the first event
+/// is reported; 0 cancels.
+pub fn notify(fd: Dir, events: usize) -> Result<()> {
+    fcntl(fd, F_NOTIFY, events).map(|_| ())
+}
+
+/// The process (or, negated, process group) signaled for I/O and lease
+/// events on `fd`.
+pub fn getown(fd: File) -> Result<isize> {
+    fcntl(fd, F_GETOWN, 0)
+}
+
+pub fn setown(fd: File, pid: isize) -> Result<()> {
+    fcntl(fd, F_SETOWN, pid as usize).map(|_| ())
+}
+
+/// The signal sent for I/O and lease events on `fd`; 0 means `SIGIO`.
+pub fn getsig(fd: File) -> Result<usize> {
+    fcntl(fd, F_GETSIG, 0).map(|s| s as usize)
+}
+
+pub fn setsig(fd: File, sig: usize) -> Result<()> {
+    fcntl(fd, F_SETSIG, sig).map(|_| ())
+}
+
+
+/// The capacity of the pipe `fd`, in bytes.
+pub fn getpipe_sz(fd: File) -> Result<usize> {
+    fcntl(fd, F_GETPIPE_SZ, 0).map(|s| s as usize)
+}
+
+/// Resizes the pipe `fd` to at least `size` bytes, and returns the size
+/// given. Unprivileged processes can't go above /proc/sys/fs/pipe-max-size
+/// (`EPERM`), and no pipe can shrink below what it holds (`EBUSY`).
+pub fn setpipe_sz(fd: File, size: usize) -> Result<usize> {
+    fcntl(fd, F_SETPIPE_SZ, size).map(|s| s as usize)
+}
+
+
+#[test]
+fn test_fcntl() {
+    use fs::file;
+    use fs::ln;
+    use process;
+
+    let path = "/tmp/test_ulc12_fcntl";
+    let _ = ln::unlink(path);
+    let f = file::open(path, O_RDWR|O_CREAT|O_APPEND, 0o644).unwrap();
+
+    // descriptor and status flags
+    assert!(getfd(f) == Ok(0));
+    let c = dupfd_cloexec(f, 100).unwrap();
+    assert!(c >= 100 && getfd(c) == Ok(FD_CLOEXEC));
+    set_cloexec(c, false).unwrap();
+    assert!(getfd(c) == Ok(0));
+    let d = dupfd(f, c).unwrap();
+    assert!(d > c && getfd(d) == Ok(0));
+    file::close(c).unwrap();
+    file::close(d).unwrap();
+    let fl = getfl(f).unwrap();
+    assert!(fl & O_ACCMODE == O_RDWR && fl & O_APPEND != 0 && fl & O_NONBLOCK == 0);
+    set_nonblock(f, true).unwrap();
+    assert!(getfl(f).unwrap() & (O_NONBLOCK|O_APPEND) == O_NONBLOCK|O_APPEND);
+    setfl(f, 0).unwrap();
+    assert!(getfl(f).unwrap() & (O_NONBLOCK|O_APPEND) == 0);
+    assert!(getfd(-1) == Err(EBADF));
+
+    // OFD locks conflict between opens of the file
+    let g = file::open(path, O_RDWR, 0).unwrap();
+    ofd_setlk(f, &Flock::new(LockType::Write, 0, 10)).unwrap();
+    assert!(ofd_setlk(g, &Flock::new(LockType::Write, 5, 10)) == Err(EAGAIN));
+    let l = ofd_getlk(g, &Flock::new(LockType::Read, 0, 0)).unwrap().unwrap();
+    assert!(l.lock_type() == Some(LockType::Write) && l.l_start == 0 && l.l_len == 10 && l.l_pid == -1);
+    ofd_setlk(g, &Flock::new(LockType::Read, 10, 10)).unwrap();
+    assert!(ofd_getlk(f, &Flock::new(LockType::Read, 0, 0)) == Ok(None));
+    assert!(ofd_setlk(g, &Flock { l_pid: 1, .. Flock::new(LockType::Read, 0, 1) }) == Err(EINVAL));
+
+    // and with POSIX locks, which never conflict within the process
+    assert!(setlk(f, &Flock::new(LockType::Write, 0, 0)) == Err(EAGAIN));
+    ofd_setlk(f, &Flock::new(LockType::Unlock, 0, 0)).unwrap();
+    ofd_setlk(g, &Flock::new(LockType::Unlock, 0, 0)).unwrap();
+    setlk(f, &Flock::new(LockType::Write, 0, 0)).unwrap();
+    setlkw(g, &Flock::new(LockType::Write, 0, 10)).unwrap();
+    assert!(getlk(g, &Flock::new(LockType::Write, 0, 0)) == Ok(None));
+    let l = ofd_getlk(g, &Flock::new(LockType::Read, 0, 0)).unwrap().unwrap();
+    assert!(l.l_pid as isize == process::getpid());
+    file::close(g).unwrap(); // drops all of this process's POSIX locks on the file
+    assert!(ofd_getlk(f, &Flock::new(LockType::Write, 0, 0)) == Ok(None));
+
+    // leases, only on files opened by nothing else
+    match setlease(f, LockType::Write) {
+        Err(EINVAL) => {}, // filesystems without lease support
+        r => {
+            r.unwrap();
+            assert!(getlease(f) == Ok(LockType::Write));
+            setlease(f, LockType::Unlock).unwrap();
+        }
+    }
+    assert!(getlease(f) == Ok(LockType::Unlock));
+    setsig(f, process::flags::SIGUSR1).unwrap();
+    assert!(getsig(f) == Ok(process::flags::SIGUSR1));
+    setown(f, process::getpid()).unwrap();
+    assert!(getown(f) == Ok(process::getpid()));
+
+    // directory notifications
+    let dir = file::open("/tmp", O_RDONLY|O_DIRECTORY, 0).unwrap();
+    assert!(notify(f, DN_CREATE) == Err(ENOTDIR));
+    match notify(dir, DN_CREATE|DN_DELETE) {
+        Err(EINVAL) => {}, // kernels without dnotify
+        r => {
+            r.unwrap();
+            notify(dir, 0).unwrap();
+        }
+    }
+    file::close(dir).unwrap();
+
+    // pipe sizes
+    let (r, w) = process::pipe2(O_CLOEXEC).unwrap();
+    assert!(getpipe_sz(w.raw()).unwrap() > 0);
+    assert!(setpipe_sz(w.raw(), 1 << 17).unwrap() >= 1 << 17);
+    assert!(getpipe_sz(r.raw()).unwrap() >= 1 << 17);
+    assert!(getpipe_sz(f) == Err(EBADF));
+
+    file::close(f).unwrap();
+    ln::unlink(path).unwrap();
+}
@@ -1,53 +1,58 @@
 use errno::*;
 use ::File;
+use ::Dir;
+use fs::flags::AT_FDCWD;
 
-pub fn open(pathname: &str, flags: usize, mode: usize) -> File {
+pub fn open(pathname: &str, flags: usize, mode: usize) -> Result<File> {
+    openat(AT_FDCWD, pathname, flags, mode)
+}
+
+/// Opens `pathname` relative to the directory `dirfd` (or `AT_FDCWD`).
+pub fn openat(dirfd: Dir, pathname: &str, flags: usize, mode: usize) -> Result<File> {
     use std::ffi::CString;
     match CString::new(pathname) {
         Ok(path) => {
-            signed_syscall!(OPEN, path.as_ptr(), flags, mode)
+            checked_syscall!(OPENAT, dirfd, path.as_ptr(), flags, mode)
         },
         Err(_) => {
-            -ENOENT
+            Err(ENOENT)
         }
     }
 }
 
-pub fn close(fd: File) -> isize {
-    signed_syscall!(CLOSE, fd)
+pub fn close(fd: File) -> Result<()> {
+    checked_syscall!(CLOSE, fd).map(|_| ())
 }
 
 
-pub fn fsync(fd: File) -> isize {
-    signed_syscall!(FSYNC, fd)
+pub fn fsync(fd: File) -> Result<()> {
+    checked_syscall!(FSYNC, fd).map(|_| ())
 }
 
-pub fn fdatasync(fd: File) -> isize {
-    signed_syscall!(FDATASYNC, fd)
+pub fn fdatasync(fd: File) -> Result<()> {
+    checked_syscall!(FDATASYNC, fd).map(|_| ())
 }
 
-pub fn lseek(fd: File, offset: isize, whence: usize) -> isize {
-    signed_syscall!(LSEEK, fd, offset, whence)
+pub fn lseek(fd: File, offset: isize, whence: usize) -> Result<u64> {
+    checked_syscall!(LSEEK, fd, offset, whence).map(|o| o as u64)
 }
 
-pub fn ftruncate(fd: File, length: u64) -> isize {
-    signed_syscall!(FTRUNCATE, fd, length)
+pub fn ftruncate(fd: File, length: u64) -> Result<()> {
+    checked_syscall!(FTRUNCATE, fd, length).map(|_| ())
 }
 
-pub fn flock(fd: File, operation: usize) -> isize {
-    signed_syscall!(FLOCK, fd, operation)
+pub fn flock(fd: File, operation: usize) -> Result<()> {
+    checked_syscall!(FLOCK, fd, operation).map(|_| ())
 }
 
-pub fn truncate(pathname: &str, length: u64) -> isize {
+pub fn truncate(pathname: &str, length: u64) -> Result<()> {
     use std::ffi::CString;
     match CString::new(pathname) {
         Ok(path) => {
-            signed_syscall!(TRUNCATE, path.as_ptr(), length)
+            checked_syscall!(TRUNCATE, path.as_ptr(), length).map(|_| ())
         },
         Err(_) => {
-            -ENOENT
+            Err(ENOENT)
         }
     }
 }
-
-
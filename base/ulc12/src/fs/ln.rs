@@ -1,7 +1,12 @@
 use errno::*;
+use fs::flags::AT_FDCWD;
 use ::Dir;
 
-pub fn readlink(pathname: &str) -> Result<String, isize> {
+pub fn readlink(pathname: &str) -> Result<String> {
+    readlinkat(AT_FDCWD, pathname)
+}
+
+pub fn readlinkat(dirfd: Dir, pathname: &str) -> Result<String> {
     use std::ffi::CString;
     
     const SIZE: usize = 4096;
@@ -9,92 +14,96 @@ pub fn readlink(pathname: &str) -> Result<String, isize> {
     
     match CString::new(pathname) {
         Ok(path) => {
-            let r = signed_syscall!(READLINK, path.as_ptr(), buf.as_mut_ptr(), SIZE);
-            if r < 0 {
-                Err(r)
+            let r = try!(checked_syscall!(READLINKAT, dirfd, path.as_ptr(), buf.as_mut_ptr(), SIZE));
+            buf.truncate(r as usize);
+            if let Ok(s) = String::from_utf8(buf) {
+                Ok(s)
             }
             else {
-                buf.truncate(r as usize);
-                if let Ok(s) = String::from_utf8(buf) {
-                    Ok(s)
-                }
-                else {
-                    tty_debug!("Non utf8 pathname returned by the kernel. Posible trouble ahead!\n");
-                    Err(-EINVAL)
-                }
+                tty_debug!("Non utf8 pathname returned by the kernel. Posible trouble ahead!\n");
+                Err(EINVAL)
             }
         },
         Err(_) => {
-            Err(-ENOENT)
+            Err(ENOENT)
         }
     }
 }
 
-pub fn symlink(oldpath: &str, newpath: &str) -> isize {
-    use std::ffi::CString;
-    
-    let op = CString::new(oldpath);
-    let np = CString::new(newpath);
-    
-    if op.is_err() || np.is_err() {
-        -EINVAL
-    }
-    else {
-        signed_syscall!(SYMLINK, op.unwrap().as_ptr(), np.unwrap().as_ptr())
-    }
+pub fn symlink(oldpath: &str, newpath: &str) -> Result<()> {
+    symlinkat(oldpath, AT_FDCWD, newpath)
+}
+
+pub fn link(oldpath: &str, newpath: &str) -> Result<()> {
+    linkat(AT_FDCWD, oldpath, AT_FDCWD, newpath, 0)
 }
 
-pub fn link(oldpath: &str, newpath: &str) -> isize {
+pub fn linkat(olddirfd: Dir, oldpath: &str, newdirfd: Dir, newpath: &str, flags: usize) -> Result<()> {
     use std::ffi::CString;
     
     let op = CString::new(oldpath);
     let np = CString::new(newpath);
     
     if op.is_err() || np.is_err() {
-        -EINVAL
+        Err(EINVAL)
     }
     else {
-        signed_syscall!(LINK, op.unwrap().as_ptr(), np.unwrap().as_ptr())
+        checked_syscall!(LINKAT, olddirfd, op.unwrap().as_ptr(), newdirfd, np.unwrap().as_ptr(), flags).map(|_| ())
     }
 }
 
-pub fn linkat(olddirfd: Dir, oldpath: &str, newdirfd: Dir, newpath: &str, flags: usize) -> isize {
+pub fn unlink(pathname: &str) -> Result<()> {
+    unlinkat(AT_FDCWD, pathname, 0)
+}
+
+pub fn rename(oldpath: &str, newpath: &str) -> Result<()> {
+    renameat2(AT_FDCWD, oldpath, AT_FDCWD, newpath, 0)
+}
+
+pub fn symlinkat(oldpath: &str, newdirfd: Dir, newpath: &str) -> Result<()> {
     use std::ffi::CString;
     
     let op = CString::new(oldpath);
     let np = CString::new(newpath);
     
     if op.is_err() || np.is_err() {
-        -EINVAL
+        Err(EINVAL)
     }
     else {
-        signed_syscall!(LINKAT, olddirfd, op.unwrap().as_ptr(), newdirfd, np.unwrap().as_ptr(), flags)
+        checked_syscall!(SYMLINKAT, op.unwrap().as_ptr(), newdirfd, np.unwrap().as_ptr()).map(|_| ())
     }
 }
 
-pub fn unlink(pathname: &str) -> isize {
+/// Removes `pathname` relative to `dirfd`; a directory if `flags` has
+/// `AT_REMOVEDIR`.
+pub fn unlinkat(dirfd: Dir, pathname: &str, flags: usize) -> Result<()> {
     use std::ffi::CString;
     
     let p = CString::new(pathname);
     
     if p.is_err() {
-        -EINVAL
+        Err(EINVAL)
     }
     else {
-        signed_syscall!(UNLINK, p.unwrap().as_ptr())
+        checked_syscall!(UNLINKAT, dirfd, p.unwrap().as_ptr(), flags).map(|_| ())
     }
 }
 
-pub fn rename(oldpath: &str, newpath: &str) -> isize {
+/// Renames `oldpath` relative to `olddirfd` to `newpath` relative to
+/// `newdirfd`. `flags` are `RENAME_NOREPLACE` (fail with `EEXIST` rather
+/// than replace `newpath`), `RENAME_EXCHANGE` (swap the two atomically) and
+/// `RENAME_WHITEOUT`; filesystems that don't support them fail with
+/// `EINVAL`.
+pub fn renameat2(olddirfd: Dir, oldpath: &str, newdirfd: Dir, newpath: &str, flags: usize) -> Result<()> {
     use std::ffi::CString;
     
     let op = CString::new(oldpath);
     let np = CString::new(newpath);
     
     if op.is_err() || np.is_err() {
-        -EINVAL
+        Err(EINVAL)
     }
     else {
-        signed_syscall!(RENAME, op.unwrap().as_ptr(), np.unwrap().as_ptr())
+        checked_syscall!(RENAMEAT2, olddirfd, op.unwrap().as_ptr(), newdirfd, np.unwrap().as_ptr(), flags).map(|_| ())
     }
 }
@@ -1,6 +1,8 @@
 pub mod file;
 pub mod stat;
 pub mod ln;
+pub mod dir;
+pub mod fcntl;
 pub mod flags {
     // asm-generic/fcntl.h
     pub const O_ACCMODE:usize                = 0o0000003;
@@ -67,6 +69,15 @@ pub mod flags {
     pub const LOCK_WRITE:usize               = 128;
     pub const LOCK_RW:usize                  = 192;
     pub const F_LINUX_SPECIFIC_BASE:usize    = 1024;
+    pub const F_SETLEASE:usize               = F_LINUX_SPECIFIC_BASE + 0;
+    pub const F_GETLEASE:usize               = F_LINUX_SPECIFIC_BASE + 1;
+    pub const F_NOTIFY:usize                 = F_LINUX_SPECIFIC_BASE + 2;
+    pub const F_CANCELLK:usize               = F_LINUX_SPECIFIC_BASE + 5;
+    pub const F_DUPFD_CLOEXEC:usize          = F_LINUX_SPECIFIC_BASE + 6;
+    pub const F_SETPIPE_SZ:usize             = F_LINUX_SPECIFIC_BASE + 7;
+    pub const F_GETPIPE_SZ:usize             = F_LINUX_SPECIFIC_BASE + 8;
+    pub const F_ADD_SEALS:usize              = F_LINUX_SPECIFIC_BASE + 9;
+    pub const F_GET_SEALS:usize              = F_LINUX_SPECIFIC_BASE + 10;
 
 
     // include/uapi/linux/stat.h
@@ -100,6 +111,8 @@ pub mod flags {
     pub const F_SEAL_SHRINK:usize          = 0x0002;
     pub const F_SEAL_GROW:usize            = 0x0004;
     pub const F_SEAL_WRITE:usize           = 0x0008;
+    pub const F_SEAL_FUTURE_WRITE:usize    = 0x0010;
+    pub const F_SEAL_EXEC:usize            = 0x0020;
     pub const DN_ACCESS:usize              = 0x00000001;
     pub const DN_MODIFY:usize              = 0x00000002;
     pub const DN_CREATE:usize              = 0x00000004;
@@ -113,6 +126,51 @@ pub mod flags {
     pub const AT_SYMLINK_FOLLOW:usize      = 0x400;
     pub const AT_NO_AUTOMOUNT:usize        = 0x800;
     pub const AT_EMPTY_PATH:usize          = 0x1000;
+    pub const AT_STATX_SYNC_AS_STAT:usize  = 0x0000;
+    pub const AT_STATX_FORCE_SYNC:usize    = 0x2000;
+    pub const AT_STATX_DONT_SYNC:usize     = 0x4000;
+
+    // include/uapi/linux/fs.h
+    pub const RENAME_NOREPLACE:usize       = 1 << 0;
+    pub const RENAME_EXCHANGE:usize        = 1 << 1;
+    pub const RENAME_WHITEOUT:usize        = 1 << 2;
+
+    // include/linux/fs.h (d_type of directory entries)
+    pub const DT_UNKNOWN:u8    = 0;
+    pub const DT_FIFO:u8       = 1;
+    pub const DT_CHR:u8        = 2;
+    pub const DT_DIR:u8        = 4;
+    pub const DT_BLK:u8        = 6;
+    pub const DT_REG:u8        = 8;
+    pub const DT_LNK:u8        = 10;
+    pub const DT_SOCK:u8       = 12;
+    pub const DT_WHT:u8        = 14;
+
+    // include/uapi/linux/stat.h (statx)
+    pub const STATX_TYPE:u32                   = 0x0001;
+    pub const STATX_MODE:u32                   = 0x0002;
+    pub const STATX_NLINK:u32                  = 0x0004;
+    pub const STATX_UID:u32                    = 0x0008;
+    pub const STATX_GID:u32                    = 0x0010;
+    pub const STATX_ATIME:u32                  = 0x0020;
+    pub const STATX_MTIME:u32                  = 0x0040;
+    pub const STATX_CTIME:u32                  = 0x0080;
+    pub const STATX_INO:u32                    = 0x0100;
+    pub const STATX_SIZE:u32                   = 0x0200;
+    pub const STATX_BLOCKS:u32                 = 0x0400;
+    pub const STATX_BASIC_STATS:u32            = 0x07ff;
+    pub const STATX_BTIME:u32                  = 0x0800;
+    pub const STATX_MNT_ID:u32                 = 0x1000;
+    pub const STATX_DIOALIGN:u32               = 0x2000;
+    pub const STATX_ATTR_COMPRESSED:u64        = 0x00000004;
+    pub const STATX_ATTR_IMMUTABLE:u64         = 0x00000010;
+    pub const STATX_ATTR_APPEND:u64            = 0x00000020;
+    pub const STATX_ATTR_NODUMP:u64            = 0x00000040;
+    pub const STATX_ATTR_ENCRYPTED:u64         = 0x00000800;
+    pub const STATX_ATTR_AUTOMOUNT:u64         = 0x00001000;
+    pub const STATX_ATTR_MOUNT_ROOT:u64        = 0x00002000;
+    pub const STATX_ATTR_VERITY:u64            = 0x00100000;
+    pub const STATX_ATTR_DAX:u64               = 0x00200000;
 
     pub const SEEK_SET:usize   = 0;
     pub const SEEK_CUR:usize   = 1;
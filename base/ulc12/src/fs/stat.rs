@@ -1,20 +1,19 @@
+//! File status: `stat`, `lstat`, `fstat`, `fstatat` and `statx`.
+//!
+//! The kernel's `struct stat` differs between architectures (x86_64 has its
+//! own, aarch64 and riscv64 use the generic one), and is converted into the
+//! arch independent `Stat`. `statx` has the same layout everywhere, and
+//! reports what the filesystem actually filled in (`stx_mask`), the birth
+//! time and mount ID included. `FileType` and `Permissions` decode modes.
+
+use std::fmt;
 use errno::*;
+use fs::flags::*;
 use ::File;
+use ::Dir;
 
 
-// Stat is an uncomplete desaster. DO NOT USE
-// Likely to be removed.
-
-/* Do these later
-pub const S_ISLNK(m):usize     = (((m) & S_IFMT) == S_IFLNK);
-pub const S_ISREG(m):usize     = (((m) & S_IFMT) == S_IFREG);
-pub const S_ISDIR(m):usize     = (((m) & S_IFMT) == S_IFDIR);
-pub const S_ISCHR(m):usize     = (((m) & S_IFMT) == S_IFCHR);
-pub const S_ISBLK(m):usize     = (((m) & S_IFMT) == S_IFBLK);
-pub const S_ISFIFO(m):usize    = (((m) & S_IFMT) == S_IFIFO);
-pub const S_ISSOCK(m):usize    = (((m) & S_IFMT) == S_IFSOCK);
-*/
-
+// arch/x86/include/uapi/asm/stat.h
 #[cfg(all(target_os="linux", target_arch="x86_64"))]
 #[derive(Default, Debug)]
 #[repr(C)]
@@ -25,23 +24,45 @@ struct IntStat {
     st_mode         :u32,
     st_uid          :u32,
     st_gid          :u32,
-    __unused0       :u32,
+    __pad0          :u32,
+    st_rdev         :u64,
+    st_size         :i64,
+    st_blksize      :i64,
+    st_blocks       :i64,
+    st_atime        :i64,
+    st_atime_nsec   :u64,
+    st_mtime        :i64,
+    st_mtime_nsec   :u64,
+    st_ctime        :i64,
+    st_ctime_nsec   :u64,
+    __unused        :[i64; 3]
+}
+
+// include/uapi/asm-generic/stat.h
+#[cfg(all(target_os="linux", any(target_arch="aarch64", target_arch="riscv64")))]
+#[derive(Default, Debug)]
+#[repr(C)]
+struct IntStat {
+    st_dev          :u64,
+    st_ino          :u64,
+    st_mode         :u32,
+    st_nlink        :u32,
+    st_uid          :u32,
+    st_gid          :u32,
     st_rdev         :u64,
-    st_size         :u64,
-    st_blksize      :u64,
-    st_blocks       :u64,
-    st_atime        :u64,
+    __pad1          :u64,
+    st_size         :i64,
+    st_blksize      :i32,
+    __pad2          :i32,
+    st_blocks       :i64,
+    st_atime        :i64,
     st_atime_nsec   :u64,
-    st_mtime        :u64,
+    st_mtime        :i64,
     st_mtime_nsec   :u64,
-    st_ctime        :u64,
+    st_ctime        :i64,
     st_ctime_nsec   :u64,
-    __unused1       :u32,
-    __unused2       :u32,
-    __unused3       :u32,
     __unused4       :u32,
-    __unused5       :u32,
-    __unused6       :u32        
+    __unused5       :u32
 }
 
 // Arch independent struct
@@ -57,134 +78,268 @@ pub struct Stat {
     pub st_size         :u64,
     pub st_blksize      :u64,
     pub st_blocks       :u64,
-    pub st_atime        :u64,
+    pub st_atime        :i64,
     pub st_atime_nsec   :u64,
-    pub st_mtime        :u64,
+    pub st_mtime        :i64,
     pub st_mtime_nsec   :u64,
-    pub st_ctime        :u64,
+    pub st_ctime        :i64,
     pub st_ctime_nsec   :u64,
 }
 
 impl Stat {
-    pub fn isreg(&self)     -> bool { use ::fs::flags::*; (self.st_mode as usize & S_IFMT) == S_IFREG }
-    pub fn isdir(&self)     -> bool { use ::fs::flags::*; (self.st_mode as usize & S_IFMT) == S_IFDIR }
-    pub fn ischr(&self)     -> bool { use ::fs::flags::*; (self.st_mode as usize & S_IFMT) == S_IFCHR }
-    pub fn isblk(&self)     -> bool { use ::fs::flags::*; (self.st_mode as usize & S_IFMT) == S_IFBLK }
-    pub fn isfifo(&self)    -> bool { use ::fs::flags::*; (self.st_mode as usize & S_IFMT) == S_IFIFO }
-    pub fn islnk(&self)     -> bool { use ::fs::flags::*; (self.st_mode as usize & S_IFMT) == S_IFLNK }
-    pub fn issock(&self)    -> bool { use ::fs::flags::*; (self.st_mode as usize & S_IFMT) == S_IFSOCK }
+    fn from_kernel(st: &IntStat) -> Stat {
+        Stat {
+            st_dev          : st.st_dev,
+            st_ino          : st.st_ino,
+            st_nlink        : st.st_nlink as u64,
+            st_mode         : st.st_mode,
+            st_uid          : st.st_uid,
+            st_gid          : st.st_gid,
+            st_rdev         : st.st_rdev,
+            st_size         : st.st_size as u64,
+            st_blksize      : st.st_blksize as u64,
+            st_blocks       : st.st_blocks as u64,
+            st_atime        : st.st_atime,
+            st_atime_nsec   : st.st_atime_nsec,
+            st_mtime        : st.st_mtime,
+            st_mtime_nsec   : st.st_mtime_nsec,
+            st_ctime        : st.st_ctime,
+            st_ctime_nsec   : st.st_ctime_nsec
+        }
+    }
+
+    pub fn file_type(&self) -> FileType {
+        FileType::from_mode(self.st_mode)
+    }
+
+    pub fn permissions(&self) -> Permissions {
+        Permissions::from_mode(self.st_mode)
+    }
+
+    #[deprecated(note = "use file_type()")]
+    pub fn isreg(&self)     -> bool { self.file_type() == FileType::Regular }
+    #[deprecated(note = "use file_type()")]
+    pub fn isdir(&self)     -> bool { self.file_type() == FileType::Directory }
+    #[deprecated(note = "use file_type()")]
+    pub fn ischr(&self)     -> bool { self.file_type() == FileType::CharDevice }
+    #[deprecated(note = "use file_type()")]
+    pub fn isblk(&self)     -> bool { self.file_type() == FileType::BlockDevice }
+    #[deprecated(note = "use file_type()")]
+    pub fn isfifo(&self)    -> bool { self.file_type() == FileType::Fifo }
+    #[deprecated(note = "use file_type()")]
+    pub fn islnk(&self)     -> bool { self.file_type() == FileType::Symlink }
+    #[deprecated(note = "use file_type()")]
+    pub fn issock(&self)    -> bool { self.file_type() == FileType::Socket }
+}
+
+
+/// The type of a file, from the `S_IFMT` bits of its mode.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileType {
+    Regular,
+    Directory,
+    Symlink,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    Socket,
+    Unknown(u32)
+}
+
+impl FileType {
+    pub fn from_mode(mode: u32) -> FileType {
+        match mode as usize & S_IFMT {
+            S_IFREG     => FileType::Regular,
+            S_IFDIR     => FileType::Directory,
+            S_IFLNK     => FileType::Symlink,
+            S_IFCHR     => FileType::CharDevice,
+            S_IFBLK     => FileType::BlockDevice,
+            S_IFIFO     => FileType::Fifo,
+            S_IFSOCK    => FileType::Socket,
+            t           => FileType::Unknown(t as u32)
+        }
+    }
+}
+
+
+/// Whether a class of users (the owner, the group or others) may read,
+/// write and execute a file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Access {
+    pub read        :bool,
+    pub write       :bool,
+    pub execute     :bool
+}
+
+/// The permission bits of a file's mode (`0o7777`). Displays as `ls -l`
+/// does, e.g. "rwxr-sr-t".
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Permissions {
+    mode: u32
+}
+
+impl Permissions {
+    pub fn from_mode(mode: u32) -> Permissions {
+        Permissions {mode: mode & 0o7777}
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    fn access(&self, shift: u32) -> Access {
+        Access {
+            read    : self.mode >> shift & 0o4 != 0,
+            write   : self.mode >> shift & 0o2 != 0,
+            execute : self.mode >> shift & 0o1 != 0
+        }
+    }
+
+    pub fn user(&self)      -> Access { self.access(6) }
+    pub fn group(&self)     -> Access { self.access(3) }
+    pub fn other(&self)     -> Access { self.access(0) }
+    pub fn setuid(&self)    -> bool { self.mode as usize & S_ISUID != 0 }
+    pub fn setgid(&self)    -> bool { self.mode as usize & S_ISGID != 0 }
+    pub fn sticky(&self)    -> bool { self.mode as usize & S_ISVTX != 0 }
+}
+
+impl fmt::Display for Permissions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let classes = [(self.user(), self.setuid(), 's'), (self.group(), self.setgid(), 's'), (self.other(), self.sticky(), 't')];
+        for &(a, special, c) in classes.iter() {
+            let x = match (a.execute, special) {
+                (true, true) => c,
+                (false, true) => c.to_uppercase().next().unwrap(),
+                (true, false) => 'x',
+                (false, false) => '-'
+            };
+            try!(write!(f, "{}{}{}", if a.read {'r'} else {'-'}, if a.write {'w'} else {'-'}, x));
+        }
+        Ok(())
+    }
+}
+
+
+pub fn stat(pathname: &str) -> Result<Stat> {
+    fstatat(AT_FDCWD, pathname, 0)
+}
+
+pub fn lstat(pathname: &str) -> Result<Stat> {
+    fstatat(AT_FDCWD, pathname, AT_SYMLINK_NOFOLLOW)
 }
 
-pub fn stat(pathname: &str) -> Result<Stat, isize> {
+pub fn fstat(fd: File) -> Result<Stat> {
+    use std::default::Default;
+    
+    let mut st: IntStat = Default::default();
+    try!(checked_syscall!(FSTAT, fd, (&mut st) as *mut IntStat));
+    Ok(Stat::from_kernel(&st))
+}
+
+/// Stats `pathname` relative to the directory `dirfd` (or `AT_FDCWD`).
+/// `flags` are `AT_SYMLINK_NOFOLLOW`, `AT_EMPTY_PATH` and `AT_NO_AUTOMOUNT`.
+pub fn fstatat(dirfd: Dir, pathname: &str, flags: usize) -> Result<Stat> {
     use std::ffi::CString;
     use std::default::Default;
     
     match CString::new(pathname) {
         Ok(path) => {
             let mut st: IntStat = Default::default();
-            let r = signed_syscall!(STAT, path.as_ptr(), (&mut st) as *mut IntStat);
-            if r < 0 {
-                Err(r as isize)
-            }
-            else {
-                Ok(Stat {
-                    st_dev          : st.st_dev,
-                    st_ino          : st.st_ino,
-                    st_nlink        : st.st_nlink,
-                    st_mode         : st.st_mode,
-                    st_uid          : st.st_uid,
-                    st_gid          : st.st_gid,
-                    st_rdev         : st.st_rdev,
-                    st_size         : st.st_size,
-                    st_blksize      : st.st_blksize,
-                    st_blocks       : st.st_blocks,
-                    st_atime        : st.st_atime,
-                    st_atime_nsec   : st.st_atime_nsec,
-                    st_mtime        : st.st_mtime,
-                    st_mtime_nsec   : st.st_mtime_nsec,
-                    st_ctime        : st.st_ctime,
-                    st_ctime_nsec   : st.st_ctime_nsec
-                })
-            }
+            try!(checked_syscall!(NEWFSTATAT, dirfd, path.as_ptr(), (&mut st) as *mut IntStat, flags));
+            Ok(Stat::from_kernel(&st))
         },
         Err(_) => {
-            Err(-ENOENT as isize)
+            Err(ENOENT)
         }
     }    
 }
 
-pub fn lstat(pathname: &str) -> Result<Stat, isize> {
+
+// include/uapi/linux/stat.h
+#[derive(Clone, Copy, Default, Debug)]
+#[repr(C)]
+pub struct StatxTimestamp {
+    pub tv_sec          :i64,
+    pub tv_nsec         :u32,
+    __reserved          :i32
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct Statx {
+    pub stx_mask                :u32,
+    pub stx_blksize             :u32,
+    pub stx_attributes          :u64,
+    pub stx_nlink               :u32,
+    pub stx_uid                 :u32,
+    pub stx_gid                 :u32,
+    pub stx_mode                :u16,
+    __spare0                    :u16,
+    pub stx_ino                 :u64,
+    pub stx_size                :u64,
+    pub stx_blocks              :u64,
+    pub stx_attributes_mask     :u64,
+    pub stx_atime               :StatxTimestamp,
+    pub stx_btime               :StatxTimestamp,
+    pub stx_ctime               :StatxTimestamp,
+    pub stx_mtime               :StatxTimestamp,
+    pub stx_rdev_major          :u32,
+    pub stx_rdev_minor          :u32,
+    pub stx_dev_major           :u32,
+    pub stx_dev_minor           :u32,
+    pub stx_mnt_id              :u64,
+    pub stx_dio_mem_align       :u32,
+    pub stx_dio_offset_align    :u32,
+    __spare3                    :[u64; 12]
+}
+
+impl Statx {
+    pub fn file_type(&self) -> FileType {
+        FileType::from_mode(self.stx_mode as u32)
+    }
+
+    pub fn permissions(&self) -> Permissions {
+        Permissions::from_mode(self.stx_mode as u32)
+    }
+
+    /// The creation time, if the filesystem records it.
+    pub fn btime(&self) -> Option<StatxTimestamp> {
+        if self.stx_mask & STATX_BTIME != 0 {Some(self.stx_btime)} else {None}
+    }
+
+    /// The ID of the mount the file is on, if the kernel reports it.
+    pub fn mnt_id(&self) -> Option<u64> {
+        if self.stx_mask & STATX_MNT_ID != 0 {Some(self.stx_mnt_id)} else {None}
+    }
+
+    /// Whether the `STATX_ATTR_` attribute `attr` is set. `None` if the
+    /// filesystem doesn't support it.
+    pub fn attribute(&self, attr: u64) -> Option<bool> {
+        if self.stx_attributes_mask & attr != 0 {Some(self.stx_attributes & attr != 0)} else {None}
+    }
+}
+
+/// Stats `pathname` relative to `dirfd`, asking for the `STATX_` fields in
+/// `mask`; `stx_mask` tells which were filled in. `flags` are the `AT_`
+/// flags of `fstatat`, and `AT_STATX_` sync flags.
+pub fn statx(dirfd: Dir, pathname: &str, flags: usize, mask: u32) -> Result<Statx> {
     use std::ffi::CString;
-    use std::default::Default;
+    use std::mem;
     
     match CString::new(pathname) {
         Ok(path) => {
-            let mut st: IntStat = Default::default();
-            let r = signed_syscall!(LSTAT, path.as_ptr(), (&mut st) as *mut IntStat);
-            if r < 0 {
-                Err(r as isize)
-            }
-            else {
-                Ok(Stat {
-                    st_dev          : st.st_dev,
-                    st_ino          : st.st_ino,
-                    st_nlink        : st.st_nlink,
-                    st_mode         : st.st_mode,
-                    st_uid          : st.st_uid,
-                    st_gid          : st.st_gid,
-                    st_rdev         : st.st_rdev,
-                    st_size         : st.st_size,
-                    st_blksize      : st.st_blksize,
-                    st_blocks       : st.st_blocks,
-                    st_atime        : st.st_atime,
-                    st_atime_nsec   : st.st_atime_nsec,
-                    st_mtime        : st.st_mtime,
-                    st_mtime_nsec   : st.st_mtime_nsec,
-                    st_ctime        : st.st_ctime,
-                    st_ctime_nsec   : st.st_ctime_nsec
-                })
-            }
+            let mut stx: Statx = unsafe { mem::zeroed() };
+            try!(checked_syscall!(STATX, dirfd, path.as_ptr(), flags, mask, (&mut stx) as *mut Statx));
+            Ok(stx)
         },
         Err(_) => {
-            Err(-ENOENT as isize)
+            Err(ENOENT)
         }
     }    
 }
 
-
-pub fn fstat(fd: File) -> Result<Stat, isize> {
-    use std::default::Default;
-    
-    let mut st: IntStat = Default::default();
-    let r = signed_syscall!(FSTAT, fd, (&mut st) as *mut IntStat);
-    if r < 0 {
-        Err(r as isize)
-    }
-    else {
-        Ok(Stat {
-            st_dev          : st.st_dev,
-            st_ino          : st.st_ino,
-            st_nlink        : st.st_nlink,
-            st_mode         : st.st_mode,
-            st_uid          : st.st_uid,
-            st_gid          : st.st_gid,
-            st_rdev         : st.st_rdev,
-            st_size         : st.st_size,
-            st_blksize      : st.st_blksize,
-            st_blocks       : st.st_blocks,
-            st_atime        : st.st_atime,
-            st_atime_nsec   : st.st_atime_nsec,
-            st_mtime        : st.st_mtime,
-            st_mtime_nsec   : st.st_mtime_nsec,
-            st_ctime        : st.st_ctime,
-            st_ctime_nsec   : st.st_ctime_nsec
-        })
-    }
-}
-
 #[test]
 fn test_stat() {
-    use super::flags::*;
     use super::file;
     use super::ln;
     use ::io;
@@ -195,14 +350,13 @@ fn test_stat() {
     let phrase = "testing that i am cool";
     
     // cleanup
-    ln::unlink(hp);
-    ln::unlink(op);
-    ln::unlink(sp);
+    let _ = ln::unlinkat(AT_FDCWD, hp, 0);
+    let _ = ln::unlinkat(AT_FDCWD, op, 0);
+    let _ = ln::unlinkat(AT_FDCWD, sp, 0);
     
     // setup
-    let fd = file::open(op, O_WRONLY|O_CREAT, 0o620);
-    assert!(fd > 0);
-    assert!(io::write(fd, phrase.as_bytes()) == phrase.len() as isize);
+    let fd = file::openat(AT_FDCWD, op, O_WRONLY|O_CREAT, 0o620).unwrap();
+    assert!(io::write(fd, phrase.as_bytes()) == Ok(phrase.len()));
     
     
     // test size
@@ -214,30 +368,56 @@ fn test_stat() {
     }
     // sanity test link field 
     {
-        assert!(ln::link(op, hp) == 0);
+        assert!(ln::linkat(AT_FDCWD, op, AT_FDCWD, hp, 0).is_ok());
         let s = stat(op);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert!(s.st_nlink == 2);
-        assert!(s.isreg());
-        assert!(!s.isdir());
+        assert!(s.file_type() == FileType::Regular);
+        assert!(s.permissions().user().write && !s.permissions().other().write); // 0o620, less the umask
     }
     // test symlink travrsal
     {    
-        assert!(ln::symlink(op, sp) == 0);
+        assert!(ln::symlinkat(op, AT_FDCWD, sp).is_ok());
         let s = stat(sp);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert!(s.st_nlink == 2); // didn;'t remove hardlink in prev blk
         assert!(s.st_size == phrase.len() as u64);
-        assert!(!s.islnk());
+        assert!(s.file_type() != FileType::Symlink);
         // stating symlink iteslf
         let s = lstat(sp);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert!(s.st_nlink == 1);
-        assert!(s.islnk());
-        assert!(ln::unlink(sp) == 0);
+        assert!(s.file_type() == FileType::Symlink);
+        assert!(ln::unlinkat(AT_FDCWD, sp, 0).is_ok());
+    }
+    // fstatat and statx
+    {
+        let s = fstatat(AT_FDCWD, "/tmp", 0).unwrap();
+        assert!(s.file_type() == FileType::Directory);
+        assert!(s.permissions().sticky());
+        let dir = file::openat(AT_FDCWD, "/tmp", O_RDONLY|O_DIRECTORY, 0).unwrap();
+        let s = fstatat(dir, "test_stat_normal_file", AT_SYMLINK_NOFOLLOW).unwrap();
+        assert!(s.st_size == phrase.len() as u64);
+        assert!(fstatat(dir, "test_stat_nothing", 0).err() == Some(ENOENT));
+
+        match statx(dir, "test_stat_normal_file", 0, STATX_BASIC_STATS|STATX_BTIME|STATX_MNT_ID) {
+            Err(ENOSYS) => {}, // before linux 4.11
+            r => {
+                let x = r.unwrap();
+                assert!(x.stx_mask & STATX_BASIC_STATS == STATX_BASIC_STATS);
+                assert!(x.stx_size == s.st_size && x.stx_ino == s.st_ino && x.stx_nlink == 2);
+                assert!(x.file_type() == FileType::Regular);
+                assert!(x.permissions() == s.permissions());
+                assert!(x.stx_mtime.tv_sec == s.st_mtime && x.stx_mtime.tv_nsec as u64 == s.st_mtime_nsec);
+                if let Some(b) = x.btime() {
+                    assert!(b.tv_sec <= x.stx_mtime.tv_sec);
+                }
+            }
+        }
+        assert!(file::close(dir).is_ok());
     }
     // fstat test
     {
@@ -247,10 +427,42 @@ fn test_stat() {
         assert!(s.st_nlink == 2);
         assert!(s.st_size == phrase.len() as u64);
     }
-    ln::unlink(hp);
-    ln::unlink(op);
+    assert!(stat(sp).err() == Some(ENOENT));
+    assert!(file::close(fd).is_ok());
+    let _ = ln::unlinkat(AT_FDCWD, hp, 0);
+    let _ = ln::unlinkat(AT_FDCWD, op, 0);
     
     //tty_print!("{} {}", format!("{:?}", s).replace(", ", "\n"), phrase.len());
 }
 
+#[test]
+fn test_layout() {
+    use std::mem::size_of;
+    assert!(size_of::<Statx>() == 256);
+    if cfg!(target_arch="x86_64") {
+        assert!(size_of::<IntStat>() == 144);
+    }
+    else {
+        assert!(size_of::<IntStat>() == 128);
+    }
+}
+
+#[test]
+fn test_mode() {
+    assert!(FileType::from_mode(0o100644) == FileType::Regular);
+    assert!(FileType::from_mode(0o040755) == FileType::Directory);
+    assert!(FileType::from_mode(0o120777) == FileType::Symlink);
+    assert!(FileType::from_mode(0o010600) == FileType::Fifo);
+    assert!(FileType::from_mode(0o000644) == FileType::Unknown(0));
+
+    let p = Permissions::from_mode(0o104751);
+    assert!(p.mode() == 0o4751 && p.setuid() && !p.setgid() && !p.sticky());
+    assert!(p.user() == Access {read: true, write: true, execute: true});
+    assert!(p.group() == Access {read: true, write: false, execute: true});
+    assert!(p.other() == Access {read: false, write: false, execute: true});
+    assert!(format!("{}", p) == "rwsr-x--x");
+    assert!(format!("{}", Permissions::from_mode(0o3644)) == "rw-r-Sr-T");
+    assert!(format!("{}", Permissions::from_mode(0o1777)) == "rwxrwxrwt");
+}
+
 
@@ -1,6 +1,37 @@
+/// Makes the system call `nr` (a name in `nr`) with up to six arguments,
+/// returning the raw result: negative values are `-errno`.
 #[macro_export]
 macro_rules! signed_syscall {
+    ($nr:ident) => (
+        unsafe {::syscall::syscall0($crate::nr::$nr)} as isize
+    );
+    ($nr:ident, $a1:expr) => (
+        unsafe {::syscall::syscall1($crate::nr::$nr, $a1 as usize)} as isize
+    );
+    ($nr:ident, $a1:expr, $a2:expr) => (
+        unsafe {::syscall::syscall2($crate::nr::$nr, $a1 as usize, $a2 as usize)} as isize
+    );
+    ($nr:ident, $a1:expr, $a2:expr, $a3:expr) => (
+        unsafe {::syscall::syscall3($crate::nr::$nr, $a1 as usize, $a2 as usize, $a3 as usize)} as isize
+    );
+    ($nr:ident, $a1:expr, $a2:expr, $a3:expr, $a4:expr) => (
+        unsafe {::syscall::syscall4($crate::nr::$nr, $a1 as usize, $a2 as usize, $a3 as usize,
+                                    $a4 as usize)} as isize
+    );
+    ($nr:ident, $a1:expr, $a2:expr, $a3:expr, $a4:expr, $a5:expr) => (
+        unsafe {::syscall::syscall5($crate::nr::$nr, $a1 as usize, $a2 as usize, $a3 as usize,
+                                    $a4 as usize, $a5 as usize)} as isize
+    );
+    ($nr:ident, $a1:expr, $a2:expr, $a3:expr, $a4:expr, $a5:expr, $a6:expr) => (
+        unsafe {::syscall::syscall6($crate::nr::$nr, $a1 as usize, $a2 as usize, $a3 as usize,
+                                    $a4 as usize, $a5 as usize, $a6 as usize)} as isize
+    );
+}
+
+/// Like `signed_syscall!`, but returns an `errno::Result`.
+#[macro_export]
+macro_rules! checked_syscall {
     ($($t:tt)*) => (
-        unsafe {syscall!($($t)*)} as isize
+        $crate::errno::check(signed_syscall!($($t)*))
     )
 }
@@ -1,7 +1,6 @@
 #![allow(trivial_numeric_casts)]
 #![allow(trivial_casts)]
 
-#[macro_use]
 extern crate syscall;
 #[macro_use]
 extern crate ulc22;
@@ -29,9 +28,18 @@ mod glue;
 pub type File = isize;
 pub type Dir = File;
 
+pub use errno::Errno;
+pub use errno::Result;
+pub use fd::Fd;
+pub use fd::BorrowedFd;
+
 pub mod errno;
+pub mod fd;
 pub mod fs;
 pub mod io;
+pub mod mem;
+pub mod nr;
+pub mod process;
 
 
 #[test]
@@ -42,39 +50,39 @@ fn it_works() {
     use fs::file;
     
     let example = "\nRaw baby. Raw.\n".as_bytes();
-    let example_len = example.len() as isize;
+    let example_len = example.len();
     {
-    assert!(io::write(1, example) == example_len);
-    assert!(io::write(50, example) == -EBADF);
-    assert!(file::open("/asfsdgf/sdfsdg", O_RDONLY, 0) == -ENOENT);
+    assert!(io::write(1, example) == Ok(example_len));
+    assert!(io::write(50, example) == Err(EBADF));
+    assert!(file::open("/asfsdgf/sdfsdg", O_RDONLY, 0) == Err(ENOENT));
     }
     {    
-        let fd = file::open("/tmp/open_test", O_WRONLY|O_CREAT, 0o640);
+        let fd = file::open("/tmp/open_test", O_WRONLY|O_CREAT, 0o640).unwrap();
         assert!(fd > 0);
-        assert!(io::write(fd, example) == example_len);
-        assert!(file::close(fd) == 0);
+        assert!(io::write(fd, example) == Ok(example_len));
+        assert!(file::close(fd).is_ok());
     }
     {
         let mut buf:Vec<u8> = vec![0;30];
-        let fd = file::open("/tmp/open_test", O_RDONLY, 0);
+        let fd = file::open("/tmp/open_test", O_RDONLY, 0).unwrap();
         assert!(fd > 0);
-        assert!(io::read(fd, buf.as_mut_slice()) == example_len);
-        assert!(&buf[..example_len as usize] == example);
-        assert!(file::close(fd) == 0);
+        assert!(io::read(fd, buf.as_mut_slice()) == Ok(example_len));
+        assert!(&buf[..example_len] == example);
+        assert!(file::close(fd).is_ok());
     }
     {
         use std::fs::File;
-        let read_fd = file::open("/tmp/open_test", O_RDONLY, 0);
-        let write_fd = file::open("/tmp/open_test_2", O_WRONLY|O_CREAT|O_TRUNC, 0o640);
+        let read_fd = file::open("/tmp/open_test", O_RDONLY, 0).unwrap();
+        let write_fd = file::open("/tmp/open_test_2", O_WRONLY|O_CREAT|O_TRUNC, 0o640).unwrap();
         let read_fd_len = File::open("/tmp/open_test").unwrap().metadata().unwrap().len();
-        assert!(example_len == read_fd_len as isize);
-        assert!(io::sendfile(write_fd, read_fd, None, read_fd_len as usize) == read_fd_len as isize);
-        file::close(read_fd);
-        file::close(write_fd);
-        let write_fd = file::open("/tmp/open_test_2", O_RDONLY, 0);
+        assert!(example_len == read_fd_len as usize);
+        assert!(io::sendfile(write_fd, read_fd, None, read_fd_len as usize) == Ok(read_fd_len as usize));
+        let _ = file::close(read_fd);
+        let _ = file::close(write_fd);
+        let write_fd = file::open("/tmp/open_test_2", O_RDONLY, 0).unwrap();
         let mut buf:Vec<u8> = vec![0;30];
-        assert!(io::read(write_fd, buf.as_mut_slice()) == example_len);
-        file::close(write_fd);
+        assert!(io::read(write_fd, buf.as_mut_slice()) == Ok(example_len));
+        let _ = file::close(write_fd);
     }
     {
         use fs::ln;
@@ -82,18 +90,18 @@ fn it_works() {
         Command::new("rm").arg("/tmp/open_test.ln").output().unwrap();
         Command::new("ln").arg("-s").arg("/tmp/open_test").arg("/tmp/open_test.ln").output().unwrap();
         assert!(ln::readlink("/tmp/open_test.ln").unwrap() == "/tmp/open_test");
-        assert!(ln::readlink("/tmp/open_test") == Err(-EINVAL));
+        assert!(ln::readlink("/tmp/open_test") == Err(EINVAL));
     }
     {
         use fs::ln;
         let r = ln::unlink("/tmp/klutplut");
-        assert!(r == -ENOENT);
-        let fd = file::open("/tmp/13333333", O_WRONLY|O_CREAT, 0o640);
+        assert!(r == Err(ENOENT));
+        let fd = file::open("/tmp/13333333", O_WRONLY|O_CREAT, 0o640).unwrap();
         assert!(fd > 0);
-        assert!(io::write(fd, &[55, 55, 55]) > 0);
-        assert!(ln::rename("/tmp/13333333", "/tmp/122") == 0);
-        assert!(ln::unlink("/tmp/122") == 0);
-        assert!(file::close(fd) == 0);
+        assert!(io::write(fd, &[55, 55, 55]).unwrap() > 0);
+        assert!(ln::rename("/tmp/13333333", "/tmp/122").is_ok());
+        assert!(ln::unlink("/tmp/122").is_ok());
+        assert!(file::close(fd).is_ok());
     }
     {
         use fs::ln;
@@ -101,9 +109,9 @@ fn it_works() {
         use std::process::Command;
         Command::new("rm").arg("/tmp/open_test.hardlink").output().unwrap();
         
-        let fd = file::open("/tmp/open_test", O_RDONLY, 0); assert!(fd > 0);
+        let fd = file::open("/tmp/open_test", O_RDONLY, 0).unwrap(); assert!(fd > 0);
         let sympath = format!("/proc/self/fd/{}", fd);
-        assert!(ln::linkat(0, &sympath, 0, "/tmp/open_test.hardlink", AT_SYMLINK_FOLLOW) == 0);
+        assert!(ln::linkat(0, &sympath, 0, "/tmp/open_test.hardlink", AT_SYMLINK_FOLLOW).is_ok());
     }
     
 }
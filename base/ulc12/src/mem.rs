@@ -0,0 +1,287 @@
+//! Memory mappings and memory backed files.
+//!
+//! `Mapping` owns a mapping made by `mmap`, unmapped when dropped, and
+//! exposes it as a byte slice. `memfd_create` makes an anonymous file; once
+//! sealed against writing, shrinking and growing (`add_seals`), its contents
+//! can't change anymore, and it can be handed to another process that maps
+//! it without trusting the sender.
+
+use std::slice;
+use errno::*;
+use fd::Fd;
+use fs::fcntl::fcntl;
+use ::File;
+use self::flags::*;
+
+
+pub fn mmap(addr: usize, length: usize, prot: usize, flags: usize, fd: File, offset: u64) -> Result<usize> {
+    checked_syscall!(MMAP, addr, length, prot, flags, fd, offset).map(|a| a as usize)
+}
+
+pub fn munmap(addr: usize, length: usize) -> Result<()> {
+    checked_syscall!(MUNMAP, addr, length).map(|_| ())
+}
+
+pub fn mprotect(addr: usize, length: usize, prot: usize) -> Result<()> {
+    checked_syscall!(MPROTECT, addr, length, prot).map(|_| ())
+}
+
+pub fn madvise(addr: usize, length: usize, advice: usize) -> Result<()> {
+    checked_syscall!(MADVISE, addr, length, advice).map(|_| ())
+}
+
+/// Resizes the mapping at `addr`. Returns its new address, which only
+/// differs with `MREMAP_MAYMOVE` (or `MREMAP_FIXED`, to `new_addr`).
+pub fn mremap(addr: usize, old_length: usize, new_length: usize, flags: usize, new_addr: usize) -> Result<usize> {
+    checked_syscall!(MREMAP, addr, old_length, new_length, flags, new_addr).map(|a| a as usize)
+}
+
+pub fn msync(addr: usize, length: usize, flags: usize) -> Result<()> {
+    checked_syscall!(MSYNC, addr, length, flags).map(|_| ())
+}
+
+
+/// A mapping of memory, unmapped when dropped.
+pub struct Mapping {
+    addr    :usize,
+    len     :usize,
+    prot    :usize
+}
+
+impl Mapping {
+    /// Maps `length` bytes of `fd` from `offset`, wherever the kernel
+    /// chooses.
+    pub fn new(length: usize, prot: usize, flags: usize, fd: File, offset: u64) -> Result<Mapping> {
+        mmap(0, length, prot, flags, fd, offset).map(|a| Mapping {addr: a, len: length, prot: prot})
+    }
+
+    /// Maps `length` bytes of private, zeroed memory.
+    pub fn anonymous(length: usize, prot: usize) -> Result<Mapping> {
+        Mapping::new(length, prot, MAP_PRIVATE|MAP_ANONYMOUS, -1, 0)
+    }
+
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The mapped memory. Reading memory mapped without `PROT_READ`, or
+    /// past the end of the file it maps, raises a signal.
+    ///
+    /// The slice is only as immutable as what it maps: through a
+    /// `MAP_SHARED` mapping, writes to the file (by this or any other
+    /// process, or through another mapping) show up in it, so its contents
+    /// may change while it is borrowed. Only rely on it staying the same
+    /// for private mappings, or for files sealed with `F_SEAL_WRITE` (see
+    /// `add_seals`).
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.addr as *const u8, self.len) }
+    }
+
+    /// The mapped memory, if it is writable.
+    pub fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+        if self.prot & PROT_WRITE == 0 {
+            return None;
+        }
+        Some(unsafe { slice::from_raw_parts_mut(self.addr as *mut u8, self.len) })
+    }
+
+    pub fn protect(&mut self, prot: usize) -> Result<()> {
+        try!(mprotect(self.addr, self.len, prot));
+        self.prot = prot;
+        Ok(())
+    }
+
+    pub fn advise(&self, advice: usize) -> Result<()> {
+        madvise(self.addr, self.len, advice)
+    }
+
+    /// Writes changes of a shared file mapping back to the file.
+    pub fn sync(&self, flags: usize) -> Result<()> {
+        msync(self.addr, self.len, flags)
+    }
+
+    /// Resizes the mapping, moving it if need be.
+    pub fn resize(&mut self, length: usize) -> Result<()> {
+        self.addr = try!(mremap(self.addr, self.len, length, MREMAP_MAYMOVE, 0));
+        self.len = length;
+        Ok(())
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        let _ = munmap(self.addr, self.len);
+    }
+}
+
+
+/// Creates an anonymous file, in memory. `name` only shows in
+/// /proc/self/fd. `flags` are `MFD_*`; the file can only be sealed with
+/// `MFD_ALLOW_SEALING`.
+pub fn memfd_create(name: &str, flags: usize) -> Result<Fd> {
+    use std::ffi::CString;
+    use std::os::unix::io::FromRawFd;
+    match CString::new(name) {
+        Ok(n) => {
+            checked_syscall!(MEMFD_CREATE, n.as_ptr(), flags).map(|fd| unsafe { Fd::from_raw_fd(fd as i32) })
+        },
+        Err(_) => {
+            Err(EINVAL)
+        }
+    }
+}
+
+/// Adds the `F_SEAL_*` seals `seals` to the memfd `fd`. Seals can't be
+/// removed; `F_SEAL_SEAL` prevents adding more.
+pub fn add_seals(fd: File, seals: usize) -> Result<()> {
+    fcntl(fd, F_ADD_SEALS, seals).map(|_| ())
+}
+
+/// The seals of `fd`. Fails with `EINVAL` if it isn't a memfd.
+pub fn get_seals(fd: File) -> Result<usize> {
+    fcntl(fd, F_GET_SEALS, 0).map(|s| s as usize)
+}
+
+
+pub mod flags {
+    pub use ::fs::flags::F_ADD_SEALS;
+    pub use ::fs::flags::F_GET_SEALS;
+    pub use ::fs::flags::F_SEAL_SEAL;
+    pub use ::fs::flags::F_SEAL_SHRINK;
+    pub use ::fs::flags::F_SEAL_GROW;
+    pub use ::fs::flags::F_SEAL_WRITE;
+    pub use ::fs::flags::F_SEAL_FUTURE_WRITE;
+    pub use ::fs::flags::F_SEAL_EXEC;
+
+    // asm-generic/mman-common.h (the same on x86_64, aarch64 and riscv64)
+    pub const PROT_NONE:usize          = 0x0;
+    pub const PROT_READ:usize          = 0x1;
+    pub const PROT_WRITE:usize         = 0x2;
+    pub const PROT_EXEC:usize          = 0x4;
+    pub const MAP_SHARED:usize         = 0x01;
+    pub const MAP_PRIVATE:usize        = 0x02;
+    pub const MAP_SHARED_VALIDATE:usize = 0x03;
+    pub const MAP_FIXED:usize          = 0x10;
+    pub const MAP_ANONYMOUS:usize      = 0x20;
+    pub const MAP_GROWSDOWN:usize      = 0x0100;
+    pub const MAP_LOCKED:usize         = 0x2000;
+    pub const MAP_NORESERVE:usize      = 0x4000;
+    pub const MAP_POPULATE:usize       = 0x8000;
+    pub const MAP_NONBLOCK:usize       = 0x10000;
+    pub const MAP_STACK:usize          = 0x20000;
+    pub const MAP_HUGETLB:usize        = 0x40000;
+    pub const MAP_FIXED_NOREPLACE:usize = 0x100000;
+    pub const MS_ASYNC:usize           = 1;
+    pub const MS_INVALIDATE:usize      = 2;
+    pub const MS_SYNC:usize            = 4;
+    pub const MADV_NORMAL:usize        = 0;
+    pub const MADV_RANDOM:usize        = 1;
+    pub const MADV_SEQUENTIAL:usize    = 2;
+    pub const MADV_WILLNEED:usize      = 3;
+    pub const MADV_DONTNEED:usize      = 4;
+    pub const MADV_FREE:usize          = 8;
+    pub const MADV_REMOVE:usize        = 9;
+    pub const MADV_DONTFORK:usize      = 10;
+    pub const MADV_DOFORK:usize        = 11;
+    pub const MADV_MERGEABLE:usize     = 12;
+    pub const MADV_UNMERGEABLE:usize   = 13;
+    pub const MADV_HUGEPAGE:usize      = 14;
+    pub const MADV_NOHUGEPAGE:usize    = 15;
+    pub const MADV_DONTDUMP:usize      = 16;
+    pub const MADV_DODUMP:usize        = 17;
+
+    // include/uapi/linux/mman.h
+    pub const MREMAP_MAYMOVE:usize     = 1;
+    pub const MREMAP_FIXED:usize       = 2;
+    pub const MREMAP_DONTUNMAP:usize   = 4;
+
+    // include/uapi/linux/memfd.h
+    pub const MFD_CLOEXEC:usize        = 0x0001;
+    pub const MFD_ALLOW_SEALING:usize  = 0x0002;
+    pub const MFD_HUGETLB:usize        = 0x0004;
+    pub const MFD_NOEXEC_SEAL:usize    = 0x0008;
+    pub const MFD_EXEC:usize           = 0x0010;
+}
+
+
+#[test]
+fn test_mapping() {
+    let page = 4096;
+    let mut m = Mapping::anonymous(page, PROT_READ|PROT_WRITE).unwrap();
+    assert!(m.as_slice().iter().all(|b| *b == 0));
+    m.as_mut_slice().unwrap()[..5].copy_from_slice(b"hello");
+    assert!(&m.as_slice()[..5] == b"hello");
+
+    // growing keeps the contents
+    m.resize(4 * page).unwrap();
+    assert!(m.len() == 4 * page);
+    assert!(&m.as_slice()[..5] == b"hello" && m.as_slice()[3 * page] == 0);
+    m.advise(MADV_SEQUENTIAL).unwrap();
+    m.advise(MADV_DONTNEED).unwrap(); // private anonymous memory reads as zeroes again
+    assert!(m.as_slice()[0] == 0);
+
+    m.protect(PROT_READ).unwrap();
+    assert!(m.as_mut_slice().is_none());
+    assert!(Mapping::anonymous(0, PROT_READ).err() == Some(EINVAL));
+    assert!(mprotect(m.addr() + 1, page, PROT_READ) == Err(EINVAL));
+}
+
+#[test]
+fn test_memfd() {
+    use std::io::Read;
+    use std::io::Write;
+    use std::io::Seek;
+    use std::io::SeekFrom;
+    use process::spawn::Spawn;
+    use process::spawn::Stdio;
+
+    let mut f = memfd_create("ulc12 test", MFD_CLOEXEC|MFD_ALLOW_SEALING).unwrap();
+    f.write_all(b"sealed contents").unwrap();
+    // vm.memfd_noexec seals memfds not created executable against exec
+    let initial = get_seals(f.raw()).unwrap();
+    assert!(initial & !F_SEAL_EXEC == 0);
+
+    // a shared writable mapping would keep F_SEAL_WRITE from being added
+    {
+        let mut m = Mapping::new(15, PROT_READ|PROT_WRITE, MAP_SHARED, f.raw(), 0).unwrap();
+        m.as_mut_slice().unwrap()[0] = b'S';
+        m.sync(MS_SYNC).unwrap();
+        assert!(add_seals(f.raw(), F_SEAL_WRITE) == Err(EBUSY));
+    }
+    add_seals(f.raw(), F_SEAL_SHRINK|F_SEAL_GROW|F_SEAL_WRITE|F_SEAL_SEAL).unwrap();
+    assert!(get_seals(f.raw()) == Ok(initial|F_SEAL_SHRINK|F_SEAL_GROW|F_SEAL_WRITE|F_SEAL_SEAL));
+    // kernels before 6.3 don't know F_SEAL_EXEC
+    let r = add_seals(f.raw(), F_SEAL_EXEC);
+    assert!(r == Err(EPERM) || r == Err(EINVAL));
+
+    // now immutable
+    assert!(f.write(b"more").is_err());
+    assert!(f.ftruncate(0) == Err(EPERM));
+    assert!(Mapping::new(15, PROT_READ|PROT_WRITE, MAP_SHARED, f.raw(), 0).err() == Some(EPERM));
+    let m = Mapping::new(15, PROT_READ, MAP_SHARED, f.raw(), 0).unwrap();
+    assert!(m.as_slice() == b"Sealed contents");
+
+    // and can be handed to another process
+    f.seek(SeekFrom::Start(0)).unwrap();
+    let mut c = Spawn::new("/bin/cat").stdin(Stdio::Fd(f.raw())).stdout(Stdio::Piped).spawn().unwrap();
+    let mut s = String::new();
+    c.stdout.as_mut().unwrap().read_to_string(&mut s).unwrap();
+    assert!(s == "Sealed contents");
+    assert!(c.wait().unwrap().success());
+
+    // unsealable, unless vm.memfd_noexec made it sealable and sealed it
+    // against exec
+    let plain = memfd_create("unsealable", MFD_CLOEXEC).unwrap();
+    let seals = get_seals(plain.raw()).unwrap();
+    if seals == F_SEAL_SEAL {
+        assert!(add_seals(plain.raw(), F_SEAL_WRITE) == Err(EPERM));
+    }
+    else {
+        assert!(seals & F_SEAL_EXEC != 0);
+    }
+    assert!(get_seals(0) == Err(EINVAL) || get_seals(0) == Err(EBADF));
+}
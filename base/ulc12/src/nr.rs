@@ -0,0 +1,117 @@
+//! System call numbers, per architecture.
+//!
+//! Only the calls ulc12 makes are listed. aarch64 and riscv64 share the
+//! generic table (asm-generic/unistd.h), which leaves out the calls
+//! superseded by an `*at` variant (`open`, `link`, `rename`, ...): the
+//! wrappers of those go through the variant on every architecture.
+
+#[cfg(all(target_os="linux", target_arch="x86_64"))]
+pub use self::x86_64::*;
+#[cfg(all(target_os="linux", any(target_arch="aarch64", target_arch="riscv64")))]
+pub use self::generic::*;
+
+// arch/x86/entry/syscalls/syscall_64.tbl
+#[cfg(all(target_os="linux", target_arch="x86_64"))]
+mod x86_64 {
+    pub const READ:usize            = 0;
+    pub const WRITE:usize           = 1;
+    pub const CLOSE:usize           = 3;
+    pub const FSTAT:usize           = 5;
+    pub const LSEEK:usize           = 8;
+    pub const MMAP:usize            = 9;
+    pub const MPROTECT:usize        = 10;
+    pub const MUNMAP:usize          = 11;
+    pub const RT_SIGACTION:usize    = 13;
+    pub const RT_SIGPROCMASK:usize  = 14;
+    pub const MREMAP:usize          = 25;
+    pub const MSYNC:usize           = 26;
+    pub const MADVISE:usize         = 28;
+    pub const GETPID:usize          = 39;
+    pub const SENDFILE:usize        = 40;
+    pub const CLONE:usize           = 56;
+    pub const WAIT4:usize           = 61;
+    pub const KILL:usize            = 62;
+    pub const FCNTL:usize           = 72;
+    pub const FLOCK:usize           = 73;
+    pub const FSYNC:usize           = 74;
+    pub const FDATASYNC:usize       = 75;
+    pub const TRUNCATE:usize        = 76;
+    pub const FTRUNCATE:usize       = 77;
+    pub const CHDIR:usize           = 80;
+    pub const FCHDIR:usize          = 81;
+    pub const GETPPID:usize         = 110;
+    pub const SETSID:usize          = 112;
+    pub const PRCTL:usize           = 157;
+    pub const GETTID:usize          = 186;
+    pub const GETDENTS64:usize      = 217;
+    pub const EXIT_GROUP:usize      = 231;
+    pub const TGKILL:usize          = 234;
+    pub const WAITID:usize          = 247;
+    pub const OPENAT:usize          = 257;
+    pub const MKDIRAT:usize         = 258;
+    pub const NEWFSTATAT:usize      = 262;
+    pub const UNLINKAT:usize        = 263;
+    pub const LINKAT:usize          = 265;
+    pub const SYMLINKAT:usize       = 266;
+    pub const READLINKAT:usize      = 267;
+    pub const DUP3:usize            = 292;
+    pub const PIPE2:usize           = 293;
+    pub const RENAMEAT2:usize       = 316;
+    pub const MEMFD_CREATE:usize    = 319;
+    pub const EXECVEAT:usize        = 322;
+    pub const STATX:usize           = 332;
+    pub const CLONE3:usize          = 435;
+}
+
+// include/uapi/asm-generic/unistd.h
+#[cfg(all(target_os="linux", any(target_arch="aarch64", target_arch="riscv64")))]
+mod generic {
+    pub const DUP3:usize            = 24;
+    pub const FCNTL:usize           = 25;
+    pub const FLOCK:usize           = 32;
+    pub const MKDIRAT:usize         = 34;
+    pub const UNLINKAT:usize        = 35;
+    pub const SYMLINKAT:usize       = 36;
+    pub const LINKAT:usize          = 37;
+    pub const TRUNCATE:usize        = 45;
+    pub const FTRUNCATE:usize       = 46;
+    pub const CHDIR:usize           = 49;
+    pub const FCHDIR:usize          = 50;
+    pub const OPENAT:usize          = 56;
+    pub const CLOSE:usize           = 57;
+    pub const PIPE2:usize           = 59;
+    pub const GETDENTS64:usize      = 61;
+    pub const LSEEK:usize           = 62;
+    pub const READ:usize            = 63;
+    pub const WRITE:usize           = 64;
+    pub const SENDFILE:usize        = 71;
+    pub const READLINKAT:usize      = 78;
+    pub const NEWFSTATAT:usize      = 79;
+    pub const FSTAT:usize           = 80;
+    pub const FSYNC:usize           = 82;
+    pub const FDATASYNC:usize       = 83;
+    pub const EXIT_GROUP:usize      = 94;
+    pub const WAITID:usize          = 95;
+    pub const KILL:usize            = 129;
+    pub const TGKILL:usize          = 131;
+    pub const RT_SIGACTION:usize    = 134;
+    pub const RT_SIGPROCMASK:usize  = 135;
+    pub const SETSID:usize          = 157;
+    pub const PRCTL:usize           = 167;
+    pub const GETPID:usize          = 172;
+    pub const GETPPID:usize         = 173;
+    pub const GETTID:usize          = 178;
+    pub const MUNMAP:usize          = 215;
+    pub const MREMAP:usize          = 216;
+    pub const CLONE:usize           = 220;
+    pub const MMAP:usize            = 222;
+    pub const MPROTECT:usize        = 226;
+    pub const MSYNC:usize           = 227;
+    pub const MADVISE:usize         = 233;
+    pub const WAIT4:usize           = 260;
+    pub const RENAMEAT2:usize       = 276;
+    pub const MEMFD_CREATE:usize    = 279;
+    pub const EXECVEAT:usize        = 281;
+    pub const STATX:usize           = 291;
+    pub const CLONE3:usize          = 435;
+}
@@ -0,0 +1,438 @@
+//! Processes: creating, replacing, waiting for and signalling them.
+//!
+//! `fork` is `clone` with only `SIGCHLD`, which every architecture has (not
+//! all have a `fork` syscall). Neither runs `pthread_atfork` handlers: in a
+//! multi-threaded program the child may only make system calls until it
+//! execs or exits, as `spawn` does.
+
+use std::ffi::CString;
+use errno::*;
+use fd::Fd;
+use ::File;
+use ::Dir;
+
+pub mod spawn;
+
+pub type Pid = isize;
+
+
+/// How a child changed state, decoded from a `wait4` status or a `waitid`
+/// siginfo.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WaitStatus {
+    Exited(usize),              // exit status
+    Signaled(usize, bool),      // signal, whether it dumped core
+    Stopped(usize),             // signal
+    Continued
+}
+
+impl WaitStatus {
+    /// Decodes the `status` `wait4` stores (`WIFEXITED` and friends).
+    pub fn from_raw(status: i32) -> WaitStatus {
+        let s = status as usize;
+        if s & 0x7f == 0 {
+            WaitStatus::Exited(s >> 8 & 0xff)
+        }
+        else if s & 0xffff == 0xffff {
+            WaitStatus::Continued
+        }
+        else if s & 0xff == 0x7f {
+            WaitStatus::Stopped(s >> 8 & 0xff)
+        }
+        else {
+            WaitStatus::Signaled(s & 0x7f, s & 0x80 != 0)
+        }
+    }
+
+    /// Whether the child exited with status 0.
+    pub fn success(&self) -> bool {
+        *self == WaitStatus::Exited(0)
+    }
+}
+
+
+/// Returns twice: the child's pid in the parent, 0 in the child.
+pub fn fork() -> Result<Pid> {
+    use self::flags::SIGCHLD;
+    checked_syscall!(CLONE, SIGCHLD, 0, 0, 0, 0)
+}
+
+/// include/uapi/linux/sched.h's `clone_args`.
+#[derive(Clone, Copy, Default, Debug)]
+#[repr(C)]
+pub struct CloneArgs {
+    pub flags           :u64,
+    pub pidfd           :u64,
+    pub child_tid       :u64,
+    pub parent_tid      :u64,
+    pub exit_signal     :u64,
+    pub stack           :u64,
+    pub stack_size      :u64,
+    pub tls             :u64,
+    pub set_tid         :u64,
+    pub set_tid_size    :u64,
+    pub cgroup          :u64
+}
+
+/// Creates a process (or thread) as described by `args`. Returns like
+/// `fork`. Fails with `ENOSYS` before linux 5.3.
+pub fn clone3(args: &mut CloneArgs) -> Result<Pid> {
+    use std::mem::size_of;
+    checked_syscall!(CLONE3, args as *mut CloneArgs, size_of::<CloneArgs>())
+}
+
+
+// A program and its arguments and environment, as execve takes them. Built
+// before forking, so that exec'ing allocates nothing.
+struct Exec {
+    path        :CString,
+    _strings    :Vec<CString>,          // owns what argv and envp point to
+    argv        :Vec<*const u8>,        // NULL terminated
+    envp        :Vec<*const u8>
+}
+
+impl Exec {
+    fn new<A: AsRef<[u8]>, E: AsRef<[u8]>>(pathname: &str, argv: &[A], envp: &[E]) -> Result<Exec> {
+        let mut strings: Vec<CString> = vec![];
+        for s in argv.iter().map(|a| a.as_ref()).chain(envp.iter().map(|e| e.as_ref())) {
+            strings.push(try!(CString::new(s).map_err(|_| EINVAL)));
+        }
+        let mut argv_ptrs: Vec<*const u8> = strings[..argv.len()].iter().map(|s| s.as_ptr() as *const u8).collect();
+        let mut envp_ptrs: Vec<*const u8> = strings[argv.len()..].iter().map(|s| s.as_ptr() as *const u8).collect();
+        argv_ptrs.push(0 as *const u8);
+        envp_ptrs.push(0 as *const u8);
+        Ok(Exec {
+            path        : try!(CString::new(pathname).map_err(|_| ENOENT)),
+            _strings    : strings,
+            argv        : argv_ptrs,
+            envp        : envp_ptrs
+        })
+    }
+
+    fn execveat(&self, dirfd: Dir, flags: usize) -> Errno {
+        match checked_syscall!(EXECVEAT, dirfd, self.path.as_ptr(), self.argv.as_ptr(), self.envp.as_ptr(), flags) {
+            Err(e) => e,
+            Ok(_) => EINVAL // unreachable: exec doesn't return on success
+        }
+    }
+}
+
+/// Replaces the program of the process with `pathname`. Only returns on
+/// failure.
+pub fn execve(pathname: &str, argv: &[&str], envp: &[&str]) -> Errno {
+    execveat(self::flags::AT_FDCWD, pathname, argv, envp, 0)
+}
+
+/// Like `execve`, with `pathname` relative to `dirfd`. With `AT_EMPTY_PATH`
+/// and an empty `pathname`, execs the file `dirfd` itself (`fexecve`).
+pub fn execveat(dirfd: Dir, pathname: &str, argv: &[&str], envp: &[&str], flags: usize) -> Errno {
+    match Exec::new(pathname, argv, envp) {
+        Ok(e) => e.execveat(dirfd, flags),
+        Err(e) => e
+    }
+}
+
+/// Ends every thread of the process.
+pub fn exit_group(status: usize) -> ! {
+    loop {
+        let _ = signed_syscall!(EXIT_GROUP, status);
+    }
+}
+
+
+/// include/uapi/linux/resource.h's `rusage`.
+#[derive(Clone, Copy, Default, Debug)]
+#[repr(C)]
+pub struct Rusage {
+    pub ru_utime_sec    :i64,
+    pub ru_utime_usec   :i64,
+    pub ru_stime_sec    :i64,
+    pub ru_stime_usec   :i64,
+    pub ru_maxrss       :i64,
+    pub ru_ixrss        :i64,
+    pub ru_idrss        :i64,
+    pub ru_isrss        :i64,
+    pub ru_minflt       :i64,
+    pub ru_majflt       :i64,
+    pub ru_nswap        :i64,
+    pub ru_inblock      :i64,
+    pub ru_oublock      :i64,
+    pub ru_msgsnd       :i64,
+    pub ru_msgrcv       :i64,
+    pub ru_nsignals     :i64,
+    pub ru_nvcsw        :i64,
+    pub ru_nivcsw       :i64
+}
+
+/// Waits for the child `pid` (or any child if -1, or any in a group) to
+/// change state. With `WNOHANG`, a returned pid of 0 means none has.
+pub fn wait4(pid: Pid, options: usize, rusage: Option<&mut Rusage>) -> Result<(Pid, WaitStatus)> {
+    let mut status: i32 = 0;
+    let r = match rusage {
+        Some(u) => checked_syscall!(WAIT4, pid, (&mut status) as *mut i32, options, u as *mut Rusage),
+        None => checked_syscall!(WAIT4, pid, (&mut status) as *mut i32, options, 0)
+    };
+    r.map(|p| (p, WaitStatus::from_raw(status)))
+}
+
+// the start of a siginfo_t for SIGCHLD (it is 128 bytes long)
+#[repr(C)]
+struct SigInfo {
+    si_signo    :i32,
+    si_errno    :i32,
+    si_code     :i32,
+    __pad0      :i32,
+    si_pid      :i32,
+    si_uid      :u32,
+    si_status   :i32,
+    __rest      :[i32; 25]
+}
+
+/// Waits for a child, selected by `idtype` (`P_PID`, `P_PGID`, `P_PIDFD`
+/// or `P_ALL`) and `id`, to change state as `options` (`WEXITED`,
+/// `WSTOPPED`, `WCONTINUED`) asks. With `WNOHANG`, a returned pid of 0
+/// means none has.
+pub fn waitid(idtype: usize, id: isize, options: usize) -> Result<(Pid, WaitStatus)> {
+    use self::flags::*;
+    let mut info: SigInfo = unsafe { ::std::mem::zeroed() };
+    try!(checked_syscall!(WAITID, idtype, id, (&mut info) as *mut SigInfo, options, 0));
+    let status = info.si_status as usize;
+    let s = match info.si_code {
+        CLD_EXITED                  => WaitStatus::Exited(status),
+        CLD_KILLED                  => WaitStatus::Signaled(status, false),
+        CLD_DUMPED                  => WaitStatus::Signaled(status, true),
+        CLD_TRAPPED | CLD_STOPPED   => WaitStatus::Stopped(status),
+        _                           => WaitStatus::Continued
+    };
+    Ok((info.si_pid as Pid, s))
+}
+
+
+/// Returns the read and write ends of a new pipe. `flags` are `O_CLOEXEC`,
+/// `O_NONBLOCK` and `O_DIRECT`.
+pub fn pipe2(flags: usize) -> Result<(Fd, Fd)> {
+    use std::os::unix::io::FromRawFd;
+    let mut fds: [i32; 2] = [-1, -1];
+    try!(checked_syscall!(PIPE2, fds.as_mut_ptr(), flags));
+    Ok(unsafe { (Fd::from_raw_fd(fds[0]), Fd::from_raw_fd(fds[1])) })
+}
+
+/// Makes `newfd` a copy of `oldfd`, closing what it was first. `flags` is
+/// 0 or `O_CLOEXEC`. Fails with `EINVAL` if the two are the same.
+pub fn dup3(oldfd: File, newfd: File, flags: usize) -> Result<File> {
+    checked_syscall!(DUP3, oldfd, newfd, flags)
+}
+
+
+pub fn kill(pid: Pid, sig: usize) -> Result<()> {
+    checked_syscall!(KILL, pid, sig).map(|_| ())
+}
+
+/// Sends `sig` to the thread `tid` of the process `tgid`.
+pub fn tgkill(tgid: Pid, tid: Pid, sig: usize) -> Result<()> {
+    checked_syscall!(TGKILL, tgid, tid, sig).map(|_| ())
+}
+
+pub fn getpid() -> Pid {
+    signed_syscall!(GETPID)
+}
+
+pub fn getppid() -> Pid {
+    signed_syscall!(GETPPID)
+}
+
+pub fn gettid() -> Pid {
+    signed_syscall!(GETTID)
+}
+
+pub fn setsid() -> Result<Pid> {
+    checked_syscall!(SETSID)
+}
+
+/// Operates on the process as `option` (`PR_*`) says. Pointers are passed
+/// as `usize`s.
+pub fn prctl(option: usize, arg2: usize, arg3: usize, arg4: usize, arg5: usize) -> Result<isize> {
+    checked_syscall!(PRCTL, option, arg2, arg3, arg4, arg5)
+}
+
+
+pub mod flags {
+    pub use ::fs::flags::AT_FDCWD;
+    pub use ::fs::flags::AT_EMPTY_PATH;
+    pub use ::fs::flags::AT_SYMLINK_NOFOLLOW;
+
+    // asm-generic/signal.h (the same on x86_64, aarch64 and riscv64)
+    pub const SIGHUP:usize     = 1;
+    pub const SIGINT:usize     = 2;
+    pub const SIGQUIT:usize    = 3;
+    pub const SIGILL:usize     = 4;
+    pub const SIGTRAP:usize    = 5;
+    pub const SIGABRT:usize    = 6;
+    pub const SIGBUS:usize     = 7;
+    pub const SIGFPE:usize     = 8;
+    pub const SIGKILL:usize    = 9;
+    pub const SIGUSR1:usize    = 10;
+    pub const SIGSEGV:usize    = 11;
+    pub const SIGUSR2:usize    = 12;
+    pub const SIGPIPE:usize    = 13;
+    pub const SIGALRM:usize    = 14;
+    pub const SIGTERM:usize    = 15;
+    pub const SIGSTKFLT:usize  = 16;
+    pub const SIGCHLD:usize    = 17;
+    pub const SIGCONT:usize    = 18;
+    pub const SIGSTOP:usize    = 19;
+    pub const SIGTSTP:usize    = 20;
+    pub const SIGTTIN:usize    = 21;
+    pub const SIGTTOU:usize    = 22;
+    pub const SIGURG:usize     = 23;
+    pub const SIGXCPU:usize    = 24;
+    pub const SIGXFSZ:usize    = 25;
+    pub const SIGVTALRM:usize  = 26;
+    pub const SIGPROF:usize    = 27;
+    pub const SIGWINCH:usize   = 28;
+    pub const SIGIO:usize      = 29;
+    pub const SIGPWR:usize     = 30;
+    pub const SIGSYS:usize     = 31;
+
+    // include/uapi/linux/sched.h
+    pub const CLONE_VM:u64             = 0x00000100;
+    pub const CLONE_FS:u64             = 0x00000200;
+    pub const CLONE_FILES:u64          = 0x00000400;
+    pub const CLONE_SIGHAND:u64        = 0x00000800;
+    pub const CLONE_PIDFD:u64          = 0x00001000;
+    pub const CLONE_PTRACE:u64         = 0x00002000;
+    pub const CLONE_VFORK:u64          = 0x00004000;
+    pub const CLONE_PARENT:u64         = 0x00008000;
+    pub const CLONE_THREAD:u64         = 0x00010000;
+    pub const CLONE_NEWNS:u64          = 0x00020000;
+    pub const CLONE_SYSVSEM:u64        = 0x00040000;
+    pub const CLONE_SETTLS:u64         = 0x00080000;
+    pub const CLONE_PARENT_SETTID:u64  = 0x00100000;
+    pub const CLONE_CHILD_CLEARTID:u64 = 0x00200000;
+    pub const CLONE_CHILD_SETTID:u64   = 0x01000000;
+    pub const CLONE_NEWCGROUP:u64      = 0x02000000;
+    pub const CLONE_NEWUTS:u64         = 0x04000000;
+    pub const CLONE_NEWIPC:u64         = 0x08000000;
+    pub const CLONE_NEWUSER:u64        = 0x10000000;
+    pub const CLONE_NEWPID:u64         = 0x20000000;
+    pub const CLONE_NEWNET:u64         = 0x40000000;
+    pub const CLONE_IO:u64             = 0x80000000;
+    pub const CLONE_CLEAR_SIGHAND:u64  = 0x100000000;
+    pub const CLONE_INTO_CGROUP:u64    = 0x200000000;
+
+    // include/uapi/linux/wait.h
+    pub const WNOHANG:usize        = 0x00000001;
+    pub const WUNTRACED:usize      = 0x00000002;
+    pub const WSTOPPED:usize       = WUNTRACED;
+    pub const WEXITED:usize        = 0x00000004;
+    pub const WCONTINUED:usize     = 0x00000008;
+    pub const WNOWAIT:usize        = 0x01000000;
+    pub const __WNOTHREAD:usize    = 0x20000000;
+    pub const __WALL:usize         = 0x40000000;
+    pub const __WCLONE:usize       = 0x80000000;
+    pub const P_ALL:usize          = 0;
+    pub const P_PID:usize          = 1;
+    pub const P_PGID:usize         = 2;
+    pub const P_PIDFD:usize        = 3;
+
+    // asm-generic/siginfo.h (si_code of SIGCHLD)
+    pub const CLD_EXITED:i32       = 1;
+    pub const CLD_KILLED:i32       = 2;
+    pub const CLD_DUMPED:i32       = 3;
+    pub const CLD_TRAPPED:i32      = 4;
+    pub const CLD_STOPPED:i32      = 5;
+    pub const CLD_CONTINUED:i32    = 6;
+
+    // include/uapi/linux/prctl.h (partial)
+    pub const PR_SET_PDEATHSIG:usize           = 1;
+    pub const PR_GET_PDEATHSIG:usize           = 2;
+    pub const PR_GET_DUMPABLE:usize            = 3;
+    pub const PR_SET_DUMPABLE:usize            = 4;
+    pub const PR_SET_NAME:usize                = 15;
+    pub const PR_GET_NAME:usize                = 16;
+    pub const PR_SET_CHILD_SUBREAPER:usize     = 36;
+    pub const PR_GET_CHILD_SUBREAPER:usize     = 37;
+    pub const PR_SET_NO_NEW_PRIVS:usize        = 38;
+    pub const PR_GET_NO_NEW_PRIVS:usize        = 39;
+}
+
+
+#[test]
+fn test_process() {
+    use std::io::Read;
+    use std::io::Write;
+    use self::flags::*;
+    use fs::ln;
+    use fs::file;
+    use fs::fcntl;
+    use fs::flags::{O_CLOEXEC, O_WRONLY, O_CREAT, O_TRUNC};
+
+    // ids
+    let pid = getpid();
+    assert!(ln::readlink("/proc/self").unwrap() == format!("{}", pid));
+    assert!(getppid() > 0 && gettid() > 0);
+    let mut sig: i32 = -1;
+    prctl(PR_GET_PDEATHSIG, (&mut sig) as *mut i32 as usize, 0, 0, 0).unwrap();
+    assert!(sig == 0);
+    assert!(prctl(0xfffff, 0, 0, 0, 0) == Err(EINVAL));
+
+    // pipes and descriptors
+    let (mut r, mut w) = pipe2(O_CLOEXEC).unwrap();
+    w.write_all(b"through").unwrap();
+    // onto a descriptor this test owns: other tests open and close theirs
+    // concurrently
+    let free = fcntl::dupfd_cloexec(w.raw(), 100).unwrap();
+    assert!(free >= 100);
+    let copy = dup3(r.raw(), free, O_CLOEXEC).unwrap();
+    assert!(copy == free);
+    assert!(dup3(copy, copy, 0) == Err(EINVAL));
+    let mut buf = [0u8; 7];
+    assert!(r.read(&mut buf).unwrap() == 7);
+    assert!(&buf == b"through");
+    drop(w);
+    assert!(r.read(&mut buf).unwrap() == 0);
+    file::close(copy).unwrap();
+
+    // fork, exit and wait
+    match fork().unwrap() {
+        0 => exit_group(if setsid() == Ok(getpid()) {3} else {4}),
+        child => {
+            let mut u: Rusage = Default::default();
+            assert!(wait4(child, 0, Some(&mut u)) == Ok((child, WaitStatus::Exited(3))));
+            assert!(wait4(child, WNOHANG, None) == Err(ECHILD));
+        }
+    }
+    let mut args = CloneArgs {exit_signal: SIGCHLD as u64, .. Default::default()};
+    match clone3(&mut args) {
+        Err(ENOSYS) | Err(EPERM) => {}, // old kernels, or filtered by a sandbox
+        Ok(0) => exit_group(5),
+        Ok(child) => assert!(waitid(P_PID, child, WEXITED) == Ok((child, WaitStatus::Exited(5)))),
+        Err(e) => panic!("clone3: {}", e)
+    }
+
+    // signals
+    match fork().unwrap() {
+        0 => loop { let _ = getppid(); },
+        child => {
+            kill(child, SIGKILL).unwrap();
+            assert!(wait4(child, 0, None) == Ok((child, WaitStatus::Signaled(SIGKILL, false))));
+        }
+    }
+    assert!(tgkill(pid, gettid(), 0).is_ok());
+    assert!(kill(pid, 0xffff) == Err(EINVAL));
+
+    // failing execs return
+    assert!(execve("/nonexistent/program", &["program"], &[]) == ENOENT);
+    let path = "/tmp/test_ulc12_exec";
+    let fd = file::open(path, O_WRONLY|O_CREAT|O_TRUNC, 0o644).unwrap();
+    assert!(execveat(fd, "", &["test"], &[], AT_EMPTY_PATH) == EACCES);
+    file::close(fd).unwrap();
+    ln::unlink(path).unwrap();
+
+    // status decoding
+    assert!(WaitStatus::from_raw(0x0100) == WaitStatus::Exited(1));
+    assert!(WaitStatus::from_raw(0x0086) == WaitStatus::Signaled(6, true));
+    assert!(WaitStatus::from_raw(0x137f) == WaitStatus::Stopped(SIGSTOP));
+    assert!(WaitStatus::from_raw(0xffff) == WaitStatus::Continued);
+    assert!(WaitStatus::from_raw(0).success() && !WaitStatus::from_raw(0x0100).success());
+}
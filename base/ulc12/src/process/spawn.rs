@@ -0,0 +1,325 @@
+//! Starting programs in child processes.
+//!
+//! `Spawn` forks, points the child's stdin, stdout and stderr where asked,
+//! and execs the program, using only the system calls in this crate. The
+//! child neither allocates nor takes locks between the fork and the exec, so
+//! spawning is safe from multi-threaded programs. If the exec fails, the
+//! child reports its errno through a close-on-exec pipe, and `spawn` fails
+//! with it.
+
+use errno::*;
+use fd::Fd;
+use fs::fcntl;
+use fs::flags::*;
+use super::Exec;
+use super::Pid;
+use super::WaitStatus;
+use super::fork;
+use super::exit_group;
+use super::wait4;
+use super::pipe2;
+use super::dup3;
+use super::kill;
+use ::File;
+
+
+/// Where a standard stream of the child goes.
+pub enum Stdio {
+    /// The parent's own.
+    Inherit,
+    /// /dev/null.
+    Null,
+    /// A new pipe, whose other end ends up in `Child`.
+    Piped,
+    /// The descriptor given, which stays owned by the caller.
+    Fd(File)
+}
+
+/// A program to run, and how. Built up by chaining, e.g.
+/// `Spawn::new("/bin/echo").arg("hi").stdout(Stdio::Piped).spawn()`.
+pub struct Spawn {
+    path    :String,
+    args    :Vec<Vec<u8>>,
+    env     :Option<Vec<Vec<u8>>>,  // None inherits the parent's
+    dir     :Option<String>,
+    stdio   :[Stdio; 3]
+}
+
+/// A running child process.
+pub struct Child {
+    pub pid     :Pid,
+    pub stdin   :Option<Fd>,    // the ends of `Stdio::Piped` streams
+    pub stdout  :Option<Fd>,
+    pub stderr  :Option<Fd>
+}
+
+impl Spawn {
+    /// Runs the program at `path` (no `PATH` search), with `path` as its
+    /// first argument.
+    pub fn new(path: &str) -> Spawn {
+        Spawn {
+            path    : path.to_string(),
+            args    : vec![path.as_bytes().to_vec()],
+            env     : None,
+            dir     : None,
+            stdio   : [Stdio::Inherit, Stdio::Inherit, Stdio::Inherit]
+        }
+    }
+
+    pub fn arg(mut self, arg: &str) -> Spawn {
+        self.args.push(arg.as_bytes().to_vec());
+        self
+    }
+
+    /// Sets the variable `key` in the child's environment.
+    pub fn env(mut self, key: &str, value: &str) -> Spawn {
+        let mut vars = self.env.take().unwrap_or_else(inherited_env);
+        let prefix = format!("{}=", key).into_bytes();
+        vars.retain(|v| !v.starts_with(&prefix));
+        vars.push(format!("{}={}", key, value).into_bytes());
+        self.env = Some(vars);
+        self
+    }
+
+    /// Starts the child with an empty environment.
+    pub fn env_clear(mut self) -> Spawn {
+        self.env = Some(vec![]);
+        self
+    }
+
+    /// Runs the child in the directory `dir`.
+    pub fn dir(mut self, dir: &str) -> Spawn {
+        self.dir = Some(dir.to_string());
+        self
+    }
+
+    pub fn stdin(mut self, s: Stdio) -> Spawn {
+        self.stdio[0] = s;
+        self
+    }
+
+    pub fn stdout(mut self, s: Stdio) -> Spawn {
+        self.stdio[1] = s;
+        self
+    }
+
+    pub fn stderr(mut self, s: Stdio) -> Spawn {
+        self.stdio[2] = s;
+        self
+    }
+
+    /// Starts the child. Fails with the errno of the exec (or of setting up
+    /// the child) if that fails.
+    pub fn spawn(self) -> Result<Child> {
+        use std::ffi::CString;
+
+        // everything the child needs is prepared here, before forking
+        let env = match self.env {
+            Some(ref e) => e.clone(),
+            None => inherited_env()
+        };
+        let exec = try!(Exec::new(&self.path, &self.args[..], &env[..]));
+        let dir = match self.dir {
+            Some(ref d) => Some(try!(CString::new(d.clone()).map_err(|_| ENOENT))),
+            None => None
+        };
+
+        let mut keep: Vec<Fd> = vec![];         // the child's ends, closed once it runs
+        let mut parent: Vec<Option<Fd>> = vec![None, None, None];   // our ends of pipes
+        let mut sources: [Option<File>; 3] = [None, None, None];
+        for (i, s) in self.stdio.iter().enumerate() {
+            let fd = match *s {
+                Stdio::Inherit => continue,
+                Stdio::Null => {
+                    let f = try!(Fd::open("/dev/null", (if i == 0 {O_RDONLY} else {O_WRONLY}) | O_CLOEXEC, 0));
+                    let raw = f.raw();
+                    keep.push(f);
+                    raw
+                },
+                Stdio::Piped => {
+                    let (r, w) = try!(pipe2(O_CLOEXEC));
+                    let (child, ours) = if i == 0 {(r, w)} else {(w, r)};
+                    let raw = child.raw();
+                    keep.push(child);
+                    parent[i] = Some(ours);
+                    raw
+                },
+                Stdio::Fd(fd) => fd
+            };
+            // keep sources clear of the standard descriptors, which the
+            // child overwrites in turn
+            sources[i] = Some(if fd < 3 && fd != i as File {
+                let copy = try!(dup_cloexec(fd));
+                let raw = copy.raw();
+                keep.push(copy);
+                raw
+            } else {
+                fd
+            });
+        }
+
+        let (report_r, report_w) = try!(pipe2(O_CLOEXEC));
+        let pid = try!(fork());
+        if pid == 0 {
+            // the child: system calls only
+            let e = child_setup(&sources, &dir).err().unwrap_or_else(|| exec.execveat(AT_FDCWD, 0));
+            let code = e.0 as u32;
+            let b = [code as u8, (code >> 8) as u8, (code >> 16) as u8, (code >> 24) as u8];
+            let _ = ::io::write(report_w.raw(), &b);
+            exit_group(127);
+        }
+        drop(report_w);
+        drop(keep);
+
+        // the report pipe closes on exec, or carries the errno of a failure
+        let mut b = [0u8; 4];
+        let mut n = 0;
+        while n < 4 {
+            match retry(|| ::io::read(report_r.raw(), &mut b[n..])) {
+                Ok(0) => break,
+                Ok(k) => n += k,
+                Err(_) => break
+            }
+        }
+        if n == 4 {
+            let _ = retry(|| wait4(pid, 0, None));
+            let code = b[0] as isize | (b[1] as isize) << 8 | (b[2] as isize) << 16 | (b[3] as isize) << 24;
+            return Err(Errno(code));
+        }
+
+        Ok(Child {pid: pid, stdin: parent.remove(0), stdout: parent.remove(0), stderr: parent.remove(0)})
+    }
+}
+
+// the parent's environment, as execve takes it
+fn inherited_env() -> Vec<Vec<u8>> {
+    use std::env;
+    use std::os::unix::ffi::OsStrExt;
+    env::vars_os().map(|(k, v)| {
+        let mut kv = k.as_bytes().to_vec();
+        kv.push(b'=');
+        kv.extend(v.as_bytes().iter().cloned());
+        kv
+    }).collect()
+}
+
+// a close-on-exec copy of `fd`, numbered 3 or above
+fn dup_cloexec(fd: File) -> Result<Fd> {
+    use std::os::unix::io::FromRawFd;
+    fcntl::dupfd_cloexec(fd, 3).map(|c| unsafe { Fd::from_raw_fd(c as i32) })
+}
+
+// rt_sigprocmask's `how`, include/uapi/asm-generic/signal-defs.h
+const SIG_SETMASK: usize = 2;
+
+// Gives the child the signal state a new program expects: the Rust runtime
+// ignores SIGPIPE, and ignored and blocked signals stay so across an exec.
+// Handled signals are reset by the exec itself.
+fn reset_signals() -> Result<()> {
+    use super::flags::SIGPIPE;
+    // a zeroed sigaction is SIG_DFL with no flags and an empty mask, whatever
+    // the architecture lays it out as (at most four words)
+    let default = [0usize; 4];
+    let empty: u64 = 0;
+    try!(checked_syscall!(RT_SIGACTION, SIGPIPE, (&default) as *const [usize; 4], 0, 8));
+    checked_syscall!(RT_SIGPROCMASK, SIG_SETMASK, (&empty) as *const u64, 0, 8).map(|_| ())
+}
+
+// Points the child's standard streams at `sources`, changes into `dir` and
+// resets its signals. Runs between fork and exec, so it must not allocate.
+fn child_setup(sources: &[Option<File>; 3], dir: &Option<::std::ffi::CString>) -> Result<()> {
+    for (i, s) in sources.iter().enumerate() {
+        match *s {
+            Some(fd) if fd == i as File => {
+                // already in place; only needs to survive the exec
+                try!(fcntl::setfd(fd, 0));
+            },
+            Some(fd) => {
+                try!(dup3(fd, i as File, 0));
+            },
+            None => {}
+        }
+    }
+    if let Some(ref d) = *dir {
+        try!(checked_syscall!(CHDIR, d.as_ptr()));
+    }
+    reset_signals()
+}
+
+impl Child {
+    /// Waits for the child to exit (or be killed).
+    pub fn wait(&mut self) -> Result<WaitStatus> {
+        self.stdin = None; // a child reading its stdin to the end would never exit
+        let pid = self.pid;
+        retry(|| wait4(pid, 0, None)).map(|(_, s)| s)
+    }
+
+    pub fn kill(&self, sig: usize) -> Result<()> {
+        kill(self.pid, sig)
+    }
+}
+
+
+#[test]
+fn test_spawn() {
+    use std::io::Read;
+    use std::io::Write;
+    use super::flags::SIGKILL;
+    use super::flags::SIGPIPE;
+
+    let read = |f: &mut Fd| {
+        let mut s = String::new();
+        f.read_to_string(&mut s).unwrap();
+        s
+    };
+
+    let mut c = Spawn::new("/bin/echo").arg("hello").arg("world").stdout(Stdio::Piped).spawn().unwrap();
+    assert!(read(c.stdout.as_mut().unwrap()) == "hello world\n");
+    assert!(c.wait().unwrap().success());
+
+    // stdin, and everything but stdout closed
+    let mut c = Spawn::new("/bin/cat").stdin(Stdio::Piped).stdout(Stdio::Piped).stderr(Stdio::Null).spawn().unwrap();
+    c.stdin.as_mut().unwrap().write_all(b"round trip").unwrap();
+    c.stdin = None;
+    assert!(read(c.stdout.as_mut().unwrap()) == "round trip");
+    assert!(c.wait() == Ok(WaitStatus::Exited(0)));
+
+    // exit statuses and signals
+    let mut c = Spawn::new("/bin/sh").arg("-c").arg("exit 7").spawn().unwrap();
+    assert!(c.wait() == Ok(WaitStatus::Exited(7)));
+    let mut c = Spawn::new("/bin/sleep").arg("10").spawn().unwrap();
+    c.kill(SIGKILL).unwrap();
+    assert!(c.wait() == Ok(WaitStatus::Signaled(SIGKILL, false)));
+
+    // environment and directory
+    let mut c = Spawn::new("/bin/sh").arg("-c").arg("echo $A $B; pwd").env_clear().env("A", "1").env("A", "2")
+        .dir("/tmp").stdout(Stdio::Piped).spawn().unwrap();
+    assert!(read(c.stdout.as_mut().unwrap()) == "2\n/tmp\n");
+    assert!(c.wait().unwrap().success());
+
+    // streams given as descriptors, including ones already in place
+    let (r, w) = pipe2(O_CLOEXEC).unwrap();
+    let mut c = Spawn::new("/bin/sh").arg("-c").arg("echo out; echo err >&2")
+        .stdin(Stdio::Fd(0)).stdout(Stdio::Fd(w.raw())).stderr(Stdio::Fd(w.raw())).spawn().unwrap();
+    drop(w);
+    assert!(c.wait().unwrap().success());
+    let mut r = r;
+    assert!(read(&mut r) == "out\nerr\n");
+
+    // signals are as a new program expects, though the test harness ignores
+    // SIGPIPE
+    let mut c = Spawn::new("/bin/cat").arg("/proc/self/status").stdout(Stdio::Piped).spawn().unwrap();
+    let status = read(c.stdout.as_mut().unwrap());
+    assert!(c.wait().unwrap().success());
+    let mask = |field: &str| {
+        let line = status.lines().find(|l| l.starts_with(field)).unwrap();
+        u64::from_str_radix(line[field.len()..].trim(), 16).unwrap()
+    };
+    assert!(mask("SigIgn:") & (1 << (SIGPIPE - 1)) == 0);
+    assert!(mask("SigBlk:") == 0);
+
+    // failures to exec are reported
+    assert!(Spawn::new("/nonexistent/program").spawn().err() == Some(ENOENT));
+    assert!(Spawn::new("/bin/true").dir("/nonexistent").spawn().err() == Some(ENOENT));
+    assert!(Spawn::new("/tmp").spawn().err() == Some(EACCES));
+}
@@ -29,6 +29,7 @@
 
 
 use ulc12;
+use ulc12::Fd;
 use ulc22::results::StackErr;
 
 use std::path::PathBuf;
@@ -62,9 +63,9 @@ use std::io;
     
 pub struct File {
     orgin_path  : String,
-    orgin_fd    : ulc12::File,
+    orgin_fd    : Option<Fd>,     // open, and locked, while valid
     shadow_path : String,
-    shadow_fd   : ulc12::File,
+    shadow_fd   : Option<Fd>,
     mode        : usize,
     valid       : bool,
     discard     : bool,
@@ -105,9 +106,9 @@ impl File {
         
         let mut f = File {
             orgin_path  : some!(op.to_str(), stacked_return!("orgin path not valid utf8")).to_string(),
-            orgin_fd    : 0,
+            orgin_fd    : None,
             shadow_path : some!(pp.to_str(), stacked_return!("shadow path not valid utf8")).to_string(), // err shouldn't occure
-            shadow_fd   : 0,
+            shadow_fd   : None,
             mode        : mode,
             valid       : false,
             discard     : false,
@@ -121,56 +122,60 @@ impl File {
     }
     
     pub fn set_len(&mut self, length: u64) -> Result<(), StackErr> {
-        if ulc12::fs::file::ftruncate(self.shadow_fd, length) != 0 {
-            stacked_return!("Could not truncate file to {}", length);
+        let r = ok!(self.shadow(), stacked_return!("Could not truncate file to {}", length)).ftruncate(length);
+        if let Err(e) = r {
+            stacked_return!("Could not truncate file to {}: {}", length, e);
         }
         Ok(())
     }
     
+    // the shadow file, if the shadowing state is valid
+    fn shadow(&mut self) -> io::Result<&mut Fd> {
+        use std::io::ErrorKind::*;
+        
+        match self.shadow_fd.as_mut() {
+            Some(fd) if self.valid => Ok(fd),
+            _ => Err(io::Error::new(InvalidInput, "Shadowing state is invalid. File object is unusable.", None))
+        }
+    }
+    
     fn load(&mut self) -> Result<(), StackErr> {
         // orgin file is locked here
-        use ulc12::fs::file;
-        use ulc12::fs::stat;
         use ulc12::io;
         use ulc12::fs::flags::*;
-        use ulc12::errno::*;
+        use ulc12::errno;
         
         self.valid = false;
         
         // opening both orgin and shadow files, and copying contents to shadow
         // linux specific: use another load for alternate systems
+        // (both are closed, and the orgin unlocked, on failure)
         
-        self.orgin_fd = file::open(&self.orgin_path, O_RDWR|O_CREAT, self.mode);
-        if self.orgin_fd < 0 {
-            stacked_return!("Could not open '{}': Error #{}", &self.orgin_path, self.orgin_fd);
-        }
+        let orgin = match Fd::open(&self.orgin_path, O_RDWR|O_CREAT, self.mode) {
+            Ok(fd) => fd,
+            Err(e) => stacked_return!("Could not open '{}': {}", &self.orgin_path, e)
+        };
 
         {        
             let w = if !self.wait {LOCK_NB} else {0};
-            loop {
-                let r = file::flock(self.orgin_fd, LOCK_EX|w);
-                if r == -EINTR {continue;}
-                else if r == 0 {break;}
-                else {
-                    stacked_return!("Could not lock '{}': #{}", &self.orgin_path, r);
-                }
+            if let Err(e) = errno::retry(|| orgin.flock(LOCK_EX|w)) {
+                stacked_return!("Could not lock '{}': {}", &self.orgin_path, e);
             }
         }
         
-        self.shadow_fd = file::open(&self.shadow_path, O_RDWR|O_CREAT|O_TRUNC, self.mode);
-        if self.shadow_fd < 0 {
-            file::close(self.orgin_fd);
-            stacked_return!("Could not open '{}': Error #{}", &self.shadow_path, self.shadow_fd);
-        }
+        let shadow = match Fd::open(&self.shadow_path, O_RDWR|O_CREAT|O_TRUNC, self.mode) {
+            Ok(fd) => fd,
+            Err(e) => stacked_return!("Could not open '{}': {}", &self.shadow_path, e)
+        };
         
-        let s = ok!(stat::fstat(self.orgin_fd), stacked_return!("could not stat '{}'", &self.orgin_path));
+        let s = ok!(orgin.fstat(), stacked_return!("could not stat '{}'", &self.orgin_path));
         
-        if io::sendfile(self.shadow_fd, self.orgin_fd, None, s.st_size as usize) < 0 {
-            file::close(self.orgin_fd);
-            file::close(self.shadow_fd);
+        if io::sendfile(shadow.raw(), orgin.raw(), None, s.st_size as usize).is_err() {
             stacked_return!("Could not sync shadow with orgin");
         }
         
+        self.orgin_fd = Some(orgin);
+        self.shadow_fd = Some(shadow);
         self.valid = true;
         
         Ok(())
@@ -186,35 +191,39 @@ impl File {
     fn store(&mut self) -> Result<(), StackErr> {
         // orgin file is unlocked her, then relocked
         use ulc12::fs::ln;
-        use ulc12::fs::file;
         use ulc12::fs::flags::*;
         
         ifn!(self.valid, stacked_return!("Shadowing state is invalid. File object is unusable."));
         
+        // the files are closed (and the orgin unlocked) however this ends
+        self.valid = false;
+        let (orgin, shadow) = match (self.orgin_fd.take(), self.shadow_fd.take()) {
+            (Some(o), Some(s)) => (o, s),
+            _ => stacked_return!("Shadowing state is invalid. File object is unusable.")
+        };
         
-        if file::fdatasync(self.shadow_fd) < 0 {
+        if shadow.fdatasync().is_err() {
             stacked_return!("Could not write pending data to shadow");
         }
         
-        file::close(self.shadow_fd);
+        let _ = shadow.close();
         
         if !self.discard {
-            if ln::rename(&self.shadow_path, &self.orgin_path) < 0 {
+            if ln::rename(&self.shadow_path, &self.orgin_path).is_err() {
                 stacked_return!("Could not sync orgin with shadow");
             }
         }
         else {
-            if ln::unlink(&self.shadow_path) < 0 {
+            if ln::unlink(&self.shadow_path).is_err() {
                 stacked_return!("Could not unlink shadow");
             }            
         }
         
-        if file::flock(self.orgin_fd, LOCK_UN) < 0 {
-            self.valid = false;
+        if orgin.flock(LOCK_UN).is_err() {
             stacked_return!("Could not unlock orgin");
         }
         
-        file::close(self.orgin_fd);
+        let _ = orgin.close();
         Ok(())
     }
     
@@ -236,111 +245,22 @@ impl Drop for File {
 
 impl Write for File {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        use ulc12::errno::*;
-        use std::io::ErrorKind::*;
-        
-        ifn!(self.valid, return Err(io::Error::new(InvalidInput, "Shadowing state is invalid. File object is unusable.", None)));
-        
-        let r = ulc12::io::write(self.shadow_fd, buf);
-        if r >= 0 {
-            Ok(r as usize)
-        }
-        else if r == -EAGAIN    {Err(io::Error::new(WouldBlock, "Write would block", None))}
-        else if r == -EBADF     {Err(io::Error::new(NotFound, "Bad file discriptor", None))}
-        else if r == -EDQUOT    {Err(io::Error::new(PermissionDenied, "Exhausted user quota", None))}
-        else if r == -EFAULT    {panic!("An attempt was made to access an address outside of this {}",
-                                        "applications address space. Something VERY wrong happened")}
-        else if r == -EFBIG     {Err(io::Error::new(PermissionDenied, "Write too big for file", None))}
-        else if r == -EINTR     {Err(io::Error::new(Interrupted, "Write was interrupted", None))}
-        else if r == -EINVAL    {Err(io::Error::new(InvalidInput, "Invalid input was specified", None))}
-        else if r == -EIO       {Err(io::Error::new(Other, "Low level I/O error while modifing inode", None))}
-        else if r == -ENOSPC    {Err(io::Error::new(PermissionDenied, "No space left on device", None))}
-        else if r == -EPIPE     {Err(io::Error::new(BrokenPipe, "A write to a closed pipe or socket", None))}
-        else                    {Err(io::Error::new(Other, "An unhandled I/O error occured", None))}
+        try!(self.shadow()).write(buf)
     }
     fn flush(&mut self) -> io::Result<()> {
-        use ulc12::errno::*;
-        use std::io::ErrorKind::*;
-
-        ifn!(self.valid, return Err(io::Error::new(InvalidInput, "Shadowing state is invalid. File object is unusable.", None)));
-        
-        let r = ulc12::fs::file::fdatasync(self.shadow_fd);
-        if r == 0 {
-            Ok(())
-        }
-        else if r == -EBADF     {Err(io::Error::new(NotFound, "Bad file discriptor", None))}
-        else if r == -EIO       {Err(io::Error::new(Other, "Low level I/O error while modifing inode", None))}
-        else if r == -EINVAL    {Err(io::Error::new(InvalidInput, "Special file does not support syncing", None))}
-        else                    {Err(io::Error::new(Other, "An unhandled I/O error occured", None))}
+        Ok(try!(try!(self.shadow()).fdatasync()))
     }
 }
 
 impl Read for File {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        use ulc12::errno::*;
-        use std::io::ErrorKind::*;
-        
-        ifn!(self.valid, return Err(io::Error::new(InvalidInput, "Shadowing state is invalid. File object is unusable.", None)));
-        
-        let r = ulc12::io::read(self.shadow_fd, buf);
-        if r >= 0 {
-            Ok(r as usize)
-        }
-        else if r == -EAGAIN    {Err(io::Error::new(WouldBlock, "Reqd would block", None))}
-        else if r == -EBADF     {Err(io::Error::new(NotFound, "Bad file discriptor", None))}
-        else if r == -EFAULT    {panic!("An attempt was made to access an address outside of this {}",
-                                        "applications address space. Something VERY wrong happened")}
-        else if r == -EINTR     {Err(io::Error::new(Interrupted, "Read was interrupted", None))}
-        else if r == -EINVAL    {Err(io::Error::new(InvalidInput, "Invalid input was specified", None))}
-        else if r == -EIO       {Err(io::Error::new(Other, "Low level I/O error while modifing inode", None))}
-        else if r == -ENOSPC    {Err(io::Error::new(PermissionDenied, "No space left on device", None))}
-        else if r == -EISDIR    {Err(io::Error::new(InvalidInput, "Cannot read a directory", None))}
-        else                    {Err(io::Error::new(Other, "An unhandled I/O error occured", None))}
+        try!(self.shadow()).read(buf)
     }
 }
 
 impl Seek for File {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        use std::io::ErrorKind::*;
-        use ulc12::fs::flags::*;
-        use ulc12::fs::file::lseek;
-        use ulc12::errno::*;
-        // should you be using llseek? No, unless you're using x86. And even 
-        // then, I think it should be handled by the system call wrapper lib
-        // should I explicitly handle negative offsets ("-10 from end of file")?
-        // Can lseek handle these cases? Will it's behavior be compatable with
-        // io::Seek's specification? Dunno.
-        
-        ifn!(self.valid, return Err(io::Error::new(InvalidInput, "Shadowing state is invalid. File object is unusable.", None)));
-        
-        let whence: usize;
-        let offset: isize;
-        match pos {
-            SeekFrom::Start(u) => {
-                whence = SEEK_SET;
-                offset = u as isize;
-            }
-            SeekFrom::End(i) => {
-                whence = SEEK_END;
-                offset = i as isize;
-            }
-            SeekFrom::Current(i) => {
-                whence = SEEK_CUR;
-                offset = i as isize;
-            }
-        }
-        let r = lseek(self.shadow_fd, offset, whence);
-        
-        if r >= 0 {
-            Ok(r as u64)
-        }
-        else if r == -EBADF     {Err(io::Error::new(NotFound, "Bad file discriptor", None))}
-        else if r == -EINVAL    {Err(io::Error::new(InvalidInput, "Whence is not valid or the seek is imposible (out of bounds)", None))}
-        else if r == -EOVERFLOW {Err(io::Error::new(Other, "Result cant be stored in the return type (>= 1<<64)", None))}
-        else if r == -ESPIPE    {Err(io::Error::new(Other, "Cannot seek a pipe", None))}
-        else if r == -ENXIO     {Err(io::Error::new(InvalidInput, "Out of bounds and seeking data/holes", None))} 
-        else                    {Err(io::Error::new(Other, "An unhandled I/O error occured", None))}
-        
+        try!(self.shadow()).seek(pos)
     }
 }
 
@@ -375,7 +295,7 @@ fn test_for_pathname(pathname: &str) {
     use std::thread;
     use std::time::duration::Duration;
     
-    ulc12::fs::ln::unlink(pathname);
+    let _ = ulc12::fs::ln::unlink(pathname);
     let mut f = ok!(File::open(pathname, 0o640, false), panic!(""));
     
     // testing staging
@@ -451,7 +371,7 @@ fn it_works() {
     assert!(env::set_current_dir(&PathBuf::from("/tmp")).is_ok());
     test_for_pathname("acid_test");
     test_for_pathname("../tmp/acid_test");
-    assert!(ulc12::fs::ln::unlink("acid_test") == 0);
+    assert!(ulc12::fs::ln::unlink("acid_test").is_ok());
     tty_print!("\n");
 }
 
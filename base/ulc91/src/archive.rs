@@ -75,6 +75,17 @@ impl Archive {
     pub fn insert(&mut self, key: String, value: Vec<u8>) {
         self.map.insert(key, value);
     }
+    
+    pub fn remove(&mut self, key: &str) -> Option<Vec<u8>> {
+        self.map.remove(key)
+    }
+    
+    /// أسماء الكتل المخزنة، مرتبة
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.map.keys().cloned().collect();
+        names.sort();
+        names
+    }
 }
 
 
@@ -161,6 +172,28 @@ fn test_archive() {
     test_for_input!([]);    
 }
 
+#[test]
+fn test_archive_remove() {
+    let data = [
+        ("File A".to_string(), vec![0x23, 0x3a, 0x00, 0x1f, 0x88]),
+        ("File B".to_string(), vec![]),
+        ("File C".to_string(), vec![0x00, 0x00]),
+    ];
+    let mut ar = Archive::new(None).unwrap();
+    for &(ref name, ref content) in data.iter() {
+        ar.insert(name.clone(), content.clone());
+    }
+    let mut ar = Archive::new(Some(ar.to_bytes().as_ref())).unwrap();
+
+    assert!(ar.names() == vec!["File A".to_string(), "File B".to_string(), "File C".to_string()]);
+    for &(ref name, ref content) in data.iter() {
+        assert!(ar.remove(name).unwrap() == *content);
+        assert!(ar.get(name).is_none());
+        assert!(ar.remove(name).is_none());
+    }
+    assert!(ar.names().len() == 0);
+}
+
 pub mod file {
     //! read/Semi-write blob access without loading everything in memory
     //! only loads the lookup table. Ment for very large files, or blobs.
@@ -221,6 +254,23 @@ pub mod file {
             })
         }
         
+        /// names of the blobs in the archive, sorted
+        pub fn names(&self) -> Vec<String> {
+            let mut names: Vec<String> = self.lookup.keys().cloned().collect();
+            names.sort();
+            names
+        }
+        
+        /// length of blob `name`, if present
+        pub fn len(&self, name: &str) -> Option<u64> {
+            self.lookup.get(name).map(|&(_, l)| l)
+        }
+        
+        /// position of blob `name` in the underlying file, if present
+        pub fn position(&self, name: &str) -> Option<u64> {
+            self.lookup.get(name).map(|&(o, _)| self.offset + o)
+        }
+        
         pub fn read(&mut self, name: &str, buf: &mut [u8], blob_offset: u64) -> io::Result<usize> {
             let offset: u64;
             let length: u64;
@@ -293,6 +343,11 @@ pub mod file {
             
             let mut buf: Vec<u8> = vec![0; 60];
             
+            assert!(ah.names() == vec![a_kun.0.to_string(), b_kun.0.to_string()]);
+            assert!(ah.len(a_kun.0) == Some(4));
+            assert!(ah.len("C-Kun") == None);
+            assert!(ah.position("C-Kun") == None);
+            
             let read_len = ah.read(a_kun.0, &mut buf, 0).unwrap();
             assert!(read_len == a_kun.1.len());
             //println!("Trying to {:?} != {:?} which both should be {} bytes long", &buf[0..read_len], a_kun.1, read_len);
@@ -0,0 +1,123 @@
+//! أدوات مساعدة
+//! =========
+
+/// CRC-32 (IEEE 802.3, as used by zlib and PNG), computed incrementally.
+pub struct Crc32 {
+    table: [u32; 256],
+    state: u32
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        let mut table = [0u32; 256];
+        for i in 0..256 {
+            let mut c = i as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 {(c >> 1) ^ 0xedb88320} else {c >> 1};
+            }
+            table[i] = c;
+        }
+        Crc32 {table: table, state: 0xffffffff}
+    }
+    
+    pub fn update(&mut self, data: &[u8]) {
+        for b in data.iter() {
+            self.state = self.table[((self.state ^ *b as u32) & 0xff) as usize] ^ (self.state >> 8);
+        }
+    }
+    
+    pub fn finish(&self) -> u32 {
+        self.state ^ 0xffffffff
+    }
+}
+
+/// CRC-32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut c = Crc32::new();
+    c.update(data);
+    c.finish()
+}
+
+#[test]
+fn test_crc32() {
+    assert!(crc32(&[]) == 0);
+    assert!(crc32("123456789".as_bytes()) == 0xcbf43926);
+    assert!(crc32("The quick brown fox jumps over the lazy dog".as_bytes()) == 0x414fa339);
+    
+    // incremental
+    let mut c = Crc32::new();
+    c.update("1234".as_bytes());
+    c.update("56789".as_bytes());
+    assert!(c.finish() == 0xcbf43926);
+}
+
+
+const BASE64: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Base64 (RFC 4648, padded) of `data`.
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            }
+            else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes padded base64. Returns None if `text` isn't valid base64.
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    if text.len() % 4 != 0 {
+        return None;
+    }
+    let mut out: Vec<u8> = Vec::with_capacity(text.len() / 4 * 3);
+    for (i, chunk) in text.chunks(4).enumerate() {
+        let last = i == text.len() / 4 - 1;
+        let mut n = 0u32;
+        let mut pad = 0;
+        for (j, c) in chunk.iter().enumerate() {
+            let v = match *c {
+                b'A'...b'Z' => c - b'A',
+                b'a'...b'z' => c - b'a' + 26,
+                b'0'...b'9' => c - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                b'=' if last && j >= 2 => {pad += 1; 0},
+                _ => return None
+            };
+            if pad > 0 && *c != b'=' {
+                return None; // data after padding
+            }
+            n = n << 6 | v as u32;
+        }
+        let bytes = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        out.push_all(&bytes[..3 - pad]);
+    }
+    Some(out)
+}
+
+#[test]
+fn test_base64() {
+    let cases: Vec<(&str, &str)> = vec![("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"),
+                                        ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy")];
+    for &(plain, encoded) in cases.iter() {
+        assert!(base64_encode(plain.as_bytes()) == encoded);
+        assert!(base64_decode(encoded).unwrap() == plain.as_bytes());
+    }
+    let all: Vec<u8> = (0..256).map(|i| i as u8).collect();
+    assert!(base64_decode(&base64_encode(&all)).unwrap() == all);
+
+    assert!(base64_decode("Zm9").is_none());
+    assert!(base64_decode("Zm9v!A==").is_none());
+    assert!(base64_decode("Z===").is_none());
+    assert!(base64_decode("Zg==Zm9v").is_none());
+    assert!(base64_decode("Zg=v").is_none());
+}